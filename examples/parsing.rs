@@ -1,9 +1,10 @@
-use std::io;
+use mcclient::mc::{
+    error::McError,
+    mctypes::{MCString, MCType, MAX_USERNAME_LENGTH},
+};
 
-use mcclient::mc::mctypes::{MCString, MCType};
-
-fn main() -> Result<(), io::Error> {
-    let s: MCString = "Makoto".into();
+fn main() -> Result<(), McError> {
+    let s = MCString::new("Makoto", MAX_USERNAME_LENGTH)?;
     let bytes = s.to_bytes();
 
     println!("{:?}", bytes);
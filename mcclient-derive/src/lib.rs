@@ -0,0 +1,404 @@
+//! Derive macros for `mcclient` packets.
+//!
+//! `#[derive(Packet)]` implements `Packet`, `OutboundPacket` and `InboundPacket`
+//! for a struct whose fields appear in the order they are encoded, along with
+//! `ServerboundPacket` or `ClientboundPacket` for its state:
+//!
+//! ```ignore
+//! #[derive(Packet)]
+//! #[packet(id = 0x00, state = Login, direction = Serverbound)]
+//! pub struct LoginStart {
+//!     #[packet(max_len = 16)]
+//!     pub username: String,
+//!     #[packet(bool_prefixed)]
+//!     pub uuid: Option<Uuid>,
+//! }
+//! ```
+//!
+//! Fields are encoded with their `MCType` implementation unless one of these
+//! field attributes applies:
+//! - `varint` / `varlong`: an `i32` / `i64` encoded as a `VarInt` / `VarLong`.
+//! - `max_len = N`: a `String` of at most `N` UTF-16 code units. Longer strings fail
+//!   to encode as well as to decode.
+//! - `remaining`: a `Vec<u8>` holding every byte left in the packet.
+//! - `nbt` / `network_nbt`: an `Option<Nbt>` / `Option<NetworkNbt>` with a named /
+//!   nameless root, where `None` is a lone `TAG_End`. Tags which NBT cannot express
+//!   fail to encode.
+//! - `slot = format`: an `ItemStack` laid out in the `SlotFormat` `format`.
+//! - `length_prefixed`: a `Vec<T>` prefixed with its element count as a `VarInt`.
+//! - `bool_prefixed`: an `Option<T>` prefixed with a boolean which is `true` if it
+//!   is present.
+//! - `condition = expr`: an `Option<T>` which is present when `expr` is `true`. The
+//!   expression may refer to earlier fields by name. When encoding, the field is
+//!   written if it is `Some`.
+//!
+//! `length_prefixed`, `bool_prefixed` and `condition` wrap the element encoding,
+//! so `#[packet(length_prefixed, varint)]` is a count-prefixed array of `VarInt`s.
+//!
+//! Encoding and decoding errors are tagged with the name of the field and the
+//! packet's state and ID, see `McError::in_field` and `McError::in_packet`.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Data, DeriveInput, Expr, Field, Fields, Ident, Index, Member};
+
+#[proc_macro_derive(Packet, attributes(packet))]
+pub fn derive_packet(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input).unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+struct PacketAttrs {
+    id: Expr,
+    state: Ident,
+    direction: Ident,
+}
+
+fn parse_packet_attrs(input: &DeriveInput) -> syn::Result<PacketAttrs> {
+    let (mut id, mut state, mut direction) = (None, None, None);
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("packet")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("id") {
+                id = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("state") {
+                state = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("direction") {
+                direction = Some(meta.value()?.parse()?);
+            } else {
+                return Err(meta.error("expected `id`, `state` or `direction`"));
+            }
+            Ok(())
+        })?;
+    }
+
+    let missing = |name: &str| {
+        syn::Error::new_spanned(&input.ident, format!("missing `#[packet({} = ..)]`", name))
+    };
+    Ok(PacketAttrs {
+        id: id.ok_or_else(|| missing("id"))?,
+        state: state.ok_or_else(|| missing("state"))?,
+        direction: direction.ok_or_else(|| missing("direction"))?,
+    })
+}
+
+/// How a single value (a field, or an element of an array or option) is encoded.
+enum Encoding {
+    MCType,
+    VarInt,
+    VarLong,
+    MaxLen(Expr),
+    Remaining,
+    Nbt,
+    NetworkNbt,
+    Slot(Expr),
+}
+
+/// How the values of a field are laid out around their element encoding.
+enum Layout {
+    Plain,
+    LengthPrefixed,
+    BoolPrefixed,
+    Condition(Expr),
+}
+
+struct FieldAttrs {
+    encoding: Encoding,
+    layout: Layout,
+}
+
+fn parse_field_attrs(field: &Field) -> syn::Result<FieldAttrs> {
+    let mut attrs = FieldAttrs {
+        encoding: Encoding::MCType,
+        layout: Layout::Plain,
+    };
+
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("packet")) {
+        attr.parse_nested_meta(|meta| {
+            let (encoding, layout) = if meta.path.is_ident("varint") {
+                (Some(Encoding::VarInt), None)
+            } else if meta.path.is_ident("varlong") {
+                (Some(Encoding::VarLong), None)
+            } else if meta.path.is_ident("max_len") {
+                (Some(Encoding::MaxLen(meta.value()?.parse()?)), None)
+            } else if meta.path.is_ident("remaining") {
+                (Some(Encoding::Remaining), None)
+            } else if meta.path.is_ident("nbt") {
+                (Some(Encoding::Nbt), None)
+            } else if meta.path.is_ident("network_nbt") {
+                (Some(Encoding::NetworkNbt), None)
+            } else if meta.path.is_ident("slot") {
+                (Some(Encoding::Slot(meta.value()?.parse()?)), None)
+            } else if meta.path.is_ident("length_prefixed") {
+                (None, Some(Layout::LengthPrefixed))
+            } else if meta.path.is_ident("bool_prefixed") {
+                (None, Some(Layout::BoolPrefixed))
+            } else if meta.path.is_ident("condition") {
+                (None, Some(Layout::Condition(meta.value()?.parse()?)))
+            } else {
+                return Err(meta.error("unknown field attribute"));
+            };
+
+            if let Some(encoding) = encoding {
+                if !matches!(attrs.encoding, Encoding::MCType) {
+                    return Err(meta.error("a field may only have one encoding"));
+                }
+                attrs.encoding = encoding;
+            }
+            if let Some(layout) = layout {
+                if !matches!(attrs.layout, Layout::Plain) {
+                    return Err(meta.error(
+                        "`length_prefixed`, `bool_prefixed` and `condition` are mutually exclusive",
+                    ));
+                }
+                attrs.layout = layout;
+            }
+            Ok(())
+        })?;
+    }
+
+    if matches!(attrs.encoding, Encoding::Remaining) && !matches!(attrs.layout, Layout::Plain) {
+        return Err(syn::Error::new_spanned(field, "`remaining` cannot be combined with a prefix or condition"));
+    }
+    Ok(attrs)
+}
+
+/// Generates statements appending the value behind the reference `value` to `__buf`.
+/// Errors are passed through `context`, which adds the field and packet to them.
+fn encode_value(encoding: &Encoding, value: &TokenStream2, context: &TokenStream2) -> TokenStream2 {
+    match encoding {
+        Encoding::MCType => quote! {
+            ::mcclient::mc::mctypes::MCType::write_to(#value, __buf);
+        },
+        // As `PacketBytesBuilder::try_append_string`, which cannot append to `__buf`.
+        Encoding::MaxLen(max_len) => quote! {
+            ::mcclient::mc::mctypes::MCType::write_to(
+                &::mcclient::mc::mctypes::MCString::new(#value, #max_len).map_err(|__e| #context(__e.into()))?,
+                __buf,
+            );
+        },
+        Encoding::VarInt => quote! {
+            ::mcclient::mc::mctypes::MCType::write_to(&::mcclient::mc::mctypes::VarInt::from(*#value), __buf);
+        },
+        Encoding::VarLong => quote! {
+            ::mcclient::mc::mctypes::MCType::write_to(&::mcclient::mc::mctypes::VarLong::from(*#value), __buf);
+        },
+        Encoding::Remaining => quote! {
+            __buf.extend_from_slice(#value);
+        },
+        // The builder methods encode into a buffer of their own, which is only appended
+        // to `__buf` once the value encoded without error.
+        Encoding::Nbt => quote! {
+            __buf.extend(
+                ::mcclient::mc::packet::builder::PacketBytesBuilder::new()
+                    .try_append_nbt(::std::option::Option::as_ref(#value))
+                    .map_err(#context)?
+                    .build(),
+            );
+        },
+        Encoding::NetworkNbt => quote! {
+            __buf.extend(
+                ::mcclient::mc::packet::builder::PacketBytesBuilder::new()
+                    .try_append_network_nbt(::std::option::Option::as_ref(#value))
+                    .map_err(#context)?
+                    .build(),
+            );
+        },
+        Encoding::Slot(format) => quote! {
+            __buf.extend(
+                ::mcclient::mc::packet::builder::PacketBytesBuilder::new()
+                    .try_append_slot(#value, #format)
+                    .map_err(#context)?
+                    .build(),
+            );
+        },
+    }
+}
+
+/// Generates an expression reading one value from `__reader`. Errors are passed
+/// through `context`, which adds the field and packet to them.
+fn decode_value(encoding: &Encoding, context: &TokenStream2) -> TokenStream2 {
+    match encoding {
+        Encoding::MCType => quote! { __reader.read().map_err(#context)? },
+        Encoding::VarInt => quote! { __reader.read_varint().map_err(#context)? },
+        Encoding::VarLong => quote! { __reader.read_varlong().map_err(#context)? },
+        Encoding::MaxLen(max_len) => quote! { __reader.read_string(#max_len).map_err(#context)? },
+        Encoding::Remaining => quote! { __reader.read_remaining().to_vec() },
+        Encoding::Nbt => quote! { __reader.read_nbt().map_err(#context)? },
+        Encoding::NetworkNbt => quote! { __reader.read_network_nbt().map_err(#context)? },
+        Encoding::Slot(format) => quote! { __reader.read_slot(#format).map_err(#context)? },
+    }
+}
+
+fn encode_field(attrs: &FieldAttrs, member: &Member, name: &str) -> TokenStream2 {
+    let field = quote! { self.#member };
+    let context = quote! { __context(#name) };
+    match &attrs.layout {
+        Layout::Plain => encode_value(&attrs.encoding, &quote! { &#field }, &context),
+        Layout::LengthPrefixed => {
+            let element = encode_value(&attrs.encoding, &quote! { __element }, &context);
+            quote! {
+                ::mcclient::mc::mctypes::MCType::write_to(
+                    &::mcclient::mc::mctypes::VarInt::from(#field.len() as i32),
+                    __buf,
+                );
+                for __element in &#field {
+                    #element
+                }
+            }
+        }
+        Layout::BoolPrefixed => {
+            let element = encode_value(&attrs.encoding, &quote! { __element }, &context);
+            quote! {
+                __buf.push(#field.is_some() as u8);
+                if let Some(__element) = &#field {
+                    #element
+                }
+            }
+        }
+        Layout::Condition(_) => {
+            let element = encode_value(&attrs.encoding, &quote! { __element }, &context);
+            quote! {
+                if let Some(__element) = &#field {
+                    #element
+                }
+            }
+        }
+    }
+}
+
+fn decode_field(attrs: &FieldAttrs, name: &str) -> TokenStream2 {
+    let context = quote! { __context(#name) };
+    let element = decode_value(&attrs.encoding, &context);
+    match &attrs.layout {
+        Layout::Plain => element,
+        Layout::LengthPrefixed => quote! {{
+            let __len = __reader.read_length().map_err(#context)?;
+            // Every element takes at least one byte, which bounds a forged count.
+            let mut __elements = ::std::vec::Vec::with_capacity(__len.min(__reader.remaining()));
+            for _ in 0..__len {
+                __elements.push(#element);
+            }
+            __elements
+        }},
+        Layout::BoolPrefixed => quote! {
+            if __reader.read_bool().map_err(#context)? {
+                ::std::option::Option::Some(#element)
+            } else {
+                ::std::option::Option::None
+            }
+        },
+        Layout::Condition(condition) => quote! {
+            if #condition { ::std::option::Option::Some(#element) } else { ::std::option::Option::None }
+        },
+    }
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let PacketAttrs { id, state, direction } = parse_packet_attrs(input)?;
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => return Err(syn::Error::new_spanned(name, "`Packet` can only be derived for structs")),
+    };
+
+    let mut encode = Vec::new();
+    let mut decode = Vec::new();
+    let mut locals = Vec::new();
+    for (i, field) in fields.iter().enumerate() {
+        let attrs = parse_field_attrs(field)?;
+        let (member, local, name) = match &field.ident {
+            Some(ident) => (Member::Named(ident.clone()), ident.clone(), ident.to_string()),
+            None => (Member::Unnamed(Index::from(i)), format_ident!("__field{}", i), i.to_string()),
+        };
+
+        encode.push(encode_field(&attrs, &member, &name));
+        let value = decode_field(&attrs, &name);
+        decode.push(quote! { let #local = #value; });
+        locals.push(local);
+    }
+
+    // Lets `Client` only accept the packets of its current state.
+    let marker = match direction.to_string().as_str() {
+        "Serverbound" => quote! { ServerboundPacket },
+        "Clientbound" => quote! { ClientboundPacket },
+        _ => return Err(syn::Error::new_spanned(&direction, "expected `Serverbound` or `Clientbound`")),
+    };
+
+    let construct = match fields {
+        Fields::Named(_) => quote! { Self { #(#locals),* } },
+        Fields::Unnamed(_) => quote! { Self(#(#locals),*) },
+        Fields::Unit => quote! { Self },
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::mcclient::mc::packet::Packet for #name #ty_generics #where_clause {
+            const ID: i32 = #id;
+            const STATE: ::mcclient::mc::packet::ConnectionState =
+                ::mcclient::mc::packet::ConnectionState::#state;
+            const DIRECTION: ::mcclient::mc::packet::PacketDirection =
+                ::mcclient::mc::packet::PacketDirection::#direction;
+        }
+
+        impl #impl_generics ::mcclient::mc::packet::#marker<::mcclient::mc::packet::state::#state>
+            for #name #ty_generics #where_clause {}
+
+        impl #impl_generics ::mcclient::mc::packet::OutboundPacket for #name #ty_generics #where_clause {
+            fn to_bytes(&self) -> ::std::result::Result<::std::vec::Vec<u8>, ::mcclient::mc::error::McError> {
+                let mut __buf = ::std::vec::Vec::new();
+                ::mcclient::mc::packet::OutboundPacket::write_to(self, &mut __buf)?;
+                ::std::result::Result::Ok(__buf)
+            }
+
+            #[allow(unused_variables)]
+            fn write_to(
+                &self,
+                __buf: &mut ::std::vec::Vec<u8>,
+            ) -> ::std::result::Result<(), ::mcclient::mc::error::McError> {
+                #[allow(unused_variables)]
+                let __context = |__field: &'static str| {
+                    move |__e: ::mcclient::mc::error::McError| {
+                        __e.in_field(__field).in_packet(
+                            <Self as ::mcclient::mc::packet::Packet>::STATE,
+                            <Self as ::mcclient::mc::packet::Packet>::ID,
+                        )
+                    }
+                };
+                #(#encode)*
+                ::std::result::Result::Ok(())
+            }
+
+            fn packet_id(&self) -> i32 {
+                <Self as ::mcclient::mc::packet::Packet>::ID
+            }
+        }
+
+        impl #impl_generics ::mcclient::mc::packet::InboundPacket for #name #ty_generics #where_clause {
+            fn from_data(
+                __packet: &::mcclient::mc::packet::ClientboundRawPacket,
+            ) -> ::std::result::Result<Self, ::mcclient::mc::error::McError> {
+                let __in_packet = |__e: ::mcclient::mc::error::McError| {
+                    __e.in_packet(
+                        <Self as ::mcclient::mc::packet::Packet>::STATE,
+                        <Self as ::mcclient::mc::packet::Packet>::ID,
+                    )
+                };
+                #[allow(unused_variables)]
+                let __context = |__field: &'static str| {
+                    move |__e: ::mcclient::mc::error::McError| __in_packet(__e.in_field(__field))
+                };
+                #[allow(unused_mut)]
+                let mut __reader = __packet.reader();
+                #(#decode)*
+                __reader.finish().map_err(__in_packet)?;
+                ::std::result::Result::Ok(#construct)
+            }
+
+            fn packet_id(&self) -> i32 {
+                <Self as ::mcclient::mc::packet::Packet>::ID
+            }
+        }
+    })
+}
@@ -1,2 +1,5 @@
+// Lets `#[derive(Packet)]` refer to this crate as `::mcclient` from within it.
+extern crate self as mcclient;
+
 pub mod mc;
 pub mod tests;
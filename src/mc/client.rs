@@ -0,0 +1,409 @@
+//! A connection whose protocol state is part of its type.
+//! <br>
+//! A `Client` starts in `Handshaking`, and each transition consumes it and returns a
+//! client in the next state, so a stream is never reused after its state is left.
+//! `send` and `read_packet` only accept the packets of the current state, so sending
+//! a packet in the wrong state does not compile:
+//! ```compile_fail
+//! use mcclient::mc::{client::Client, packet::serverbound::status_request::StatusRequest};
+//!
+//! let mut login = Client::connect("localhost", 25565)?.into_login()?;
+//! login.send(&StatusRequest)?; // Status Request is not a Login packet.
+//! # Ok::<(), mcclient::mc::error::McError>(())
+//! ```
+//! Neither can a status be requested once logged in:
+//! ```compile_fail
+//! use mcclient::mc::client::Client;
+//!
+//! let (mut play, _) = Client::connect("localhost", 25565)?.into_login()?.login("Makoto")?;
+//! play.status()?;
+//! # Ok::<(), mcclient::mc::error::McError>(())
+//! ```
+//! Clients speak `PROTOCOL_VERSION` unless another version is picked with
+//! `Client::with_protocol_version`. From 1.20.2, logging in passes through the
+//! Configuration state, which `Client::login` handles on its own; use
+//! `Client::login_into_configuration` to take part in it.
+
+use std::{marker::PhantomData, time::Duration};
+
+use super::{
+    configuration::{
+        self, ConfigurationPacket, Registries, ResourcePackResult, ServerboundConfigurationPacket,
+    },
+    connection::{self, LoginPluginHandler, SessionAuthenticator},
+    error::McError,
+    legacy::{self, LegacyPingFormat},
+    mctypes::{check_string_length, MAX_SERVER_ADDRESS_LENGTH, MAX_USERNAME_LENGTH},
+    options::ConnectionOptions,
+    packet::{
+        clientbound::login_success::LoginSuccess,
+        serverbound::{handshake::NextState, login_acknowledged::LOGIN_ACKNOWLEDGED_PROTOCOL},
+        state::{Configuration, Handshaking, Login, Play, ProtocolState, Status},
+        ClientboundPacket, ClientboundRawPacket, ConnectionState, Packet, ServerboundPacket, ServerboundRawPacket,
+    },
+    resolver::{Resolver, ServerAddress, SystemResolver, DEFAULT_PORT},
+    status::ServerStatus,
+    stream::MinecraftStream,
+    PROTOCOL_VERSION,
+};
+
+/// The protocol versions a `Client` can speak, 1.19.3 to 1.21.1.
+pub const SUPPORTED_PROTOCOLS: std::ops::RangeInclusive<i32> = 761..=767;
+
+/// A connection to a Minecraft server in the protocol state `S`.
+/// # Example
+/// ```no_run
+/// use mcclient::mc::client::Client;
+///
+/// let mut status = Client::connect("localhost", 25565)?.into_status()?;
+/// println!("{:?}", status.status()?.players);
+/// println!("{:?}", status.ping()?);
+///
+/// // The server closes the connection after a ping, so logging in needs a new one.
+/// let (play, login_success) = Client::connect("localhost", 25565)?.into_login()?.login("Makoto")?;
+/// # Ok::<(), mcclient::mc::error::McError>(())
+/// ```
+pub struct Client<S: ProtocolState> {
+    stream: MinecraftStream,
+    domain: String,
+    port: u16,
+    plugin_handler: Option<Box<dyn LoginPluginHandler>>,
+    authenticator: Option<Box<dyn SessionAuthenticator>>,
+    require_offline_uuid: bool,
+    protocol_version: i32,
+    registries: Registries,
+    state: PhantomData<S>,
+}
+
+impl<S: ProtocolState> Client<S> {
+    /// Sends a packet of the current state.
+    /// # Errors
+    /// An `Io` error will be returned if the packet cannot be sent.
+    pub fn send<P: ServerboundPacket<S>>(&mut self, packet: &P) -> Result<(), McError> {
+        self.stream.send(packet)
+    }
+
+    /// Reads the next packet without decoding it.
+    /// # Errors
+    /// This function will return any error `MinecraftStream::read` returns.
+    pub fn read(&mut self) -> Result<ClientboundRawPacket, McError> {
+        self.stream.read()
+    }
+
+    /// Reads the next packet, which must be a `P`.
+    /// # Errors
+    /// This function will return an `UnexpectedPacket` error if the server sends a
+    /// different packet, or any error raised while reading or decoding it.
+    pub fn read_packet<P: ClientboundPacket<S> + Packet>(&mut self) -> Result<P, McError> {
+        let inbound = self.stream.read()?;
+        if inbound.header.id != P::ID {
+            return Err(McError::UnexpectedPacket {
+                state: S::STATE,
+                id: inbound.header.id,
+            });
+        }
+        P::from_data(&inbound)
+    }
+
+    /// Gets the host the client connected to, which is also sent in the Handshake.
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    /// Gets the port sent in the Handshake. See `Connection::port`.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Gets the protocol version the client speaks.
+    pub fn protocol_version(&self) -> i32 {
+        self.protocol_version
+    }
+
+    /// Gets the registries received in the Configuration state. This stays empty before
+    /// 1.20.2, whose servers send the registries in the Login (play) packet instead.
+    pub fn registries(&self) -> &Registries {
+        &self.registries
+    }
+
+    fn into_state<T: ProtocolState>(self) -> Client<T> {
+        Client {
+            stream: self.stream,
+            domain: self.domain,
+            port: self.port,
+            plugin_handler: self.plugin_handler,
+            authenticator: self.authenticator,
+            require_offline_uuid: self.require_offline_uuid,
+            protocol_version: self.protocol_version,
+            registries: self.registries,
+            state: PhantomData,
+        }
+    }
+
+    /// Sends a packet encoded for the protocol version, which `send` cannot check.
+    pub(crate) fn send_raw_packet(&mut self, packet: &ServerboundRawPacket) -> Result<(), McError> {
+        self.stream.send(packet)
+    }
+}
+
+impl Client<Handshaking> {
+    /// Connects to a Minecraft server with the default `ConnectionOptions`.
+    /// # Errors
+    /// This function will return any error `connect_with` returns.
+    pub fn connect<T: Into<String>>(domain: T, port: u16) -> Result<Self, McError> {
+        Client::connect_with(domain, port, &ConnectionOptions::default())
+    }
+
+    /// Connects to a Minecraft server on an explicit port as configured by `options`.
+    /// # Errors
+    /// This function will return any error `connect_to` returns.
+    pub fn connect_with<T: Into<String>>(domain: T, port: u16, options: &ConnectionOptions) -> Result<Self, McError> {
+        Client::connect_to(&ServerAddress::new(domain, Some(port)), options, &SystemResolver)
+    }
+
+    /// Connects to a Minecraft server at `address`, resolved with `resolver`. See
+    /// `Connection::connect_to`.
+    /// # Errors
+    /// This function will return any error `Connection::connect_to` returns.
+    pub fn connect_to(
+        address: &ServerAddress,
+        options: &ConnectionOptions,
+        resolver: &dyn Resolver,
+    ) -> Result<Self, McError> {
+        check_string_length(&address.host, MAX_SERVER_ADDRESS_LENGTH)?;
+        let stream = MinecraftStream::connect_with(&address.resolve(resolver)?[..], options)?;
+
+        Ok(Client {
+            stream,
+            domain: address.host.clone(),
+            port: address.port.unwrap_or(DEFAULT_PORT),
+            plugin_handler: None,
+            authenticator: None,
+            require_offline_uuid: false,
+            protocol_version: PROTOCOL_VERSION,
+            registries: Registries::new(),
+            state: PhantomData,
+        })
+    }
+
+    /// Picks the protocol version sent in the Handshake and spoken afterwards.
+    /// # Errors
+    /// This function will return a `Malformed` error if the version is not one of
+    /// `SUPPORTED_PROTOCOLS`.
+    pub fn with_protocol_version(mut self, protocol_version: i32) -> Result<Self, McError> {
+        if !SUPPORTED_PROTOCOLS.contains(&protocol_version) {
+            return Err(McError::invalid(format!("Protocol {} is not supported", protocol_version)));
+        }
+
+        self.protocol_version = protocol_version;
+        Ok(self)
+    }
+
+    /// Sends the Handshake for a status request.
+    /// # Errors
+    /// An `Io` error will be returned if the Handshake cannot be sent.
+    pub fn into_status(mut self) -> Result<Client<Status>, McError> {
+        connection::send_handshake(&mut self.stream, self.protocol_version, &self.domain, self.port, NextState::STATUS)?;
+        Ok(self.into_state())
+    }
+
+    /// Sends the Handshake for a login.
+    /// # Errors
+    /// An `Io` error will be returned if the Handshake cannot be sent.
+    pub fn into_login(mut self) -> Result<Client<Login>, McError> {
+        connection::send_handshake(&mut self.stream, self.protocol_version, &self.domain, self.port, NextState::LOGIN)?;
+        Ok(self.into_state())
+    }
+
+    /// Fetches the status with the legacy server list ping instead of a Handshake. The
+    /// server closes the connection afterwards.
+    /// # Errors
+    /// This function will return any error `Connection::legacy_status` returns.
+    pub fn legacy_status(mut self, format: LegacyPingFormat) -> Result<ServerStatus, McError> {
+        self.stream.send_raw(&legacy::encode_request(format, &self.domain, self.port)?)?;
+        legacy::read_response(&mut self.stream)
+    }
+}
+
+impl Client<Status> {
+    /// Requests the status of the server. Vanilla servers answer a single request per
+    /// connection.
+    /// # Errors
+    /// This function will return an error if the request fails, or an `UnexpectedPacket`
+    /// error if the server replies with anything but a status response.
+    pub fn status(&mut self) -> Result<ServerStatus, McError> {
+        Ok(connection::request_status(&mut self.stream)?.status)
+    }
+
+    /// Pings the server with a random payload, returning the round-trip time. This ends
+    /// the exchange, as the server closes the connection after answering.
+    /// # Errors
+    /// This function will return any error `Connection::ping` returns.
+    pub fn ping(mut self) -> Result<Duration, McError> {
+        connection::request_ping(&mut self.stream)
+    }
+}
+
+impl Client<Login> {
+    /// Sets the handler answering Login Plugin Requests. Without one, every request is
+    /// answered as not understood, like the vanilla client does.
+    pub fn set_login_plugin_handler<H: LoginPluginHandler + 'static>(&mut self, handler: H) {
+        self.plugin_handler = Some(Box::new(handler));
+    }
+
+    /// Sets the authenticator joining online-mode servers. Without one, logging into a
+    /// server which requires a session fails.
+    pub fn set_session_authenticator<A: SessionAuthenticator + 'static>(&mut self, authenticator: A) {
+        self.authenticator = Some(Box::new(authenticator));
+    }
+
+    /// Makes logging in fail if Login Success assigns another UUID than the offline-mode
+    /// one sent. See `OfflineConnection::set_require_offline_uuid`.
+    pub fn set_require_offline_uuid(&mut self, require: bool) {
+        self.require_offline_uuid = require;
+    }
+
+    /// Logs in as `username`, handling the Login state until Login Success. From 1.20.2,
+    /// the Configuration state is handled too, see `Client::finish`; before, the client
+    /// moves straight to Play.
+    /// # Errors
+    /// This function will return any error `Connection::login` or `Client::finish` returns.
+    pub fn login<T: Into<String>>(mut self, username: T) -> Result<(Client<Play>, LoginSuccess), McError> {
+        if self.protocol_version < LOGIN_ACKNOWLEDGED_PROTOCOL {
+            let login_success = self.perform_login(username.into())?;
+            return Ok((self.into_state(), login_success));
+        }
+
+        let (configuration, login_success) = self.login_into_configuration(username)?;
+        Ok((configuration.finish()?, login_success))
+    }
+
+    /// Logs in as `username` and stops in the Configuration state.
+    /// # Errors
+    /// This function will return a `Malformed` error if the protocol version is older
+    /// than 1.20.2, which has no Configuration state, or any error `Connection::login`
+    /// returns.
+    pub fn login_into_configuration<T: Into<String>>(
+        mut self,
+        username: T,
+    ) -> Result<(Client<Configuration>, LoginSuccess), McError> {
+        check_configuration_protocol(self.protocol_version)?;
+        let login_success = self.perform_login(username.into())?;
+        Ok((self.into_state(), login_success))
+    }
+
+    fn perform_login(&mut self, username: String) -> Result<LoginSuccess, McError> {
+        check_string_length(&username, MAX_USERNAME_LENGTH)?;
+        connection::perform_login(
+            &mut self.stream,
+            self.protocol_version,
+            username,
+            &mut self.plugin_handler,
+            &mut self.authenticator,
+            self.require_offline_uuid,
+        )
+    }
+}
+
+impl Client<Configuration> {
+    /// Reads and decodes the next packet. Keep Alive and Ping are answered, Known Packs
+    /// is answered with no packs so that the server sends every registry entry, and
+    /// registries are recorded in `registries`.
+    /// # Errors
+    /// This function will return a `Disconnected` error if the server sends Disconnect,
+    /// or any error raised while reading, decoding or answering the packet.
+    pub fn next_packet(&mut self) -> Result<ConfigurationPacket, McError> {
+        let inbound = self.stream.read()?;
+        let packet = ConfigurationPacket::read_with(&inbound, self.protocol_version)?;
+        match &packet {
+            ConfigurationPacket::KeepAlive(id) => self.send_packet(&ServerboundConfigurationPacket::KeepAlive(*id))?,
+            ConfigurationPacket::Ping(id) => self.send_packet(&ServerboundConfigurationPacket::Pong(*id))?,
+            ConfigurationPacket::KnownPacks(_) => {
+                self.send_packet(&ServerboundConfigurationPacket::KnownPacks(Vec::new()))?
+            }
+            ConfigurationPacket::RegistryData(registries) => {
+                for registry in registries {
+                    self.registries.insert(registry.clone());
+                }
+            }
+            ConfigurationPacket::Disconnect(reason) => return Err(McError::Disconnected(Box::new(reason.clone()))),
+            _ => {}
+        }
+
+        Ok(packet)
+    }
+
+    /// Sends a Configuration packet, encoded for the protocol version.
+    /// # Errors
+    /// This function will return any error `ServerboundConfigurationPacket::write_with`
+    /// returns, or an `Io` error if the packet cannot be sent.
+    pub fn send_packet(&mut self, packet: &ServerboundConfigurationPacket) -> Result<(), McError> {
+        let packet = packet.write_with(self.protocol_version)?;
+        self.send_raw_packet(&packet)
+    }
+
+    /// Handles packets until Finish Configuration, then acknowledges it. Resource packs
+    /// are declined, so servers which force one will disconnect the client.
+    /// # Errors
+    /// This function will return any error `next_packet` returns.
+    pub fn finish(mut self) -> Result<Client<Play>, McError> {
+        loop {
+            match self.next_packet()? {
+                ConfigurationPacket::FinishConfiguration => return self.acknowledge_finish(),
+                ConfigurationPacket::AddResourcePack(pack) => {
+                    self.send_packet(&ServerboundConfigurationPacket::ResourcePackResponse {
+                        id: pack.id,
+                        result: ResourcePackResult::Declined,
+                    })?;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Acknowledges Finish Configuration, which must have been received, moving the
+    /// client to Play.
+    /// # Errors
+    /// An `Io` error will be returned if the acknowledgement cannot be sent.
+    pub fn acknowledge_finish(mut self) -> Result<Client<Play>, McError> {
+        self.send_packet(&ServerboundConfigurationPacket::AcknowledgeFinishConfiguration)?;
+        Ok(self.into_state())
+    }
+}
+
+impl Client<Play> {
+    /// Returns `true` if `packet` is Start Configuration, after which the client must
+    /// `reconfigure`.
+    pub fn is_start_configuration(&self, packet: &ClientboundRawPacket) -> bool {
+        configuration::start_configuration_id(self.protocol_version) == Some(packet.header.id)
+    }
+
+    /// Acknowledges Start Configuration, which must have been received, moving the
+    /// client back to the Configuration state.
+    /// # Errors
+    /// This function will return a `Malformed` error if the protocol version is older
+    /// than 1.20.2 or its Acknowledge Configuration ID is not known, or an `Io` error if
+    /// the acknowledgement cannot be sent.
+    pub fn reconfigure(mut self) -> Result<Client<Configuration>, McError> {
+        check_configuration_protocol(self.protocol_version)?;
+        let id = configuration::acknowledge_configuration_id(self.protocol_version).ok_or_else(|| {
+            McError::invalid(format!(
+                "Protocol {} has no Acknowledge Configuration packet",
+                self.protocol_version
+            ))
+        })?;
+        self.send_raw_packet(&ServerboundRawPacket { id, data: Vec::new() })?;
+        Ok(self.into_state())
+    }
+}
+
+fn check_configuration_protocol(protocol_version: i32) -> Result<(), McError> {
+    if protocol_version < LOGIN_ACKNOWLEDGED_PROTOCOL {
+        return Err(McError::invalid(format!(
+            "Protocol {} has no {:?} state",
+            protocol_version,
+            ConnectionState::Configuration
+        )));
+    }
+    Ok(())
+}
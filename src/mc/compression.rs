@@ -0,0 +1,85 @@
+//! The compressed packet format, enabled by Set Compression during login.
+//! <https://wiki.vg/Protocol#With_compression>
+//! <br>
+//! Once compression is enabled, every frame holds the uncompressed length of its
+//! body as a `VarInt`, followed by the body compressed with zlib. Bodies smaller
+//! than the threshold are sent uncompressed, with an uncompressed length of 0.
+
+use std::io::{Read, Write};
+
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+
+use super::{
+    error::McError,
+    mctypes::{MCType, VarInt},
+    packet::reader::BufferedPacketReader,
+};
+
+/// The largest uncompressed body the protocol allows in a compressed frame.
+pub const MAX_DECOMPRESSED_SIZE: usize = 1 << 23;
+
+/// Appends the compressed frame of `body`, i.e., the packet ID and data, to `buf`,
+/// without the frame length prefix. `body` is compressed if it holds at least
+/// `threshold` bytes.
+/// # Errors
+/// This function will return a `Compression` error if zlib fails.
+pub fn compress_frame(body: &[u8], threshold: usize, buf: &mut Vec<u8>) -> Result<(), McError> {
+    if body.len() < threshold {
+        VarInt::from(0).write_to(buf);
+        buf.extend_from_slice(body);
+        return Ok(());
+    }
+
+    VarInt::from(body.len() as i32).write_to(buf);
+    let mut encoder = ZlibEncoder::new(buf, Compression::default());
+    encoder
+        .write_all(body)
+        .and_then(|_| encoder.finish())
+        .map_err(|e| McError::Compression(format!("Could not compress packet: {}", e)))?;
+
+    Ok(())
+}
+
+/// Decodes a compressed frame, returning its body, i.e., the packet ID and data.
+/// # Errors
+/// This function will return a `Compression` error if the declared length exceeds
+/// `MAX_DECOMPRESSED_SIZE`, a compressed body is smaller than `threshold`, the body
+/// does not decompress, or it does not decompress to the declared length.
+pub fn decompress_frame(frame: &[u8], threshold: usize) -> Result<Vec<u8>, McError> {
+    let mut reader = BufferedPacketReader::new(frame);
+    let data_len = reader.read_length()?;
+    let compressed = reader.read_remaining();
+
+    if data_len == 0 {
+        return Ok(compressed.to_vec());
+    }
+    if data_len > MAX_DECOMPRESSED_SIZE {
+        return Err(McError::Compression(format!(
+            "Packet declares {} uncompressed bytes, exceeding the maximum of {}",
+            data_len, MAX_DECOMPRESSED_SIZE
+        )));
+    }
+    if data_len < threshold {
+        return Err(McError::Compression(format!(
+            "Packet of {} bytes is compressed below the threshold of {}",
+            data_len, threshold
+        )));
+    }
+
+    // Reading one byte past the declared length detects bodies which are too long
+    // without decompressing an unbounded amount.
+    let mut body = Vec::with_capacity(data_len);
+    ZlibDecoder::new(compressed)
+        .take(data_len as u64 + 1)
+        .read_to_end(&mut body)
+        .map_err(|e| McError::Compression(format!("Could not decompress packet: {}", e)))?;
+    if body.len() != data_len {
+        return Err(McError::Compression(format!(
+            "Packet declares {} uncompressed bytes, but holds {}",
+            data_len,
+            body.len()
+        )));
+    }
+
+    Ok(body)
+}
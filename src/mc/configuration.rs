@@ -0,0 +1,551 @@
+//! The Configuration state of 1.20.2 and later. <https://wiki.vg/Protocol#Configuration>
+//! <br>
+//! Once Login Success is acknowledged, the server sends the registries, enabled
+//! feature flags and tags the game needs, optionally along with resource packs and
+//! plugin messages, and ends the state with Finish Configuration, which the client
+//! acknowledges to enter Play. A server may also send a client in Play back into
+//! this state with Start Configuration, e.g., when a proxy moves it to another
+//! server. Packet IDs were renumbered whenever a packet was added, so they are
+//! looked up in the table of the protocol version, see
+//! `ClientboundConfigurationType::from_id`.
+
+use std::collections::BTreeMap;
+
+use uuid::Uuid;
+
+use super::{
+    error::McError,
+    mctypes::{Identifier, MAX_STRING_LENGTH},
+    nbt::{binary, Tag},
+    packet::{
+        builder::PacketBytesBuilder, reader::BufferedPacketReader, ClientboundRawPacket, ConnectionState,
+        ServerboundRawPacket,
+    },
+    text::TextComponent,
+};
+
+/// The maximum length of a resource pack hash, a hexadecimal SHA-1 digest.
+pub const MAX_RESOURCE_PACK_HASH_LENGTH: usize = 40;
+
+/// A clientbound packet of the Configuration state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ClientboundConfigurationType {
+    CookieRequest,
+    PluginMessage,
+    Disconnect,
+    FinishConfiguration,
+    KeepAlive,
+    Ping,
+    ResetChat,
+    RegistryData,
+    RemoveResourcePack,
+    AddResourcePack,
+    StoreCookie,
+    Transfer,
+    FeatureFlags,
+    UpdateTags,
+    KnownPacks,
+    CustomReportDetails,
+    ServerLinks,
+}
+
+use ClientboundConfigurationType as C;
+
+/// Clientbound packets of 1.20.2.
+const CLIENTBOUND_1_20_2: &[ClientboundConfigurationType] = &[
+    C::PluginMessage, C::Disconnect, C::FinishConfiguration, C::KeepAlive, C::Ping,
+    C::RegistryData, C::AddResourcePack, C::FeatureFlags, C::UpdateTags,
+];
+
+/// Clientbound packets of 1.20.3 and 1.20.4.
+const CLIENTBOUND_1_20_3: &[ClientboundConfigurationType] = &[
+    C::PluginMessage, C::Disconnect, C::FinishConfiguration, C::KeepAlive, C::Ping,
+    C::RegistryData, C::RemoveResourcePack, C::AddResourcePack, C::FeatureFlags, C::UpdateTags,
+];
+
+/// Clientbound packets of 1.20.5 and 1.20.6.
+const CLIENTBOUND_1_20_5: &[ClientboundConfigurationType] = &[
+    C::CookieRequest, C::PluginMessage, C::Disconnect, C::FinishConfiguration, C::KeepAlive,
+    C::Ping, C::ResetChat, C::RegistryData, C::RemoveResourcePack, C::AddResourcePack,
+    C::StoreCookie, C::Transfer, C::FeatureFlags, C::UpdateTags, C::KnownPacks,
+];
+
+/// Clientbound packets of 1.21 and 1.21.1.
+const CLIENTBOUND_1_21: &[ClientboundConfigurationType] = &[
+    C::CookieRequest, C::PluginMessage, C::Disconnect, C::FinishConfiguration, C::KeepAlive,
+    C::Ping, C::ResetChat, C::RegistryData, C::RemoveResourcePack, C::AddResourcePack,
+    C::StoreCookie, C::Transfer, C::FeatureFlags, C::UpdateTags, C::KnownPacks,
+    C::CustomReportDetails, C::ServerLinks,
+];
+
+impl ClientboundConfigurationType {
+    /// Gets the clientbound packet table of `protocol_version`, indexed by packet ID.
+    /// # Returns
+    /// `None` for versions without a Configuration state or after 1.21.1, whose tables
+    /// are not known.
+    pub fn table(protocol_version: i32) -> Option<&'static [ClientboundConfigurationType]> {
+        match protocol_version {
+            764 => Some(CLIENTBOUND_1_20_2),
+            765 => Some(CLIENTBOUND_1_20_3),
+            766 => Some(CLIENTBOUND_1_20_5),
+            767 => Some(CLIENTBOUND_1_21),
+            _ => None,
+        }
+    }
+
+    /// Looks up the packet with ID `id` in the table of `protocol_version`.
+    pub fn from_id(protocol_version: i32, id: i32) -> Option<Self> {
+        let table = ClientboundConfigurationType::table(protocol_version)?;
+        usize::try_from(id).ok().and_then(|id| table.get(id)).copied()
+    }
+
+    /// Gets the ID of this packet in the table of `protocol_version`.
+    /// # Returns
+    /// `None` if the packet does not exist in that version.
+    pub fn id(&self, protocol_version: i32) -> Option<i32> {
+        let table = ClientboundConfigurationType::table(protocol_version)?;
+        table.iter().position(|t| t == self).map(|id| id as i32)
+    }
+}
+
+/// A serverbound packet of the Configuration state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ServerboundConfigurationType {
+    ClientInformation,
+    CookieResponse,
+    PluginMessage,
+    AcknowledgeFinishConfiguration,
+    KeepAlive,
+    Pong,
+    ResourcePackResponse,
+    KnownPacks,
+}
+
+use ServerboundConfigurationType as S;
+
+/// Serverbound packets of 1.20.2 to 1.20.4.
+const SERVERBOUND_1_20_2: &[ServerboundConfigurationType] = &[
+    S::ClientInformation, S::PluginMessage, S::AcknowledgeFinishConfiguration, S::KeepAlive,
+    S::Pong, S::ResourcePackResponse,
+];
+
+/// Serverbound packets of 1.20.5 to 1.21.1.
+const SERVERBOUND_1_20_5: &[ServerboundConfigurationType] = &[
+    S::ClientInformation, S::CookieResponse, S::PluginMessage, S::AcknowledgeFinishConfiguration,
+    S::KeepAlive, S::Pong, S::ResourcePackResponse, S::KnownPacks,
+];
+
+impl ServerboundConfigurationType {
+    /// Gets the serverbound packet table of `protocol_version`, indexed by packet ID.
+    /// See `ClientboundConfigurationType::table`.
+    pub fn table(protocol_version: i32) -> Option<&'static [ServerboundConfigurationType]> {
+        match protocol_version {
+            764..=765 => Some(SERVERBOUND_1_20_2),
+            766..=767 => Some(SERVERBOUND_1_20_5),
+            _ => None,
+        }
+    }
+
+    /// Looks up the packet with ID `id` in the table of `protocol_version`.
+    pub fn from_id(protocol_version: i32, id: i32) -> Option<Self> {
+        let table = ServerboundConfigurationType::table(protocol_version)?;
+        usize::try_from(id).ok().and_then(|id| table.get(id)).copied()
+    }
+
+    /// Gets the ID of this packet in the table of `protocol_version`.
+    /// # Returns
+    /// `None` if the packet does not exist in that version.
+    pub fn id(&self, protocol_version: i32) -> Option<i32> {
+        let table = ServerboundConfigurationType::table(protocol_version)?;
+        table.iter().position(|t| t == self).map(|id| id as i32)
+    }
+}
+
+/// Gets the ID of Start Configuration, the Play packet which sends the client back
+/// into the Configuration state, in `protocol_version`.
+pub fn start_configuration_id(protocol_version: i32) -> Option<i32> {
+    match protocol_version {
+        764 => Some(0x65),
+        765 => Some(0x67),
+        766..=767 => Some(0x69),
+        _ => None,
+    }
+}
+
+/// Gets the ID of Acknowledge Configuration, the Play packet answering Start
+/// Configuration, in `protocol_version`.
+pub fn acknowledge_configuration_id(protocol_version: i32) -> Option<i32> {
+    match protocol_version {
+        764..=765 => Some(0x0B),
+        766..=767 => Some(0x0C),
+        _ => None,
+    }
+}
+
+/// A registry sent by the server, e.g., `minecraft:dimension_type`. The network ID
+/// of an entry is its index.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Registry {
+    pub id: Identifier,
+    pub entries: Vec<RegistryEntry>,
+}
+
+impl Registry {
+    /// Gets the entry with the network ID `id`.
+    pub fn get(&self, id: i32) -> Option<&RegistryEntry> {
+        usize::try_from(id).ok().and_then(|id| self.entries.get(id))
+    }
+
+    /// Gets the network ID of the entry named `name`.
+    pub fn id_of(&self, name: &Identifier) -> Option<i32> {
+        self.entries.iter().position(|entry| &entry.id == name).map(|id| id as i32)
+    }
+}
+
+/// An entry of a registry. From 1.20.5, the data is left out for entries of packs
+/// both sides know, see `KnownPack`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegistryEntry {
+    pub id: Identifier,
+    pub data: Option<Tag>,
+}
+
+/// The registries received during configuration, by registry ID.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Registries {
+    registries: BTreeMap<Identifier, Registry>,
+}
+
+impl Registries {
+    pub fn new() -> Self {
+        Registries::default()
+    }
+
+    /// Gets the registry `id`.
+    pub fn get(&self, id: &Identifier) -> Option<&Registry> {
+        self.registries.get(id)
+    }
+
+    /// Adds `registry`, returning the registry it replaces, if any. Servers send every
+    /// registry again when reconfiguring a client.
+    pub fn insert(&mut self, registry: Registry) -> Option<Registry> {
+        self.registries.insert(registry.id.clone(), registry)
+    }
+
+    /// Iterates over the registries in the order of their IDs.
+    pub fn iter(&self) -> impl Iterator<Item = &Registry> {
+        self.registries.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.registries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.registries.is_empty()
+    }
+}
+
+/// The tags of a registry, mapping each tag to the network IDs of its entries.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegistryTags {
+    pub registry: Identifier,
+    pub tags: BTreeMap<Identifier, Vec<i32>>,
+}
+
+/// A data pack, which lets the server leave out registry entries the client has.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KnownPack {
+    pub namespace: String,
+    pub id: String,
+    pub version: String,
+}
+
+/// A resource pack the server asks the client to apply.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResourcePack {
+    /// The ID of the pack, from 1.20.3, when servers started sending several.
+    pub id: Option<Uuid>,
+    pub url: String,
+    /// The SHA-1 digest of the pack as a hexadecimal string, or empty.
+    pub hash: String,
+    /// Whether the server disconnects clients which decline the pack.
+    pub forced: bool,
+    pub prompt: Option<TextComponent>,
+}
+
+/// The result of a resource pack, reported in Resource Pack Response. The results from
+/// `Downloaded` onwards were added in 1.20.3.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ResourcePackResult {
+    SuccessfullyLoaded,
+    Declined,
+    FailedDownload,
+    Accepted,
+    Downloaded,
+    InvalidUrl,
+    FailedReload,
+    Discarded,
+}
+
+impl ResourcePackResult {
+    pub fn id(&self) -> i32 {
+        *self as i32
+    }
+}
+
+/// A decoded clientbound packet of the Configuration state.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfigurationPacket {
+    PluginMessage { channel: Identifier, data: Vec<u8> },
+    Disconnect(TextComponent),
+    FinishConfiguration,
+    KeepAlive(i64),
+    Ping(i32),
+    ResetChat,
+    /// Holds every registry in 1.20.2 to 1.20.4, and a single one from 1.20.5.
+    RegistryData(Vec<Registry>),
+    /// Removes the pack with the ID, or every pack for `None`.
+    RemoveResourcePack(Option<Uuid>),
+    AddResourcePack(ResourcePack),
+    FeatureFlags(Vec<Identifier>),
+    UpdateTags(Vec<RegistryTags>),
+    KnownPacks(Vec<KnownPack>),
+    /// A packet which is not decoded, i.e., cookies, transfers, report details and
+    /// server links.
+    Other { kind: ClientboundConfigurationType, data: Vec<u8> },
+}
+
+impl ConfigurationPacket {
+    /// Decodes `packet` as sent by `protocol_version`.
+    /// # Errors
+    /// This function will return an `UnexpectedPacket` error if the packet ID is not
+    /// part of the Configuration state of `protocol_version`, or a `Malformed` error if
+    /// the packet data is ill-formed.
+    pub fn read_with(packet: &ClientboundRawPacket, protocol_version: i32) -> Result<Self, McError> {
+        let id = packet.header.id;
+        let kind = ClientboundConfigurationType::from_id(protocol_version, id).ok_or(McError::UnexpectedPacket {
+            state: ConnectionState::Configuration,
+            id,
+        })?;
+
+        let mut reader = packet.reader();
+        let decoded = ConfigurationPacket::read_data(&mut reader, kind, protocol_version)
+            .and_then(|decoded| reader.finish().map(|_| decoded));
+        decoded.map_err(|e| e.in_packet(ConnectionState::Configuration, id))
+    }
+
+    fn read_data(
+        reader: &mut BufferedPacketReader,
+        kind: ClientboundConfigurationType,
+        protocol_version: i32,
+    ) -> Result<Self, McError> {
+        let packet = match kind {
+            C::PluginMessage => ConfigurationPacket::PluginMessage {
+                channel: reader.read().map_err(|e| e.in_field("channel"))?,
+                data: reader.read_remaining().to_vec(),
+            },
+            C::Disconnect => ConfigurationPacket::Disconnect(
+                TextComponent::read_with(reader, protocol_version).map_err(|e| e.in_field("reason"))?,
+            ),
+            C::FinishConfiguration => ConfigurationPacket::FinishConfiguration,
+            C::KeepAlive => ConfigurationPacket::KeepAlive(reader.read_i64().map_err(|e| e.in_field("keep_alive_id"))?),
+            C::Ping => ConfigurationPacket::Ping(reader.read_i32().map_err(|e| e.in_field("id"))?),
+            C::ResetChat => ConfigurationPacket::ResetChat,
+            C::RegistryData => {
+                ConfigurationPacket::RegistryData(read_registries(reader, protocol_version).map_err(|e| e.in_field("registry"))?)
+            }
+            C::RemoveResourcePack => ConfigurationPacket::RemoveResourcePack(
+                read_optional(reader, |r| r.read_uuid()).map_err(|e| e.in_field("uuid"))?,
+            ),
+            C::AddResourcePack => ConfigurationPacket::AddResourcePack(read_resource_pack(reader, protocol_version)?),
+            C::FeatureFlags => ConfigurationPacket::FeatureFlags(
+                read_array(reader, |r| r.read()).map_err(|e| e.in_field("feature_flags"))?,
+            ),
+            C::UpdateTags => ConfigurationPacket::UpdateTags(read_array(reader, read_tags).map_err(|e| e.in_field("tags"))?),
+            C::KnownPacks => ConfigurationPacket::KnownPacks(
+                read_array(reader, read_known_pack).map_err(|e| e.in_field("known_packs"))?,
+            ),
+            C::CookieRequest | C::StoreCookie | C::Transfer | C::CustomReportDetails | C::ServerLinks => {
+                ConfigurationPacket::Other {
+                    kind,
+                    data: reader.read_remaining().to_vec(),
+                }
+            }
+        };
+
+        Ok(packet)
+    }
+}
+
+fn read_optional<T>(
+    reader: &mut BufferedPacketReader,
+    read: impl FnOnce(&mut BufferedPacketReader) -> Result<T, McError>,
+) -> Result<Option<T>, McError> {
+    if reader.read_bool()? {
+        Ok(Some(read(reader)?))
+    } else {
+        Ok(None)
+    }
+}
+
+fn read_array<T>(
+    reader: &mut BufferedPacketReader,
+    mut read: impl FnMut(&mut BufferedPacketReader) -> Result<T, McError>,
+) -> Result<Vec<T>, McError> {
+    let len = reader.read_length()?;
+    (0..len).map(|_| read(reader)).collect()
+}
+
+/// Reads the registries of Registry Data. Before 1.20.5, the packet holds the whole
+/// registry codec, a compound of registries, each listing its entries with their
+/// network IDs.
+fn read_registries(reader: &mut BufferedPacketReader, protocol_version: i32) -> Result<Vec<Registry>, McError> {
+    if protocol_version >= 766 {
+        let id = reader.read()?;
+        let entries = read_array(reader, |r| {
+            Ok(RegistryEntry {
+                id: r.read()?,
+                data: read_optional(r, binary::read_nameless)?.flatten(),
+            })
+        })?;
+        return Ok(vec![Registry { id, entries }]);
+    }
+
+    let codec = binary::read_nameless(reader)?.ok_or_else(|| McError::invalid("Registry codec is empty"))?;
+    let codec = codec
+        .as_compound()
+        .ok_or_else(|| McError::invalid("Registry codec is not a compound"))?;
+    codec
+        .iter()
+        .map(|(id, registry)| {
+            let invalid = || McError::invalid(format!("Registry {} is malformed", id));
+            let values = registry
+                .as_compound()
+                .and_then(|registry| registry.get_list("value"))
+                .ok_or_else(invalid)?;
+            let mut entries = values
+                .iter()
+                .map(|value| {
+                    let value = value.as_compound().ok_or_else(invalid)?;
+                    let name = value.get_str("name").ok_or_else(invalid)?;
+                    let network_id = value.get_i64("id").ok_or_else(invalid)?;
+                    let entry = RegistryEntry {
+                        id: Identifier::parse(name)?,
+                        data: value.get("element").cloned(),
+                    };
+                    Ok((network_id, entry))
+                })
+                .collect::<Result<Vec<_>, McError>>()?;
+            entries.sort_by_key(|(network_id, _)| *network_id);
+
+            Ok(Registry {
+                id: Identifier::parse(id)?,
+                entries: entries.into_iter().map(|(_, entry)| entry).collect(),
+            })
+        })
+        .collect()
+}
+
+fn read_resource_pack(reader: &mut BufferedPacketReader, protocol_version: i32) -> Result<ResourcePack, McError> {
+    let id = if protocol_version >= 765 {
+        Some(reader.read_uuid().map_err(|e| e.in_field("uuid"))?)
+    } else {
+        None
+    };
+
+    Ok(ResourcePack {
+        id,
+        url: reader.read_string(MAX_STRING_LENGTH).map_err(|e| e.in_field("url"))?,
+        hash: reader
+            .read_string(MAX_RESOURCE_PACK_HASH_LENGTH)
+            .map_err(|e| e.in_field("hash"))?,
+        forced: reader.read_bool().map_err(|e| e.in_field("forced"))?,
+        prompt: read_optional(reader, |r| TextComponent::read_with(r, protocol_version))
+            .map_err(|e| e.in_field("prompt"))?,
+    })
+}
+
+fn read_tags(reader: &mut BufferedPacketReader) -> Result<RegistryTags, McError> {
+    let registry = reader.read()?;
+    let tags = read_array(reader, |r| Ok((r.read()?, read_array(r, |r| r.read_varint())?)))?;
+
+    Ok(RegistryTags {
+        registry,
+        tags: tags.into_iter().collect(),
+    })
+}
+
+fn read_known_pack(reader: &mut BufferedPacketReader) -> Result<KnownPack, McError> {
+    Ok(KnownPack {
+        namespace: reader.read_string(MAX_STRING_LENGTH)?,
+        id: reader.read_string(MAX_STRING_LENGTH)?,
+        version: reader.read_string(MAX_STRING_LENGTH)?,
+    })
+}
+
+/// A serverbound packet of the Configuration state.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ServerboundConfigurationPacket {
+    PluginMessage { channel: Identifier, data: Vec<u8> },
+    AcknowledgeFinishConfiguration,
+    KeepAlive(i64),
+    Pong(i32),
+    /// Answers Add Resource Pack. The pack ID is only sent from 1.20.3.
+    ResourcePackResponse { id: Option<Uuid>, result: ResourcePackResult },
+    KnownPacks(Vec<KnownPack>),
+}
+
+impl ServerboundConfigurationPacket {
+    pub fn kind(&self) -> ServerboundConfigurationType {
+        match self {
+            ServerboundConfigurationPacket::PluginMessage { .. } => S::PluginMessage,
+            ServerboundConfigurationPacket::AcknowledgeFinishConfiguration => S::AcknowledgeFinishConfiguration,
+            ServerboundConfigurationPacket::KeepAlive(_) => S::KeepAlive,
+            ServerboundConfigurationPacket::Pong(_) => S::Pong,
+            ServerboundConfigurationPacket::ResourcePackResponse { .. } => S::ResourcePackResponse,
+            ServerboundConfigurationPacket::KnownPacks(_) => S::KnownPacks,
+        }
+    }
+
+    /// Encodes the packet as sent by `protocol_version`.
+    /// # Errors
+    /// This function will return a `Malformed` error if the packet does not exist in
+    /// `protocol_version`, e.g., Known Packs before 1.20.5.
+    pub fn write_with(&self, protocol_version: i32) -> Result<ServerboundRawPacket, McError> {
+        let id = self.kind().id(protocol_version).ok_or_else(|| {
+            McError::invalid(format!(
+                "{:?} does not exist in the Configuration state of protocol {}",
+                self.kind(),
+                protocol_version
+            ))
+        })?;
+
+        let builder = PacketBytesBuilder::new();
+        let mut builder = match self {
+            ServerboundConfigurationPacket::PluginMessage { channel, data } => builder.append(channel).append_bytes(data),
+            ServerboundConfigurationPacket::AcknowledgeFinishConfiguration => builder,
+            ServerboundConfigurationPacket::KeepAlive(keep_alive_id) => builder.append_i64(*keep_alive_id),
+            ServerboundConfigurationPacket::Pong(ping_id) => builder.append_i32(*ping_id),
+            ServerboundConfigurationPacket::ResourcePackResponse { id, result } => {
+                let builder = if protocol_version >= 765 {
+                    builder.append_uuid(&id.unwrap_or_default())
+                } else {
+                    builder
+                };
+                builder.append_varint(&result.id().into())
+            }
+            ServerboundConfigurationPacket::KnownPacks(packs) => packs.iter().fold(
+                builder.append_varint(&(packs.len() as i32).into()),
+                |builder, pack| {
+                    builder
+                        .append_string(&pack.namespace)
+                        .append_string(&pack.id)
+                        .append_string(&pack.version)
+                },
+            ),
+        };
+
+        Ok(ServerboundRawPacket { id, data: builder.build() })
+    }
+}
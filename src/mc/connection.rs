@@ -1,18 +1,38 @@
-use std::io;
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use md5::{Digest, Md5};
+use uuid::{Builder, Uuid};
 
 use super::{
+    encryption,
+    error::McError,
+    legacy::{self, LegacyPingFormat},
+    mctypes::{check_string_length, MAX_SERVER_ADDRESS_LENGTH, MAX_USERNAME_LENGTH},
+    options::ConnectionOptions,
     packet::{
         clientbound::{
-            login_success::LoginSuccess, ping_response::PingResponse,
+            encryption_request::EncryptionRequest, login_disconnect::LoginDisconnect,
+            login_plugin_request::LoginPluginRequest, login_success::LoginSuccess,
+            ping_response::PingResponse, set_compression::SetCompression,
             status_response::StatusResponse,
         },
         packet_ids,
         serverbound::{
             handshake::{Handshake, NextState},
-            status_request::StatusRequest, login_start::LoginStart,
+            encryption_response::EncryptionResponse,
+            login_acknowledged::{LoginAcknowledged, LOGIN_ACKNOWLEDGED_PROTOCOL},
+            login_plugin_response::LoginPluginResponse,
+            login_start::LoginStart,
+            ping_request::PingRequest,
+            status_request::StatusRequest,
         },
-        InboundPacket,
+        ConnectionState, InboundPacket, ServerboundRawPacket,
     },
+    resolver::{Resolver, ServerAddress, SystemResolver, DEFAULT_PORT},
+    status::ServerStatus,
     stream::MinecraftStream,
     PROTOCOL_VERSION,
 };
@@ -24,7 +44,7 @@ use super::{
 /// the domain and port are known when the initial connection attempt is made, and the username will
 /// be inferred once a user attempts to login.
 /// # Example
-/// ```
+/// ```no_run
 /// use mcclient::mc::connection::{Connection, OfflineConnection};
 /// 
 /// let mut connection = OfflineConnection::connect("localhost", 25565).expect("Could not connect");
@@ -33,46 +53,148 @@ use super::{
 /// connection.username(); // -> Returns `Some` of String "Makoto"
 /// ```
 pub trait Connection: Sized {
-    /// Attempts to connect to a Minecraft server. On success, the `Connection` is returned.
+    /// Attempts to connect to a Minecraft server with the default `ConnectionOptions`. On
+    /// success, the `Connection` is returned.
+    /// # Errors
+    /// This function will return any error `connect_with` returns.
+    fn connect<T: Into<String>>(domain: T, port: u16) -> Result<Self, McError> {
+        Self::connect_with(domain, port, ConnectionOptions::default())
+    }
+    /// Attempts to connect to a Minecraft server on an explicit port as configured by
+    /// `options`. On success, the `Connection` is returned.
     /// # Errors
-    /// This function will return an error if the connection cannot be established.
-    fn connect<T: Into<String>>(domain: T, port: u16) -> Result<Self, io::Error>;
+    /// This function will return any error `connect_to` returns.
+    fn connect_with<T: Into<String>>(domain: T, port: u16, options: ConnectionOptions) -> Result<Self, McError> {
+        Self::connect_to(&ServerAddress::new(domain, Some(port)), options, &SystemResolver)
+    }
+    /// Attempts to connect to a Minecraft server at `address`, resolved with `resolver`, as
+    /// configured by `options`, which are kept for `reset`. An address without a port is looked
+    /// up in SRV records, see `ServerAddress::resolve`. The Handshake carries the host and port of
+    /// `address` rather than the SRV target, as proxies and virtual hosts expect.
+    /// # Errors
+    /// This function will return a `Malformed` error if the host is longer than the 255
+    /// characters the handshake permits, or an `Io` error if the address cannot be resolved
+    /// or the connection cannot be established, e.g., because the connect timeout elapsed.
+    fn connect_to(address: &ServerAddress, options: ConnectionOptions, resolver: &dyn Resolver) -> Result<Self, McError>;
     /// Attempts to fetch a status report of the server.
     /// # Errors
-    /// This function will return an error if the connection cannot be established. It can be
-    /// inferred that failure to receive this packet means the connection cannot continue.
-    fn status(&mut self) -> Result<StatusResponse, io::Error>;
-    /// Attempts to ping the recipient server.
+    /// This function will return an error if the connection fails, or an `UnexpectedPacket`
+    /// error if the server replies with anything but a status response. It can be inferred
+    /// that failure to receive this packet means the connection cannot continue.
+    fn status(&mut self) -> Result<StatusResponse, McError>;
+    /// Attempts to fetch a status report of the server with the legacy server list ping,
+    /// which servers from before 1.7 understand, in the request `format`. Modern servers
+    /// answer it too. The connection is closed by the server afterwards.
+    /// # Errors
+    /// This function will return an error if the connection fails, or a `Malformed` error
+    /// if the server does not reply with a legacy kick packet holding a status.
+    fn legacy_status(&mut self, format: LegacyPingFormat) -> Result<ServerStatus, McError>;
+    /// Attempts to ping the recipient server with a random payload.
+    /// # Returns
+    /// The round-trip time between sending the Ping Request and receiving its Ping Response.
+    /// # Errors
+    /// This function will return an error if the ping fails, an `UnexpectedPacket` error if
+    /// the server replies with anything but a ping response, or a `Malformed` error if the
+    /// response does not echo the payload. It can be inferred that failure to receive this
+    /// packet means the connection cannot continue.
+    fn ping(&mut self) -> Result<Duration, McError>;
+    /// Performs the exchange of the multiplayer server list on a single handshake, i.e., a
+    /// status request followed by a ping.
     /// # Errors
-    /// This function will return an error if the ping fails. It can be inferred that failure
-    /// to receive this packet means the connection cannot continue.
-    fn ping(&mut self) -> Result<PingResponse, io::Error>;
+    /// This function will return any error `status` or `ping` would return.
+    fn server_list_ping(&mut self) -> Result<ServerListPing, McError>;
     /// Attempts to log into the recipient server. The steps for this varies by connection type.
     /// For offline connections, a Login Request packet is followed immediately by a Login Success,
-    /// while an online connection may require Mojang server authentication, and in newer versions,
-    /// encryption authentication for Microsoft clients.
+    /// while an online-mode server sends an Encryption Request, which is answered after joining the
+    /// server through the connection's `SessionAuthenticator` unless the server says the join is
+    /// not needed, as servers may from 1.20.5. Logins send the UUID `offline_uuid` derives; a server
+    /// may assign another, which is accepted unless the connection requires the offline-mode UUID.
+    /// A Set Compression packet received on the way enables compression on the stream, and Login
+    /// Plugin Requests are answered. From protocol 764 onwards, Login Success is acknowledged,
+    /// moving the connection to the Configuration state.
     /// # Errors
-    /// This function will return an error if the login attempt fails. It can be inferred that
-    /// failure to receive this packet means the connection cannot continue.
-    fn login<T: Into<String>>(&mut self, username: T) -> Result<LoginSuccess, io::Error>;
+    /// This function will return a `Malformed` error if the username is longer than 16
+    /// characters, a `Disconnected` error if the server refuses the login, an `Auth` error
+    /// if it requires a session but no authenticator is set, or if the offline-mode UUID is
+    /// required and the server assigns another, an `Encryption` error if the server's public
+    /// key is invalid, or any error raised while sending or receiving. It can be inferred that failure to
+    /// receive this packet means the connection cannot continue.
+    fn login<T: Into<String>>(&mut self, username: T) -> Result<LoginSuccess, McError>;
 
     /// Gets the stream managed by this connection, which is used to send and receive packets.
     fn sock(&mut self) -> &mut MinecraftStream;
 
     /// Resets the connection. This must be done when issuing different requests established via handshakes.
-    fn reset(&mut self);
+    /// Failed attempts are retried as the `ReconnectPolicy` of the connection options says.
+    /// # Errors
+    /// This function will return an `Io` error if the connection cannot be re-established
+    /// within the attempts of the policy.
+    fn reset(&mut self) -> Result<(), McError>;
 
     /// Gets the domain of the connection. 
     /// # Note
     /// This retrieves the domain passed to the initial connection
     /// attempt, not the endpoint IP resolved by an underlying TCP stream object.
     fn domain(&self) -> &str;
-    /// Gets the port of the connection.
+    /// Gets the port sent in the Handshake, which is the port of the address passed to the
+    /// initial connection attempt, or `DEFAULT_PORT` if it had none. Like `domain`, this is
+    /// not the port of an SRV target the address was resolved to.
     fn port(&self) -> u16;
     /// Gets the username of the connection if it is set. This is set by a `login` invocation.
     fn username(&self) -> &Option<String>;
 }
 
+/// The result of `Connection::server_list_ping`.
+#[derive(Debug)]
+pub struct ServerListPing {
+    pub status: ServerStatus,
+    /// The round-trip time of the ping.
+    pub latency: Duration,
+}
+
+/// Answers the Login Plugin Requests a server sends during login, e.g., for proxy
+/// forwarding. Closures taking the request are handlers too.
+pub trait LoginPluginHandler {
+    /// Returns the payload of the response, or `None` if the channel is not understood.
+    fn handle(&mut self, request: &LoginPluginRequest) -> Option<Vec<u8>>;
+}
+
+impl<F: FnMut(&LoginPluginRequest) -> Option<Vec<u8>>> LoginPluginHandler for F {
+    fn handle(&mut self, request: &LoginPluginRequest) -> Option<Vec<u8>> {
+        self(request)
+    }
+}
+
+/// Joins servers on the session server, which online-mode servers check before letting a
+/// player in. <https://wiki.vg/Protocol_Encryption#Authentication> Closures taking the
+/// server hash are authenticators too.
+pub trait SessionAuthenticator {
+    /// Joins the server identified by `server_hash`, see `encryption::server_hash`.
+    /// # Errors
+    /// This function should return an `Auth` error if the session server refuses the join.
+    fn join(&mut self, server_hash: &str) -> Result<(), McError>;
+}
+
+impl<F: FnMut(&str) -> Result<(), McError>> SessionAuthenticator for F {
+    fn join(&mut self, server_hash: &str) -> Result<(), McError> {
+        self(server_hash)
+    }
+}
+
+/// Derives the UUID an offline-mode server assigns to `username`: a version 3 UUID of
+/// the MD5 hash of `OfflinePlayer:<username>`. Unlike RFC 4122 name-based UUIDs, no
+/// namespace is hashed along. The name is hashed as given, so the UUID is case-sensitive.
+/// # Example
+/// ```
+/// use mcclient::mc::connection::offline_uuid;
+///
+/// assert_eq!(offline_uuid("Notch").to_string(), "b50ad385-829d-3141-a216-7e7d7539ba7f");
+/// ```
+pub fn offline_uuid(username: &str) -> Uuid {
+    let hash = Md5::digest(format!("OfflinePlayer:{}", username));
+    Builder::from_md5_bytes(hash.into()).into_uuid()
+}
+
 /// Represents a connection stream to an offline Minecraft server.
 /// <br>
 /// The handshake packet is sent when either a status or login request
@@ -83,79 +205,106 @@ pub struct OfflineConnection {
     domain: String,
     port: u16,
     username: Option<String>,
+    plugin_handler: Option<Box<dyn LoginPluginHandler>>,
+    authenticator: Option<Box<dyn SessionAuthenticator>>,
+    require_offline_uuid: bool,
+    options: ConnectionOptions,
+    /// The addresses the server address resolved to, which `reset` connects to again.
+    addrs: Vec<SocketAddr>,
+}
+
+impl OfflineConnection {
+    /// Sets the handler answering Login Plugin Requests. Without one, every request is
+    /// answered as not understood, like the vanilla client does.
+    pub fn set_login_plugin_handler<H: LoginPluginHandler + 'static>(&mut self, handler: H) {
+        self.plugin_handler = Some(Box::new(handler));
+    }
+
+    /// Sets the authenticator joining online-mode servers. Without one, logging into a
+    /// server which requires a session fails.
+    pub fn set_session_authenticator<A: SessionAuthenticator + 'static>(&mut self, authenticator: A) {
+        self.authenticator = Some(Box::new(authenticator));
+    }
+
+    /// Makes `login` fail if Login Success assigns another UUID than the offline-mode one
+    /// sent. This is off by default, as online-mode servers and proxies assign their own
+    /// UUIDs; compare `LoginSuccess::uuid` with `offline_uuid` to tell them apart instead.
+    pub fn set_require_offline_uuid(&mut self, require: bool) {
+        self.require_offline_uuid = require;
+    }
 }
 
 impl Connection for OfflineConnection {
-    fn connect<T: Into<String>>(domain: T, port: u16) -> Result<Self, io::Error> {
-        let domain_parsed = domain.into();
-        let stream = MinecraftStream::connect(format!("{}:{}", domain_parsed.clone(), port))?;
+    fn connect_to(address: &ServerAddress, options: ConnectionOptions, resolver: &dyn Resolver) -> Result<Self, McError> {
+        check_string_length(&address.host, MAX_SERVER_ADDRESS_LENGTH)?;
+        let addrs = address.resolve(resolver)?;
+        let stream = MinecraftStream::connect_with(&addrs[..], &options)?;
 
         Ok(OfflineConnection {
             stream,
-            domain: domain_parsed,
-            port,
+            port: address.port.unwrap_or(DEFAULT_PORT),
+            domain: address.host.clone(),
             username: None,
+            plugin_handler: None,
+            authenticator: None,
+            require_offline_uuid: false,
+            options,
+            addrs,
         })
     }
 
-    fn status(&mut self) -> Result<StatusResponse, io::Error> {
-        let handshake = Handshake {
-            protocol_version: PROTOCOL_VERSION.into(),
-            server_addr: self.domain.clone().into(), // TODO change to String type after MCType refactor
-            port: self.port,
-            next_state: NextState::STATUS,
-        };
-
-        self.stream.send(&handshake)?;
-        self.stream.send(&StatusRequest)?;
+    fn status(&mut self) -> Result<StatusResponse, McError> {
+        send_handshake(&mut self.stream, PROTOCOL_VERSION, &self.domain, self.port, NextState::STATUS)?;
+        request_status(&mut self.stream)
+    }
 
-        let inbound = self.stream.read()?;
-        if inbound.header.id != packet_ids::clientbound::STATUS_RESPONSE {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, "Bad packet ID."));
-        }
-        let response = StatusResponse::from_data(&inbound)?;
+    fn legacy_status(&mut self, format: LegacyPingFormat) -> Result<ServerStatus, McError> {
+        self.stream.send_raw(&legacy::encode_request(format, &self.domain, self.port)?)?;
+        legacy::read_response(&mut self.stream)
+    }
 
-        Ok(response)
+    fn ping(&mut self) -> Result<Duration, McError> {
+        send_handshake(&mut self.stream, PROTOCOL_VERSION, &self.domain, self.port, NextState::STATUS)?;
+        request_ping(&mut self.stream)
     }
 
-    fn ping(&mut self) -> Result<PingResponse, io::Error> {
-        Ok(PingResponse {})
+    fn server_list_ping(&mut self) -> Result<ServerListPing, McError> {
+        send_handshake(&mut self.stream, PROTOCOL_VERSION, &self.domain, self.port, NextState::STATUS)?;
+        let status = request_status(&mut self.stream)?.status;
+        let latency = request_ping(&mut self.stream)?;
+
+        Ok(ServerListPing { status, latency })
     }
 
-    fn login<T: Into<String>>(&mut self, username: T) -> Result<LoginSuccess, io::Error> {
+    fn login<T: Into<String>>(&mut self, username: T) -> Result<LoginSuccess, McError> {
         let username_parsed = username.into();
+        check_string_length(&username_parsed, MAX_USERNAME_LENGTH)?;
         self.username = Some(username_parsed.clone());
-        let login_handshake = Handshake {
-            protocol_version: PROTOCOL_VERSION.into(),
-            server_addr: self.domain.clone().into(),
-            port: self.port(),
-            next_state: NextState::LOGIN
-        };
-        let login_start = LoginStart {
-            username: username_parsed,
-            uuid: None
-        };
-
-        self.stream.send(&login_handshake).expect("Could not send handshake.");
-        self.stream.send(&login_start).expect("Could not send login start.");
-
-        let inbound = self.stream.read().expect("Cannot read packet.");
-        println!("Packet ID: {}, {}", inbound.header.id, inbound.header.size);
-        // Ignore this, working on login.
-        // let mut inbound_data = inbound.data.clone();
-        // let size = VarInt::from_vec_front(&mut inbound_data).unwrap();
-        // let relevant_bytes = &inbound_data[size.bytes().len() - 1 as usize..];
-        // println!("Packet data: {:?}", TryInto::<String>::try_into(MCString::from_bytes(relevant_bytes).unwrap()));
-
-        Ok(LoginSuccess {})
+
+        send_handshake(&mut self.stream, PROTOCOL_VERSION, &self.domain, self.port, NextState::LOGIN)?;
+        let login_success = perform_login(
+            &mut self.stream,
+            PROTOCOL_VERSION,
+            username_parsed,
+            &mut self.plugin_handler,
+            &mut self.authenticator,
+            self.require_offline_uuid,
+        )?;
+        self.username = Some(login_success.username.clone());
+
+        Ok(login_success)
     }
 
     fn sock(&mut self) -> &mut MinecraftStream {
         &mut self.stream
     }
 
-    fn reset(&mut self) {
-        self.stream = MinecraftStream::connect(format!("{}:{}", self.domain.clone(), self.port.clone())).unwrap();
+    fn reset(&mut self) -> Result<(), McError> {
+        self.stream = self
+            .options
+            .reconnect_policy()
+            .retry(|| MinecraftStream::connect_with(&self.addrs[..], &self.options))?;
+        Ok(())
     }
 
     fn domain(&self) -> &str {
@@ -170,3 +319,147 @@ impl Connection for OfflineConnection {
         &self.username
     }
 }
+
+/// Sends the Handshake which moves the connection on `stream` to `next_state`.
+pub(crate) fn send_handshake(
+    stream: &mut MinecraftStream,
+    protocol_version: i32,
+    domain: &str,
+    port: u16,
+    next_state: NextState,
+) -> Result<(), McError> {
+    let handshake = Handshake {
+        protocol_version,
+        server_addr: domain.to_owned(),
+        port,
+        next_state,
+    };
+
+    stream.send(&handshake)
+}
+
+/// Sends a Status Request and reads its response.
+pub(crate) fn request_status(stream: &mut MinecraftStream) -> Result<StatusResponse, McError> {
+    stream.send(&StatusRequest)?;
+
+    let inbound = stream.read()?;
+    if inbound.header.id != packet_ids::clientbound::STATUS_RESPONSE {
+        return Err(McError::UnexpectedPacket {
+            state: ConnectionState::Status,
+            id: inbound.header.id,
+        });
+    }
+    StatusResponse::from_data(&inbound)
+}
+
+/// Sends a Ping Request with a random payload, returning the time until the server echoes it.
+pub(crate) fn request_ping(stream: &mut MinecraftStream) -> Result<Duration, McError> {
+    let payload = rand::random();
+    let sent = Instant::now();
+    stream.send(&PingRequest { payload })?;
+
+    let inbound = stream.read()?;
+    let latency = sent.elapsed();
+    if inbound.header.id != packet_ids::clientbound::PING_RESPONSE {
+        return Err(McError::UnexpectedPacket {
+            state: ConnectionState::Status,
+            id: inbound.header.id,
+        });
+    }
+    let response = PingResponse::from_data(&inbound)?;
+    if response.payload != payload {
+        return Err(McError::invalid(format!(
+            "Ping response payload {} does not match the request payload {}",
+            response.payload, payload
+        ))
+        .in_field("payload")
+        .in_packet(ConnectionState::Status, packet_ids::clientbound::PING_RESPONSE));
+    }
+
+    Ok(latency)
+}
+
+/// Sends Login Start for `username` and handles the Login state until Login Success,
+/// speaking `protocol_version`. Another UUID than the offline-mode one sent is refused
+/// if `require_offline_uuid` is set.
+pub(crate) fn perform_login(
+    stream: &mut MinecraftStream,
+    protocol_version: i32,
+    username: String,
+    plugin_handler: &mut Option<Box<dyn LoginPluginHandler>>,
+    authenticator: &mut Option<Box<dyn SessionAuthenticator>>,
+    require_offline_uuid: bool,
+) -> Result<LoginSuccess, McError> {
+    let uuid = offline_uuid(&username);
+    let login_start = LoginStart { username, uuid: Some(uuid) };
+    stream.send(&ServerboundRawPacket {
+        id: packet_ids::serverbound::LOGIN_START,
+        data: login_start.to_bytes_with(protocol_version)?,
+    })?;
+
+    loop {
+        let inbound = stream.read()?;
+        match inbound.header.id {
+            packet_ids::clientbound::LOGIN_SUCCESS => {
+                let login_success = LoginSuccess::from_data_with(&inbound, protocol_version)?;
+                if require_offline_uuid && login_success.uuid != uuid {
+                    return Err(McError::Auth(format!(
+                        "The server assigned UUID {} rather than the offline-mode UUID {}",
+                        login_success.uuid, uuid
+                    )));
+                }
+                if protocol_version >= LOGIN_ACKNOWLEDGED_PROTOCOL {
+                    stream.send(&LoginAcknowledged)?;
+                }
+                return Ok(login_success);
+            }
+            packet_ids::clientbound::SET_COMPRESSION => {
+                let set_compression = SetCompression::from_data(&inbound)?;
+                stream.set_compression(set_compression.threshold);
+            }
+            packet_ids::clientbound::LOGIN_PLUGIN_REQUEST => {
+                let request = LoginPluginRequest::from_data(&inbound)?;
+                let response = match plugin_handler.as_mut().and_then(|handler| handler.handle(&request)) {
+                    Some(data) => LoginPluginResponse::understood(request.message_id, data),
+                    None => LoginPluginResponse::not_understood(request.message_id),
+                };
+                stream.send(&response)?;
+            }
+            packet_ids::clientbound::LOGIN_DISCONNECT => {
+                let disconnect = LoginDisconnect::from_data(&inbound)?;
+                return Err(McError::Disconnected(Box::new(disconnect.reason)));
+            }
+            packet_ids::clientbound::ENCRYPTION_REQUEST => {
+                let request = EncryptionRequest::from_data_with(&inbound, protocol_version)?;
+                perform_encryption(stream, &request, authenticator)?;
+            }
+            id => {
+                return Err(McError::UnexpectedPacket {
+                    state: ConnectionState::Login,
+                    id,
+                })
+            }
+        }
+    }
+}
+
+/// Joins the server through `authenticator` if `request` asks for it, answers `request`
+/// with an Encryption Response and encrypts `stream` from then on.
+/// <https://wiki.vg/Protocol_Encryption>
+fn perform_encryption(
+    stream: &mut MinecraftStream,
+    request: &EncryptionRequest,
+    authenticator: &mut Option<Box<dyn SessionAuthenticator>>,
+) -> Result<(), McError> {
+    let shared_secret = encryption::generate_shared_secret();
+    if request.should_authenticate() {
+        let authenticator = authenticator.as_mut().ok_or_else(|| {
+            McError::Auth("The server is in online mode and requires an authenticated session".to_owned())
+        })?;
+        authenticator.join(&encryption::server_hash(&request.server_id, &shared_secret, &request.public_key))?;
+    }
+
+    let response = EncryptionResponse::encrypt(&request.public_key, &shared_secret, &request.verify_token)?;
+    stream.send(&response)?;
+    stream.enable_encryption(&shared_secret)
+}
@@ -0,0 +1,163 @@
+//! Protocol encryption. <https://wiki.vg/Protocol_Encryption>
+//! <br>
+//! An online-mode server sends its RSA public key in Encryption Request. The client
+//! picks a random 16-byte shared secret, encrypts it and the server's verify token
+//! with the key, and sends both back in Encryption Response. From then on, both
+//! directions of the stream are encrypted with AES-128 in CFB8 mode, using the
+//! shared secret as both the key and the IV.
+
+use std::io::{self, Read, Write};
+
+use aes::{
+    cipher::{generic_array::GenericArray, BlockDecryptMut, BlockEncryptMut, KeyIvInit},
+    Aes128,
+};
+use num_bigint::BigInt;
+use rand::RngCore;
+use rsa::{pkcs8::DecodePublicKey, Pkcs1v15Encrypt, RsaPublicKey};
+use sha1::{Digest, Sha1};
+
+use super::error::McError;
+
+/// The length of the shared secret, in bytes.
+pub const SHARED_SECRET_LENGTH: usize = 16;
+
+type Encryptor = cfb8::Encryptor<Aes128>;
+type Decryptor = cfb8::Decryptor<Aes128>;
+
+/// Generates a random shared secret.
+pub fn generate_shared_secret() -> [u8; SHARED_SECRET_LENGTH] {
+    let mut secret = [0_u8; SHARED_SECRET_LENGTH];
+    rand::thread_rng().fill_bytes(&mut secret);
+    secret
+}
+
+/// Encrypts `data` with PKCS#1 v1.5 padding using `public_key`, the DER-encoded
+/// X.509 SubjectPublicKeyInfo sent in Encryption Request.
+/// # Errors
+/// This function will return an `Encryption` error if the key cannot be parsed or
+/// `data` is too long for it.
+pub fn encrypt_with_public_key(public_key: &[u8], data: &[u8]) -> Result<Vec<u8>, McError> {
+    let key = RsaPublicKey::from_public_key_der(public_key)
+        .map_err(|e| McError::Encryption(format!("Invalid server public key: {}", e)))?;
+
+    key.encrypt(&mut rand::thread_rng(), Pkcs1v15Encrypt, data)
+        .map_err(|e| McError::Encryption(format!("Could not encrypt with the server public key: {}", e)))
+}
+
+/// Computes the server hash sent to the session server when joining a server,
+/// i.e., the SHA-1 digest of the server ID, shared secret and public key, printed
+/// as a signed hexadecimal number without leading zeros.
+/// # Example
+/// ```
+/// use mcclient::mc::encryption::server_hash;
+///
+/// // The digest of "jeb_" is negative when read as a two's complement number.
+/// assert_eq!(server_hash("jeb_", b"", b""), "-7c9d5b0044c130109a5d7b5fb5c317c02b4e28c1");
+/// ```
+pub fn server_hash(server_id: &str, shared_secret: &[u8], public_key: &[u8]) -> String {
+    let digest = Sha1::new()
+        .chain_update(server_id.as_bytes())
+        .chain_update(shared_secret)
+        .chain_update(public_key)
+        .finalize();
+
+    BigInt::from_signed_bytes_be(&digest).to_str_radix(16)
+}
+
+/// A reader which decrypts everything read from `inner` once encryption is enabled.
+pub struct DecryptingReader<R> {
+    inner: R,
+    cipher: Option<Decryptor>,
+}
+
+impl<R: Read> DecryptingReader<R> {
+    /// Wraps `inner`, passing bytes through unchanged until `enable` is called.
+    pub fn new(inner: R) -> Self {
+        DecryptingReader { inner, cipher: None }
+    }
+
+    /// Decrypts every byte read from now on with `shared_secret`.
+    pub fn enable(&mut self, shared_secret: &[u8; SHARED_SECRET_LENGTH]) {
+        self.cipher = Some(Decryptor::new(shared_secret.into(), shared_secret.into()));
+    }
+
+    /// Returns `true` if encryption is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.cipher.is_some()
+    }
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+}
+
+impl<R: Read> Read for DecryptingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if let Some(cipher) = &mut self.cipher {
+            // CFB8 works on one byte at a time.
+            for byte in &mut buf[..n] {
+                cipher.decrypt_block_mut(GenericArray::from_mut_slice(std::slice::from_mut(byte)));
+            }
+        }
+        Ok(n)
+    }
+}
+
+/// A writer which encrypts everything written to `inner` once encryption is enabled.
+pub struct EncryptingWriter<W> {
+    inner: W,
+    cipher: Option<Encryptor>,
+    /// Reused to hold the encrypted copy of the bytes being written.
+    encrypted: Vec<u8>,
+}
+
+impl<W: Write> EncryptingWriter<W> {
+    /// Wraps `inner`, passing bytes through unchanged until `enable` is called.
+    pub fn new(inner: W) -> Self {
+        EncryptingWriter {
+            inner,
+            cipher: None,
+            encrypted: Vec::new(),
+        }
+    }
+
+    /// Encrypts every byte written from now on with `shared_secret`.
+    pub fn enable(&mut self, shared_secret: &[u8; SHARED_SECRET_LENGTH]) {
+        self.cipher = Some(Encryptor::new(shared_secret.into(), shared_secret.into()));
+    }
+
+    /// Returns `true` if encryption is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.cipher.is_some()
+    }
+
+    /// Gets a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+}
+
+impl<W: Write> Write for EncryptingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let cipher = match &mut self.cipher {
+            Some(cipher) => cipher,
+            None => return self.inner.write(buf),
+        };
+
+        self.encrypted.clear();
+        self.encrypted.extend_from_slice(buf);
+        for byte in &mut self.encrypted {
+            cipher.encrypt_block_mut(GenericArray::from_mut_slice(std::slice::from_mut(byte)));
+        }
+        // The cipher state has advanced past every byte, so all of them must be written.
+        self.inner.write_all(&self.encrypted)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
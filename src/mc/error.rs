@@ -0,0 +1,159 @@
+use std::{error::Error, fmt, io};
+
+use super::{mctypes::StringError, packet::ConnectionState, text::TextComponent};
+
+/// Describes why a value could not be decoded.
+#[derive(Debug)]
+pub enum DataError {
+    /// The data ended before the value was complete.
+    Truncated {
+        needed: usize,
+        remaining: usize,
+        position: usize,
+    },
+    /// Bytes were left over after the last field of a packet was read.
+    TrailingBytes { count: usize, position: usize },
+    /// A string was too long for its field or was not valid UTF-8.
+    String(StringError),
+    /// The value does not follow the protocol, e.g., an unknown enum variant.
+    Invalid(String),
+    /// A slot holds a data component type which `DataComponent` does not model.
+    /// Components are not length-prefixed, so the rest of the slot cannot be read.
+    UnsupportedComponent { id: i32 },
+}
+
+impl fmt::Display for DataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataError::Truncated { needed, remaining, position } => write!(
+                f,
+                "data truncated: needed {} bytes at position {}, but only {} remain",
+                needed, position, remaining
+            ),
+            DataError::TrailingBytes { count, position } => {
+                write!(f, "{} trailing bytes left unread at position {}", count, position)
+            }
+            DataError::String(e) => e.fmt(f),
+            DataError::Invalid(msg) => f.write_str(msg),
+            DataError::UnsupportedComponent { id } => {
+                write!(f, "unsupported data component type {}, which cannot be skipped", id)
+            }
+        }
+    }
+}
+
+/// The error type returned by every fallible operation in this crate.
+#[derive(Debug)]
+pub enum McError {
+    /// The underlying stream or file failed.
+    Io(io::Error),
+    /// Data received from the server, or passed to an encoder, does not follow the
+    /// protocol. `field` and the packet `state` and `id` are filled in when known.
+    Malformed {
+        error: DataError,
+        field: Option<&'static str>,
+        state: Option<ConnectionState>,
+        id: Option<i32>,
+    },
+    /// The server sent a packet which is not expected in the current state.
+    UnexpectedPacket { state: ConnectionState, id: i32 },
+    /// The server closed the connection, giving a reason.
+    Disconnected(Box<TextComponent>),
+    /// The server or session service rejected the client's credentials.
+    Auth(String),
+    /// A compressed packet or file could not be compressed or decompressed.
+    Compression(String),
+    /// The encrypted session could not be set up.
+    Encryption(String),
+}
+
+impl McError {
+    /// Constructs a `Malformed` error without field or packet context.
+    pub fn malformed(error: DataError) -> Self {
+        McError::Malformed {
+            error,
+            field: None,
+            state: None,
+            id: None,
+        }
+    }
+
+    /// Constructs a `Malformed` error describing an invalid value.
+    pub fn invalid<S: Into<String>>(msg: S) -> Self {
+        McError::malformed(DataError::Invalid(msg.into()))
+    }
+
+    /// Records the field being decoded when a `Malformed` error occurred. The
+    /// innermost field is kept if one was already recorded.
+    pub fn in_field(mut self, name: &'static str) -> Self {
+        if let McError::Malformed { field: field @ None, .. } = &mut self {
+            *field = Some(name);
+        }
+        self
+    }
+
+    /// Records the packet being decoded when a `Malformed` error occurred.
+    pub fn in_packet(mut self, packet_state: ConnectionState, packet_id: i32) -> Self {
+        if let McError::Malformed { state, id, .. } = &mut self {
+            state.get_or_insert(packet_state);
+            id.get_or_insert(packet_id);
+        }
+        self
+    }
+}
+
+impl fmt::Display for McError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            McError::Io(e) => write!(f, "I/O error: {}", e),
+            McError::Malformed { error, field, state, id } => {
+                f.write_str("malformed data")?;
+                if let Some(field) = field {
+                    write!(f, " in field `{}`", field)?;
+                }
+                if let Some(id) = id {
+                    write!(f, " of packet {:#04x}", id)?;
+                }
+                if let Some(state) = state {
+                    write!(f, " ({:?})", state)?;
+                }
+                write!(f, ": {}", error)
+            }
+            McError::UnexpectedPacket { state, id } => {
+                write!(f, "unexpected packet {:#04x} in state {:?}", id, state)
+            }
+            McError::Disconnected(reason) => write!(f, "disconnected by server: {}", reason.to_plain_string()),
+            McError::Auth(msg) => write!(f, "authentication failed: {}", msg),
+            McError::Compression(msg) => write!(f, "compression error: {}", msg),
+            McError::Encryption(msg) => write!(f, "encryption error: {}", msg),
+        }
+    }
+}
+
+impl Error for McError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            McError::Io(e) => Some(e),
+            McError::Malformed { error: DataError::String(e), .. } => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for McError {
+    fn from(value: io::Error) -> Self {
+        McError::Io(value)
+    }
+}
+
+impl From<StringError> for McError {
+    fn from(value: StringError) -> Self {
+        McError::malformed(DataError::String(value))
+    }
+}
+
+impl From<serde_json::Error> for McError {
+    fn from(value: serde_json::Error) -> Self {
+        McError::invalid(format!("invalid JSON: {}", value))
+    }
+}
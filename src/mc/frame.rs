@@ -0,0 +1,122 @@
+use std::io::{self, Read};
+
+use super::error::McError;
+
+/// The largest packet frame the protocol allows, excluding its length prefix.
+/// A frame length is a `VarInt` of at most 3 bytes, i.e. `2^21 - 1`.
+/// <https://wiki.vg/Protocol#Packet_format>
+pub const MAX_FRAME_SIZE: usize = (1 << 21) - 1;
+
+/// The largest number of bytes a frame length prefix may occupy.
+pub const MAX_FRAME_PREFIX_SIZE: usize = 3;
+
+/// Splits an inbound byte stream into length-delimited packet frames.
+/// <br>
+/// Bytes are buffered between calls, so a frame split across several reads is
+/// reassembled, and bytes belonging to the next frame are kept for the next call
+/// rather than discarded.
+#[derive(Default)]
+pub struct FrameDecoder {
+    buffer: Vec<u8>,
+    start: usize,
+}
+
+impl FrameDecoder {
+    /// Constructs a decoder with an empty buffer.
+    pub fn new() -> Self {
+        FrameDecoder::default()
+    }
+
+    /// Appends `bytes` received from the remote end to the internal buffer.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        // Reclaim the space of frames that were already handed out before growing.
+        if self.start > 0 && self.start == self.buffer.len() {
+            self.buffer.clear();
+            self.start = 0;
+        } else if self.start > self.buffer.len() / 2 {
+            self.buffer.drain(..self.start);
+            self.start = 0;
+        }
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Returns the number of buffered bytes which have not been returned as a frame yet.
+    pub fn buffered(&self) -> usize {
+        self.buffer.len() - self.start
+    }
+
+    /// Attempts to take one complete frame from the buffered bytes. The returned frame
+    /// excludes the length prefix, i.e., it starts with the packet ID.
+    /// # Returns
+    /// `None` if the buffer does not hold a complete frame yet.
+    /// # Errors
+    /// This function will return a `Malformed` error if the length prefix is longer
+    /// than 3 bytes. A 3-byte prefix cannot describe more than `MAX_FRAME_SIZE` bytes,
+    /// so this also rejects oversized frames.
+    pub fn next_frame(&mut self) -> Result<Option<Vec<u8>>, McError> {
+        let pending = &self.buffer[self.start..];
+        let (len, prefix_size) = match read_frame_length(pending)? {
+            Some(t) => t,
+            None => return Ok(None),
+        };
+
+        if pending.len() < prefix_size + len {
+            return Ok(None);
+        }
+
+        let frame = pending[prefix_size..prefix_size + len].to_vec();
+        self.start += prefix_size + len;
+
+        Ok(Some(frame))
+    }
+
+    /// Reads from `reader` until one complete frame is buffered, and returns it.
+    /// # Errors
+    /// This function will return an `Io` error of kind `UnexpectedEof` if the stream
+    /// closes in the middle of a frame, or any error returned by `next_frame` or the
+    /// reader.
+    pub fn read_frame<R: Read>(&mut self, reader: &mut R) -> Result<Vec<u8>, McError> {
+        let mut buf = [0_u8; 4096];
+        loop {
+            if let Some(frame) = self.next_frame()? {
+                return Ok(frame);
+            }
+
+            let bytes_read = match reader.read(&mut buf) {
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            if bytes_read == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "Stream closed before a complete packet frame was received",
+                )
+                .into());
+            }
+            self.feed(&buf[..bytes_read]);
+        }
+    }
+}
+
+/// Parses the `VarInt` length prefix at the front of `bytes`.
+/// # Returns
+/// A pair of the frame length and the byte size of the prefix, or `None` if the
+/// prefix itself is incomplete.
+fn read_frame_length(bytes: &[u8]) -> Result<Option<(usize, usize)>, McError> {
+    let mut value = 0_usize;
+
+    for (i, b) in bytes.iter().take(MAX_FRAME_PREFIX_SIZE).enumerate() {
+        value |= ((*b & 0x7F) as usize) << (7 * i);
+
+        if *b & 0x80 == 0 {
+            return Ok(Some((value, i + 1)));
+        }
+    }
+
+    if bytes.len() >= MAX_FRAME_PREFIX_SIZE {
+        return Err(McError::invalid("Packet length prefix exceeds 3 bytes"));
+    }
+
+    Ok(None)
+}
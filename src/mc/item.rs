@@ -0,0 +1,503 @@
+//! Item stacks, i.e., the Slot data type. <https://wiki.vg/Slot_Data>
+//! <br>
+//! Slots were encoded as a present flag, item ID, count and NBT until 1.20.5,
+//! which replaced the NBT with structured data components. Both layouts decode
+//! into `ItemStack`; `SlotFormat` selects which one is on the wire. Since the layout
+//! depends on the protocol version, `ItemStack` is not an `MCType`: read and write
+//! slots with `BufferedPacketReader::read_slot` and `PacketBytesBuilder::try_append_slot`.
+//! <br>
+//! Data components carry no length prefix, so a structured slot holding a component
+//! type `DataComponent` does not model cannot be decoded, and fails with
+//! `DataError::UnsupportedComponent` rather than being skipped.
+
+use super::{
+    error::{DataError, McError},
+    nbt::{binary, Compound, NetworkNbt, Nbt, Tag},
+    packet::{builder::PacketBytesBuilder, reader::BufferedPacketReader},
+    text::TextComponent,
+};
+
+/// The wire layout of a Slot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlotFormat {
+    /// Present flag, item ID, byte count and NBT with a named root (before 1.20.2).
+    Legacy,
+    /// As `Legacy`, but with a nameless NBT root (1.20.2 to 1.20.4).
+    NamelessNbt,
+    /// Count, item ID and data components (1.20.5 and later).
+    Structured,
+}
+
+impl SlotFormat {
+    /// Selects the slot layout used by `protocol_version`.
+    /// # Returns
+    /// `None` for versions before 1.13.2 or after 1.21.1, whose layouts are not known.
+    pub fn for_protocol(protocol_version: i32) -> Option<Self> {
+        match protocol_version {
+            404..=763 => Some(SlotFormat::Legacy),
+            764..=765 => Some(SlotFormat::NamelessNbt),
+            766..=767 => Some(SlotFormat::Structured),
+            _ => None,
+        }
+    }
+}
+
+/// The ID of an enchantment. Legacy NBT names enchantments by identifier, while
+/// data components refer to them by registry ID.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EnchantmentId {
+    Named(String),
+    Registry(i32),
+}
+
+/// An enchantment and its level.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Enchantment {
+    pub id: EnchantmentId,
+    pub level: i32,
+}
+
+pub const COMPONENT_CUSTOM_DATA: i32 = 0;
+pub const COMPONENT_MAX_STACK_SIZE: i32 = 1;
+pub const COMPONENT_MAX_DAMAGE: i32 = 2;
+pub const COMPONENT_DAMAGE: i32 = 3;
+pub const COMPONENT_UNBREAKABLE: i32 = 4;
+pub const COMPONENT_CUSTOM_NAME: i32 = 5;
+pub const COMPONENT_ITEM_NAME: i32 = 6;
+pub const COMPONENT_LORE: i32 = 7;
+pub const COMPONENT_RARITY: i32 = 8;
+pub const COMPONENT_ENCHANTMENTS: i32 = 9;
+pub const COMPONENT_CUSTOM_MODEL_DATA: i32 = 13;
+pub const COMPONENT_HIDE_ADDITIONAL_TOOLTIP: i32 = 14;
+pub const COMPONENT_HIDE_TOOLTIP: i32 = 15;
+pub const COMPONENT_REPAIR_COST: i32 = 16;
+pub const COMPONENT_CREATIVE_SLOT_LOCK: i32 = 17;
+pub const COMPONENT_ENCHANTMENT_GLINT_OVERRIDE: i32 = 18;
+pub const COMPONENT_INTANGIBLE_PROJECTILE: i32 = 19;
+pub const COMPONENT_FIRE_RESISTANT: i32 = 21;
+pub const COMPONENT_STORED_ENCHANTMENTS: i32 = 23;
+
+/// A structured item data component, keyed by the component registry of
+/// 1.20.5 to 1.21.1, which agree on the IDs of every type modelled here.
+/// <br>
+/// Only the types with a `COMPONENT_` constant are modelled. Components are not
+/// length-prefixed on the wire, so a slot carrying any other type, e.g. `food`,
+/// `attribute_modifiers`, `dyed_color` or `potion_contents`, cannot be decoded.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DataComponent {
+    CustomData(Tag),
+    MaxStackSize(i32),
+    MaxDamage(i32),
+    Damage(i32),
+    Unbreakable { show_in_tooltip: bool },
+    CustomName(TextComponent),
+    ItemName(TextComponent),
+    Lore(Vec<TextComponent>),
+    Rarity(i32),
+    Enchantments { enchantments: Vec<(i32, i32)>, show_in_tooltip: bool },
+    CustomModelData(i32),
+    HideAdditionalTooltip,
+    HideTooltip,
+    RepairCost(i32),
+    CreativeSlotLock,
+    EnchantmentGlintOverride(bool),
+    IntangibleProjectile(Tag),
+    FireResistant,
+    StoredEnchantments { enchantments: Vec<(i32, i32)>, show_in_tooltip: bool },
+}
+
+impl DataComponent {
+    /// Gets the registry ID of the component type.
+    pub fn id(&self) -> i32 {
+        match self {
+            DataComponent::CustomData(_) => COMPONENT_CUSTOM_DATA,
+            DataComponent::MaxStackSize(_) => COMPONENT_MAX_STACK_SIZE,
+            DataComponent::MaxDamage(_) => COMPONENT_MAX_DAMAGE,
+            DataComponent::Damage(_) => COMPONENT_DAMAGE,
+            DataComponent::Unbreakable { .. } => COMPONENT_UNBREAKABLE,
+            DataComponent::CustomName(_) => COMPONENT_CUSTOM_NAME,
+            DataComponent::ItemName(_) => COMPONENT_ITEM_NAME,
+            DataComponent::Lore(_) => COMPONENT_LORE,
+            DataComponent::Rarity(_) => COMPONENT_RARITY,
+            DataComponent::Enchantments { .. } => COMPONENT_ENCHANTMENTS,
+            DataComponent::CustomModelData(_) => COMPONENT_CUSTOM_MODEL_DATA,
+            DataComponent::HideAdditionalTooltip => COMPONENT_HIDE_ADDITIONAL_TOOLTIP,
+            DataComponent::HideTooltip => COMPONENT_HIDE_TOOLTIP,
+            DataComponent::RepairCost(_) => COMPONENT_REPAIR_COST,
+            DataComponent::CreativeSlotLock => COMPONENT_CREATIVE_SLOT_LOCK,
+            DataComponent::EnchantmentGlintOverride(_) => COMPONENT_ENCHANTMENT_GLINT_OVERRIDE,
+            DataComponent::IntangibleProjectile(_) => COMPONENT_INTANGIBLE_PROJECTILE,
+            DataComponent::FireResistant => COMPONENT_FIRE_RESISTANT,
+            DataComponent::StoredEnchantments { .. } => COMPONENT_STORED_ENCHANTMENTS,
+        }
+    }
+
+    /// Reads the data of a component of type `id`.
+    /// # Errors
+    /// This function will return a `Malformed` error if its data is malformed, with
+    /// `DataError::UnsupportedComponent` if `id` is not a type modelled by
+    /// `DataComponent`.
+    pub fn read_with_id(reader: &mut BufferedPacketReader, id: i32) -> Result<Self, McError> {
+        let component = match id {
+            COMPONENT_CUSTOM_DATA => DataComponent::CustomData(read_required_nbt(reader)?),
+            COMPONENT_MAX_STACK_SIZE => DataComponent::MaxStackSize(reader.read_varint()?),
+            COMPONENT_MAX_DAMAGE => DataComponent::MaxDamage(reader.read_varint()?),
+            COMPONENT_DAMAGE => DataComponent::Damage(reader.read_varint()?),
+            COMPONENT_UNBREAKABLE => DataComponent::Unbreakable {
+                show_in_tooltip: reader.read_bool()?,
+            },
+            COMPONENT_CUSTOM_NAME => DataComponent::CustomName(read_nbt_text(reader)?),
+            COMPONENT_ITEM_NAME => DataComponent::ItemName(read_nbt_text(reader)?),
+            COMPONENT_LORE => {
+                let len = reader.read_length()?;
+                let mut lines = Vec::new();
+                for _ in 0..len {
+                    lines.push(read_nbt_text(reader)?);
+                }
+                DataComponent::Lore(lines)
+            }
+            COMPONENT_RARITY => DataComponent::Rarity(reader.read_varint()?),
+            COMPONENT_ENCHANTMENTS | COMPONENT_STORED_ENCHANTMENTS => {
+                let len = reader.read_length()?;
+                let mut enchantments = Vec::new();
+                for _ in 0..len {
+                    enchantments.push((reader.read_varint()?, reader.read_varint()?));
+                }
+                let show_in_tooltip = reader.read_bool()?;
+                if id == COMPONENT_ENCHANTMENTS {
+                    DataComponent::Enchantments { enchantments, show_in_tooltip }
+                } else {
+                    DataComponent::StoredEnchantments { enchantments, show_in_tooltip }
+                }
+            }
+            COMPONENT_CUSTOM_MODEL_DATA => DataComponent::CustomModelData(reader.read_varint()?),
+            COMPONENT_HIDE_ADDITIONAL_TOOLTIP => DataComponent::HideAdditionalTooltip,
+            COMPONENT_HIDE_TOOLTIP => DataComponent::HideTooltip,
+            COMPONENT_REPAIR_COST => DataComponent::RepairCost(reader.read_varint()?),
+            COMPONENT_CREATIVE_SLOT_LOCK => DataComponent::CreativeSlotLock,
+            COMPONENT_ENCHANTMENT_GLINT_OVERRIDE => {
+                DataComponent::EnchantmentGlintOverride(reader.read_bool()?)
+            }
+            COMPONENT_INTANGIBLE_PROJECTILE => {
+                DataComponent::IntangibleProjectile(read_required_nbt(reader)?)
+            }
+            COMPONENT_FIRE_RESISTANT => DataComponent::FireResistant,
+            _ => return Err(McError::malformed(DataError::UnsupportedComponent { id })),
+        };
+
+        Ok(component)
+    }
+
+    /// Appends the component's type ID and data to `builder`.
+    /// # Errors
+    /// This function will return a `Malformed` error if NBT data cannot be encoded,
+    /// see `nbt::binary::write_named`.
+    pub fn write(&self, builder: PacketBytesBuilder) -> Result<PacketBytesBuilder, McError> {
+        let builder = builder.append_varint(&self.id().into());
+        let builder = match self {
+            DataComponent::CustomData(tag) | DataComponent::IntangibleProjectile(tag) => {
+                builder.try_append_network_nbt(Some(&NetworkNbt(tag.clone())))?
+            }
+            DataComponent::MaxStackSize(v)
+            | DataComponent::MaxDamage(v)
+            | DataComponent::Damage(v)
+            | DataComponent::Rarity(v)
+            | DataComponent::CustomModelData(v)
+            | DataComponent::RepairCost(v) => builder.append_varint(&(*v).into()),
+            DataComponent::Unbreakable { show_in_tooltip } => builder.append_bool(*show_in_tooltip),
+            DataComponent::CustomName(text) | DataComponent::ItemName(text) => {
+                builder.try_append_network_nbt(Some(&NetworkNbt(text.to_nbt())))?
+            }
+            DataComponent::Lore(lines) => {
+                let builder = builder.append_varint(&(lines.len() as i32).into());
+                lines.iter().try_fold(builder, |builder, line| {
+                    builder.try_append_network_nbt(Some(&NetworkNbt(line.to_nbt())))
+                })?
+            }
+            DataComponent::Enchantments { enchantments, show_in_tooltip }
+            | DataComponent::StoredEnchantments { enchantments, show_in_tooltip } => {
+                let mut builder = builder.append_varint(&(enchantments.len() as i32).into());
+                for (id, level) in enchantments {
+                    builder = builder.append_varint(&(*id).into()).append_varint(&(*level).into());
+                }
+                builder.append_bool(*show_in_tooltip)
+            }
+            DataComponent::HideAdditionalTooltip
+            | DataComponent::HideTooltip
+            | DataComponent::CreativeSlotLock
+            | DataComponent::FireResistant => builder,
+            DataComponent::EnchantmentGlintOverride(v) => builder.append_bool(*v),
+        };
+
+        Ok(builder)
+    }
+}
+
+fn read_required_nbt(reader: &mut BufferedPacketReader) -> Result<Tag, McError> {
+    binary::read_nameless(reader)?.ok_or_else(|| McError::invalid("Data component NBT is empty"))
+}
+
+fn read_nbt_text(reader: &mut BufferedPacketReader) -> Result<TextComponent, McError> {
+    TextComponent::from_nbt(&read_required_nbt(reader)?)
+}
+
+/// The data components of a structured slot: components added on top of the
+/// item's defaults, and default components removed from it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DataComponents {
+    pub added: Vec<DataComponent>,
+    pub removed: Vec<i32>,
+}
+
+impl DataComponents {
+    /// Gets the added component of type `id`.
+    pub fn get(&self, id: i32) -> Option<&DataComponent> {
+        self.added.iter().find(|c| c.id() == id)
+    }
+}
+
+/// The extra data an item stack carries besides its ID and count.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ItemData {
+    /// The NBT compound of a `Legacy` or `NamelessNbt` slot.
+    Nbt(Option<Compound>),
+    /// The data components of a `Structured` slot.
+    Components(DataComponents),
+}
+
+/// A stack of items in a slot. An empty slot is an `ItemStack` with a count of 0.
+/// <br>
+/// Data which the target `SlotFormat` cannot express (components in a legacy slot,
+/// or NBT in a structured slot) is dropped when encoding.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ItemStack {
+    pub item_id: i32,
+    pub count: i32,
+    pub data: ItemData,
+}
+
+impl ItemStack {
+    /// Creates an empty slot.
+    pub fn empty() -> Self {
+        ItemStack {
+            item_id: 0,
+            count: 0,
+            data: ItemData::Nbt(None),
+        }
+    }
+
+    /// Creates a stack of `count` items of `item_id` without extra data.
+    pub fn new(item_id: i32, count: i32) -> Self {
+        ItemStack {
+            item_id,
+            count,
+            data: ItemData::Nbt(None),
+        }
+    }
+
+    /// Returns `true` if the slot holds no items.
+    pub fn is_empty(&self) -> bool {
+        self.count <= 0
+    }
+
+    /// Reads a slot encoded in `format`.
+    /// # Errors
+    /// This function will return an error if the slot is truncated or malformed, or
+    /// holds a data component this crate cannot decode, see `DataComponent`.
+    pub fn read_with(reader: &mut BufferedPacketReader, format: SlotFormat) -> Result<Self, McError> {
+        match format {
+            SlotFormat::Legacy | SlotFormat::NamelessNbt => {
+                if !reader.read_bool()? {
+                    return Ok(ItemStack::empty());
+                }
+                let item_id = reader.read_varint()?;
+                let count = reader.read_i8()? as i32;
+                let tag = if format == SlotFormat::Legacy {
+                    binary::read_named(reader)?.map(|(_, tag)| tag)
+                } else {
+                    binary::read_nameless(reader)?
+                };
+                let nbt = match tag {
+                    Some(Tag::Compound(compound)) => Some(compound),
+                    Some(_) => return Err(McError::invalid("Slot NBT is not a compound")),
+                    None => None,
+                };
+
+                Ok(ItemStack {
+                    item_id,
+                    count,
+                    data: ItemData::Nbt(nbt),
+                })
+            }
+            SlotFormat::Structured => {
+                let count = reader.read_varint()?;
+                if count <= 0 {
+                    return Ok(ItemStack::empty());
+                }
+                let item_id = reader.read_varint()?;
+                let added_len = reader.read_length()?;
+                let removed_len = reader.read_length()?;
+
+                let mut components = DataComponents::default();
+                for _ in 0..added_len {
+                    let id = reader.read_varint()?;
+                    components.added.push(DataComponent::read_with_id(reader, id)?);
+                }
+                for _ in 0..removed_len {
+                    components.removed.push(reader.read_varint()?);
+                }
+
+                Ok(ItemStack {
+                    item_id,
+                    count,
+                    data: ItemData::Components(components),
+                })
+            }
+        }
+    }
+
+    /// Appends the slot encoded in `format` to `builder`.
+    /// # Errors
+    /// This function will return a `Malformed` error if the NBT or a data component
+    /// of the stack cannot be encoded, see `nbt::binary::write_named`.
+    pub fn write_with(&self, builder: PacketBytesBuilder, format: SlotFormat) -> Result<PacketBytesBuilder, McError> {
+        match format {
+            SlotFormat::Legacy | SlotFormat::NamelessNbt => {
+                if self.is_empty() {
+                    return Ok(builder.append_bool(false));
+                }
+                let builder = builder
+                    .append_bool(true)
+                    .append_varint(&self.item_id.into())
+                    .append_i8(self.count as i8);
+                let tag = match &self.data {
+                    ItemData::Nbt(Some(compound)) => Some(Tag::Compound(compound.clone())),
+                    _ => None,
+                };
+                match format {
+                    SlotFormat::Legacy => builder.try_append_nbt(tag.map(|tag| Nbt::new("", tag)).as_ref()),
+                    _ => builder.try_append_network_nbt(tag.map(NetworkNbt).as_ref()),
+                }
+            }
+            SlotFormat::Structured => {
+                if self.is_empty() {
+                    return Ok(builder.append_varint(&0.into()));
+                }
+                let empty = DataComponents::default();
+                let components = match &self.data {
+                    ItemData::Components(components) => components,
+                    ItemData::Nbt(_) => &empty,
+                };
+
+                let builder = builder
+                    .append_varint(&self.count.into())
+                    .append_varint(&self.item_id.into())
+                    .append_varint(&(components.added.len() as i32).into())
+                    .append_varint(&(components.removed.len() as i32).into());
+                let mut builder = components
+                    .added
+                    .iter()
+                    .try_fold(builder, |builder, component| component.write(builder))?;
+                for id in &components.removed {
+                    builder = builder.append_varint(&(*id).into());
+                }
+                Ok(builder)
+            }
+        }
+    }
+
+    /// Gets the legacy NBT compound, if the stack has one.
+    pub fn nbt(&self) -> Option<&Compound> {
+        match &self.data {
+            ItemData::Nbt(nbt) => nbt.as_ref(),
+            ItemData::Components(_) => None,
+        }
+    }
+
+    /// Gets the data components, if the stack was read from a structured slot.
+    pub fn components(&self) -> Option<&DataComponents> {
+        match &self.data {
+            ItemData::Components(components) => Some(components),
+            ItemData::Nbt(_) => None,
+        }
+    }
+
+    /// Gets the custom name given to the item, e.g. by an anvil.
+    pub fn custom_name(&self) -> Option<TextComponent> {
+        match &self.data {
+            ItemData::Nbt(_) => {
+                let name = self.nbt()?.get_compound("display")?.get_str("Name")?;
+                TextComponent::from_json_str(name).ok()
+            }
+            ItemData::Components(components) => match components.get(COMPONENT_CUSTOM_NAME) {
+                Some(DataComponent::CustomName(name)) => Some(name.clone()),
+                _ => None,
+            },
+        }
+    }
+
+    /// Gets the lore lines of the item.
+    pub fn lore(&self) -> Vec<TextComponent> {
+        match &self.data {
+            ItemData::Nbt(_) => self
+                .nbt()
+                .and_then(|nbt| nbt.get_compound("display"))
+                .and_then(|display| display.get_list("Lore"))
+                .map(|lines| {
+                    lines
+                        .iter()
+                        .filter_map(Tag::as_str)
+                        .filter_map(|line| TextComponent::from_json_str(line).ok())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            ItemData::Components(components) => match components.get(COMPONENT_LORE) {
+                Some(DataComponent::Lore(lines)) => lines.clone(),
+                _ => Vec::new(),
+            },
+        }
+    }
+
+    /// Gets the damage taken by the item, if it is damageable and damaged.
+    pub fn damage(&self) -> Option<i32> {
+        match &self.data {
+            ItemData::Nbt(_) => self.nbt()?.get_i64("Damage").map(|d| d as i32),
+            ItemData::Components(components) => match components.get(COMPONENT_DAMAGE) {
+                Some(DataComponent::Damage(damage)) => Some(*damage),
+                _ => None,
+            },
+        }
+    }
+
+    /// Gets the enchantments applied to the item. Stored enchantments of enchanted
+    /// books are not included.
+    pub fn enchantments(&self) -> Vec<Enchantment> {
+        match &self.data {
+            ItemData::Nbt(_) => self
+                .nbt()
+                .and_then(|nbt| nbt.get_list("Enchantments"))
+                .map(|list| {
+                    list.iter()
+                        .filter_map(Tag::as_compound)
+                        .filter_map(|e| {
+                            Some(Enchantment {
+                                id: EnchantmentId::Named(e.get_str("id")?.to_owned()),
+                                level: e.get_i64("lvl")? as i32,
+                            })
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+            ItemData::Components(components) => match components.get(COMPONENT_ENCHANTMENTS) {
+                Some(DataComponent::Enchantments { enchantments, .. }) => enchantments
+                    .iter()
+                    .map(|(id, level)| Enchantment {
+                        id: EnchantmentId::Registry(*id),
+                        level: *level,
+                    })
+                    .collect(),
+                _ => Vec::new(),
+            },
+        }
+    }
+}
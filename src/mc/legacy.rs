@@ -0,0 +1,182 @@
+//! The legacy server list ping, used by clients before 1.7.
+//! <https://wiki.vg/Server_List_Ping#1.6>
+//! <br>
+//! The client sends a `0xFE` byte, optionally followed by more data depending on the
+//! version, and the server answers with a kick packet, `0xFF`, followed by a string
+//! of UTF-16BE code units prefixed with its length as an unsigned short. Modern
+//! servers still answer the ping, so it works as a fallback for any server.
+
+use super::{
+    error::McError,
+    mctypes::{check_string_length, MAX_SERVER_ADDRESS_LENGTH},
+    status::{Players, ServerStatus, Version},
+    stream::MinecraftStream,
+    text::TextComponent,
+};
+
+/// The legacy ping request.
+pub const LEGACY_PING: u8 = 0xFE;
+
+/// The kick packet answering a legacy ping.
+pub const LEGACY_KICK: u8 = 0xFF;
+
+/// The channel of the plugin message sent by 1.6 clients.
+pub const PING_HOST_CHANNEL: &str = "MC|PingHost";
+
+/// The protocol version sent in the 1.6 ping, 1.6.4.
+pub const LEGACY_PROTOCOL_VERSION: u8 = 78;
+
+/// The protocol version reported for responses in the beta 1.8 to 1.3 format, which
+/// do not include a version.
+pub const UNKNOWN_LEGACY_PROTOCOL: i32 = -1;
+
+/// The request formats of the legacy ping, by the client versions which sent them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LegacyPingFormat {
+    /// `0xFE`. The response holds the MOTD and player counts, separated by `§`.
+    Beta18To13,
+    /// `0xFE 0x01`. The response starts with `§1` and also holds the server version.
+    V14To15,
+    /// `0xFE 0x01` followed by a `MC|PingHost` plugin message with the address the
+    /// client connected to, which lets virtual hosts answer. The response format is
+    /// the same as for 1.4 and 1.5.
+    V16,
+}
+
+/// Encodes the ping request in `format`. `host` and `port` are only sent in the 1.6 format.
+/// # Errors
+/// This function will return a `Malformed` error if `host` is longer than the 255
+/// characters the Handshake permits, even when the format does not send it.
+pub fn encode_request(format: LegacyPingFormat, host: &str, port: u16) -> Result<Vec<u8>, McError> {
+    // This also keeps the lengths below within a `u16`.
+    check_string_length(host, MAX_SERVER_ADDRESS_LENGTH)?;
+
+    let mut buf = vec![LEGACY_PING];
+    if format == LegacyPingFormat::Beta18To13 {
+        return Ok(buf);
+    }
+
+    buf.push(0x01);
+    if format == LegacyPingFormat::V16 {
+        let host: Vec<u16> = host.encode_utf16().collect();
+        buf.push(0xFA);
+        write_utf16(&mut buf, &PING_HOST_CHANNEL.encode_utf16().collect::<Vec<_>>());
+        // The protocol version, the host and the port.
+        buf.extend_from_slice(&(7 + 2 * host.len() as u16).to_be_bytes());
+        buf.push(LEGACY_PROTOCOL_VERSION);
+        write_utf16(&mut buf, &host);
+        buf.extend_from_slice(&(port as i32).to_be_bytes());
+    }
+    Ok(buf)
+}
+
+fn write_utf16(buf: &mut Vec<u8>, units: &[u16]) {
+    buf.extend_from_slice(&(units.len() as u16).to_be_bytes());
+    for unit in units {
+        buf.extend_from_slice(&unit.to_be_bytes());
+    }
+}
+
+/// Reads the kick packet answering a legacy ping from `stream`, and parses it.
+/// # Errors
+/// This function will return an `Io` error if the stream closes before the whole
+/// response is read, or any error `parse_response` returns.
+pub fn read_response(stream: &mut MinecraftStream) -> Result<ServerStatus, McError> {
+    let mut header = [0_u8; 3];
+    stream.read_raw(&mut header)?;
+    if header[0] != LEGACY_KICK {
+        return Err(McError::invalid(format!(
+            "Expected a legacy kick packet, but received packet {:#04x}",
+            header[0]
+        )));
+    }
+
+    let len = u16::from_be_bytes([header[1], header[2]]) as usize;
+    let mut response = vec![0_u8; 2 * len];
+    stream.read_raw(&mut response)?;
+
+    parse_response(&decode_utf16(&response)?)
+}
+
+/// Decodes a string of UTF-16BE code units.
+/// # Errors
+/// This function will return a `Malformed` error if `bytes` has an odd length or
+/// holds unpaired surrogates.
+pub fn decode_utf16(bytes: &[u8]) -> Result<String, McError> {
+    if !bytes.len().is_multiple_of(2) {
+        return Err(McError::invalid("UTF-16 string has an odd number of bytes"));
+    }
+
+    let units: Vec<u16> = bytes.chunks_exact(2).map(|unit| u16::from_be_bytes([unit[0], unit[1]])).collect();
+    String::from_utf16(&units).map_err(|e| McError::invalid(format!("Invalid UTF-16 string: {}", e)))
+}
+
+/// Parses the string of a legacy kick packet into a status, detecting its format.
+/// Responses in the beta 1.8 to 1.3 format have no version, so it is left empty with
+/// protocol `UNKNOWN_LEGACY_PROTOCOL`.
+/// # Example
+/// ```
+/// use mcclient::mc::legacy::parse_response;
+///
+/// let status = parse_response("§1\0127\01.6.4\0A Minecraft Server\03\020").unwrap();
+/// assert_eq!(status.version.name, "1.6.4");
+/// assert_eq!(status.players.unwrap().max, 20);
+/// ```
+/// # Errors
+/// This function will return a `Malformed` error if the string has too few parts or
+/// the player counts or protocol are not numbers.
+pub fn parse_response(response: &str) -> Result<ServerStatus, McError> {
+    let (version, motd, online, max) = match response.strip_prefix("\u{a7}1\0") {
+        Some(rest) => {
+            let parts: Vec<&str> = rest.split('\0').collect();
+            let [protocol, name, motd, online, max] = parts[..] else {
+                return Err(McError::invalid(format!(
+                    "Legacy ping response has {} parts instead of 5",
+                    parts.len()
+                )));
+            };
+            let version = Version {
+                name: name.to_string(),
+                protocol: parse_number(protocol, "protocol")?,
+                extra: Default::default(),
+            };
+            (version, motd, online, max)
+        }
+        None => {
+            // The MOTD comes first, so it is the only part which may hold a `§`.
+            let mut parts = response.rsplitn(3, '\u{a7}');
+            let (Some(max), Some(online), Some(motd)) = (parts.next(), parts.next(), parts.next()) else {
+                return Err(McError::invalid("Legacy ping response has fewer than 3 parts"));
+            };
+            let version = Version {
+                name: String::new(),
+                protocol: UNKNOWN_LEGACY_PROTOCOL,
+                extra: Default::default(),
+            };
+            (version, motd, online, max)
+        }
+    };
+
+    Ok(ServerStatus {
+        version,
+        players: Some(Players {
+            max: parse_number(max, "max players")?,
+            online: parse_number(online, "online players")?,
+            sample: Vec::new(),
+            extra: Default::default(),
+        }),
+        description: Some(TextComponent::from_legacy(motd)),
+        favicon: None,
+        enforces_secure_chat: None,
+        previews_chat: None,
+        modinfo: None,
+        forge_data: None,
+        extra: Default::default(),
+    })
+}
+
+fn parse_number(value: &str, name: &str) -> Result<i32, McError> {
+    value
+        .parse()
+        .map_err(|_| McError::invalid(format!("Legacy ping {} {:?} is not a number", name, value)))
+}
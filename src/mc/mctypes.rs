@@ -1,121 +1,244 @@
-use std::io;
+use std::{borrow::Cow, fmt, str::FromStr};
 
-use serde_json::Value;
+use uuid::Uuid;
+
+use super::{
+    error::{DataError, McError},
+    packet::reader::BufferedPacketReader,
+};
 
 pub trait MCType: Sized {
+    /// The encoded byte size shared by every value of this type, or `None` if
+    /// the size depends on the value. This allows sizes of fixed-width fields to
+    /// be computed without an instance.
+    const FIXED_SIZE: Option<i32> = None;
+
+    /// Encodes this `MCType` according to its Minecraft protocol packet structure,
+    /// appending the bytes to `buf`.
+    fn write_to(&self, buf: &mut Vec<u8>);
     /// Copies the data of this `MCType` and encodes it according to itso
-    /// Minecraft protocol packet structure.
-    fn to_bytes(&self) -> Vec<u8>;
-    fn from_bytes(bytes: &[u8]) -> Result<Self, io::Error>;
+    /// Minecraft protocol packet structure. Prefer `write_to` when encoding
+    /// into an existing buffer.
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.write_to(&mut bytes);
+        bytes
+    }
+    fn from_bytes(bytes: &[u8]) -> Result<Self, McError>;
+    /// Reads this `MCType` from the front of `reader`, advancing it past the bytes
+    /// consumed. By default, this decodes with `from_bytes` and advances by `size`,
+    /// so types whose re-encoded size can differ from the bytes received (e.g. JSON
+    /// text) must override it.
+    fn read_from(reader: &mut BufferedPacketReader) -> Result<Self, McError> {
+        let value = Self::from_bytes(reader.peek_remaining())?;
+        reader.read_bytes(value.size() as usize)?;
+        Ok(value)
+    }
     /// Gets the bytesize of the serialized version this `MCType`.
     /// # Examples
     /// ```
     /// use crate::mcclient::mc::mctypes::MCString;
     /// use crate::mcclient::mc::mctypes::MCType;
     /// 
-    /// let string = MCString::from("Hello!".to_owned());
+    /// let string = MCString::try_from("Hello!").unwrap();
     /// let size = string.size();
     /// // ^ returns length of "Hello!" + bytesize of `VarInt` size.
     /// // i.e., 6 + [6].len() = 7
+    /// assert_eq!(size, 7);
     /// ```
     fn size(&self) -> i32;
 }
 
+/// The maximum length of a protocol string, in UTF-16 code units, when a field
+/// does not declare a smaller one.
+pub const MAX_STRING_LENGTH: usize = 32767;
+/// The maximum length of a player username.
+pub const MAX_USERNAME_LENGTH: usize = 16;
+/// The maximum length of the server address sent in the handshake.
+pub const MAX_SERVER_ADDRESS_LENGTH: usize = 255;
+/// The maximum length of a chat message or command sent by the client.
+pub const MAX_CHAT_LENGTH: usize = 256;
+
+/// The reasons a protocol string can be rejected.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StringError {
+    /// The string holds more UTF-16 code units than the field permits.
+    TooLong { len: usize, max_len: usize },
+    /// The declared byte length is more than `max_len` characters could take.
+    TooManyBytes { bytes: usize, max_len: usize },
+    /// The string bytes are not valid UTF-8.
+    InvalidUtf8(std::str::Utf8Error),
+}
+
+impl fmt::Display for StringError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StringError::TooLong { len, max_len } => write!(
+                f,
+                "String is {} characters long, exceeding the maximum of {}",
+                len, max_len
+            ),
+            StringError::TooManyBytes { bytes, max_len } => write!(
+                f,
+                "String is {} bytes long, exceeding the maximum of {} characters",
+                bytes, max_len
+            ),
+            StringError::InvalidUtf8(e) => write!(f, "String is not valid UTF-8: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for StringError {}
+
+/// Checks that `value` is at most `max_len` UTF-16 code units long.
+pub fn check_string_length(value: &str, max_len: usize) -> Result<(), StringError> {
+    // A code unit takes 1 to 3 bytes, so most strings are decided by the byte length.
+    if value.len() <= max_len {
+        return Ok(());
+    }
+    let len = value.encode_utf16().count();
+    if len > max_len {
+        return Err(StringError::TooLong { len, max_len });
+    }
+    Ok(())
+}
+
 /// Represents a Minecraft Protocol-encoded string, i.e., a UTF-8 string prefixed
-/// with its length encoded as a `VarInt`. This structure is immutable and is only
-/// used for serialization and deserialization.
-pub struct MCString {
-    size: VarInt,
-    bytes: Vec<u8>,
-} // TODO: Find most optimal way to represent MCString
+/// with its length encoded as a `VarInt`. An `MCString` always respects the
+/// maximum length it was created or read with. One created from a `&str`, or
+/// read with `MCString::read`, borrows the string instead of copying it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MCString<'a> {
+    value: Cow<'a, str>,
+}
 
-#[allow(dead_code)]
-impl MCString {
-    /// Returns a reference to the VarInt-encoded size descriptor of a MC String.
-    pub fn size(&self) -> &VarInt {
-        &self.size
+impl<'a> MCString<'a> {
+    /// Creates a string for a field of at most `max_len` UTF-16 code units.
+    /// # Errors
+    /// This function will return `StringError::TooLong` if `value` is longer than
+    /// `max_len`.
+    pub fn new<S: Into<Cow<'a, str>>>(value: S, max_len: usize) -> Result<Self, StringError> {
+        let value = value.into();
+        check_string_length(&value, max_len.min(MAX_STRING_LENGTH))?;
+        Ok(MCString { value })
     }
 
-    /// Returns a reference to the bytes of a MC String, excluding the size descriptor.
-    pub fn bytes(&self) -> &Vec<u8> {
-        &self.bytes
+    /// Reads a string of at most `max_len` UTF-16 code units, borrowed from the
+    /// buffer of `reader`. Exactly the number of bytes declared by the length prefix
+    /// is read.
+    /// # Errors
+    /// See `BufferedPacketReader::read_str`.
+    pub fn read(reader: &mut BufferedPacketReader<'a>, max_len: usize) -> Result<Self, McError> {
+        Ok(MCString {
+            value: Cow::Borrowed(reader.read_str(max_len)?),
+        })
+    }
+
+    /// Returns the string, excluding the size descriptor.
+    pub fn as_str(&self) -> &str {
+        &self.value
+    }
+
+    /// Returns the UTF-8 bytes of the string, excluding the size descriptor.
+    pub fn bytes(&self) -> &[u8] {
+        self.value.as_bytes()
+    }
+
+    /// Gets the byte length of the string, excluding the size descriptor.
+    pub fn len(&self) -> usize {
+        self.value.len()
+    }
+
+    /// Returns `true` if the string is empty.
+    pub fn is_empty(&self) -> bool {
+        self.value.is_empty()
     }
 }
 
-impl From<String> for MCString {
-    /// Creates a Minecraft string from a `String`.
-    /// # Panics
-    /// This function will panic if the size of the String cannot
-    /// be parsed to an `i32`.
-    fn from(value: String) -> Self {
-        let size: i32 = value.len().try_into().unwrap();
-        MCString {
-            size: VarInt::from(size),
-            bytes: value.into_bytes(),
-        }
+impl TryFrom<String> for MCString<'_> {
+    type Error = StringError;
+
+    /// Creates a Minecraft string which owns `value`, limited to `MAX_STRING_LENGTH`.
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        MCString::new(value, MAX_STRING_LENGTH)
     }
 }
 
-impl Into<String> for MCString {
-    fn into(self) -> String {
-        String::from_utf8(self.bytes).unwrap()
+impl<'a> TryFrom<&'a str> for MCString<'a> {
+    type Error = StringError;
+
+    /// Creates a Minecraft string which borrows `value`, limited to `MAX_STRING_LENGTH`.
+    fn try_from(value: &'a str) -> Result<Self, Self::Error> {
+        MCString::new(value, MAX_STRING_LENGTH)
     }
 }
 
-impl From<&str> for MCString {
-    /// Creates a Minecraft string from a `&str`.
-    /// # Panics
-    /// This function will panic if the size of the String cannot
-    /// be parsed to an `i32`.
-    fn from(value: &str) -> Self {
-        let size: i32 = match value.len().try_into() {
-            Ok(num) => num,
-            Err(msg) => panic!("{}", msg),
-        };
-        MCString {
-            size: VarInt::from(size),
-            bytes: value.into(),
-        }
+impl From<MCString<'_>> for String {
+    fn from(value: MCString) -> Self {
+        value.value.into_owned()
     }
 }
 
-impl MCType for MCString {
+impl MCType for MCString<'_> {
     /// Serializes the MCString to a packet-structured array of bytes.
-    fn to_bytes(&self) -> Vec<u8> {
-        let mut bytes = Vec::<u8>::new();
-
-        bytes.append(&mut self.size.to_bytes());
-        bytes.append(&mut self.bytes.clone());
-
-        bytes
+    fn write_to(&self, buf: &mut Vec<u8>) {
+        write_str(buf, &self.value);
     }
 
     /// Attempts to create a `MCString` from a set of bytes, which should be
-    /// lead with a `VarInt` descriptor followed by a UTF-8 string.
-    /// # Panics
-    /// This function will panic if the constituent string cannot properly be
-    /// parsed as
+    /// lead with a `VarInt` descriptor followed by a UTF-8 string of at most
+    /// `MAX_STRING_LENGTH` characters. Only the number of bytes declared by the
+    /// descriptor is read.
     /// # Errors
     /// This function will error in the instance that the `VarInt` header cannot
-    /// be parsed.
-    fn from_bytes(mut bytes: &[u8]) -> Result<Self, io::Error> {
-        let size = VarInt::from_bytes(bytes)?;
-        bytes = &bytes[size.len() as usize..];
-
-        let string_res = String::from_utf8(bytes.to_vec());
-        if string_res.is_err() {
-            Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                string_res.err().unwrap(),
-            ))
-        } else {
-            let string = string_res.unwrap();
-            Ok(MCString { size, bytes: string.into_bytes() })
-        }
+    /// be parsed, fewer bytes than declared remain, the string is too long or it
+    /// is not valid UTF-8.
+    fn from_bytes(bytes: &[u8]) -> Result<Self, McError> {
+        Self::read_from(&mut BufferedPacketReader::new(bytes))
+    }
+
+    fn read_from(reader: &mut BufferedPacketReader) -> Result<Self, McError> {
+        Ok(MCString {
+            value: Cow::Owned(reader.read_string(MAX_STRING_LENGTH)?),
+        })
+    }
+
+    fn size(&self) -> i32 {
+        str_size(&self.value)
+    }
+}
+
+/// Appends `value` prefixed with its byte length. The length of a string which
+/// passed `check_string_length` always fits in a `VarInt`.
+pub(crate) fn write_str(buf: &mut Vec<u8>, value: &str) {
+    VarInt::from(value.len() as i32).write_to(buf);
+    buf.extend_from_slice(value.as_bytes());
+}
+
+/// Computes the encoded size of `value` as a protocol string.
+pub(crate) fn str_size(value: &str) -> i32 {
+    let len = value.len() as i32;
+    VarInt::encoded_len(len) + len
+}
+
+impl MCType for String {
+    /// Encodes the string like `MCString`.
+    fn write_to(&self, buf: &mut Vec<u8>) {
+        write_str(buf, self);
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, McError> {
+        Self::read_from(&mut BufferedPacketReader::new(bytes))
+    }
+
+    /// Reads a string of at most `MAX_STRING_LENGTH` code units. Use
+    /// `BufferedPacketReader::read_string` for fields with a smaller limit.
+    fn read_from(reader: &mut BufferedPacketReader) -> Result<Self, McError> {
+        reader.read_string(MAX_STRING_LENGTH)
     }
 
     fn size(&self) -> i32 {
-        self.size.len() + TryInto::<i32>::try_into(self.bytes.len()).unwrap()
+        str_size(self)
     }
 }
 
@@ -124,11 +247,13 @@ impl MCType for MCString {
 /// between 1 and 5 bytes. <https://wiki.vg/Protocol#VarInt_and_VarLong>
 /// <br>
 /// This structure is meant purely for data I/O and should not be used
-/// to perform any sort of arithmetic.
-#[derive(Clone)]
+/// to perform any sort of arithmetic. The bytes are stored inline, so
+/// creating a `VarInt` does not allocate.
+#[derive(Clone, Copy)]
 #[allow(dead_code)]
 pub struct VarInt {
-    bytes: Vec<u8>,
+    bytes: [u8; 5],
+    len: u8,
 }
 // Places to remove VarInt dependency:
 //  - stream.rs (MinecraftStream::read())
@@ -137,56 +262,31 @@ pub struct VarInt {
 impl From<i32> for VarInt {
     /// Creates a `VarInt` representation of `value`.
     fn from(value: i32) -> Self {
-        VarInt {
-            bytes: to_varint(value),
-        }
+        VarInt::from_i32(value)
     }
 }
 
-impl From<&[u8]> for VarInt {
-    /// Creates a `VarInt` from a slice `&[u8]` whose leading bytes represent
-    /// a `VarInt` string between 1 and 5 bytes. Because it is expected that the
-    /// data being passed to `from_bytes` is a slice of a Minecraft packet, and
-    /// the size of a `VarInt` is variadic, a slice larger than the encompassed
-    /// number can be passed without unexpected error.
-    ///
-    /// # Panics
-    ///
-    /// The parsing of the leading bytes to a `VarInt` will panic if the number
-    /// is evaluated to greater than 5 bytes in size. This can be caused by
-    /// either the wrong data type being read or the bytes being badly formatted.
-    fn from(bytes: &[u8]) -> Self {
-        let val = match from_varint_bytes(bytes) {
-            Ok(t) => t,
-            Err(msg) => panic!("{}", msg),
-        };
-        VarInt {
-            bytes: VarInt::from_i32(val).to_bytes(),
-        }
+impl TryFrom<&[u8]> for VarInt {
+    type Error = McError;
+
+    /// Creates a `VarInt` from the leading bytes of `bytes`. See `VarInt::from_bytes`.
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        VarInt::from_bytes(bytes)
     }
 }
 
 impl MCType for VarInt {
-    fn to_bytes(&self) -> Vec<u8> {
-        let mut bytes = Vec::<u8>::new();
-
-        bytes.append(&mut self.bytes.clone());
-
-        bytes
+    fn write_to(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(self.bytes());
     }
 
-    /// Similar to VarInt::from for From<&[u8]>, however this is recommended
-    /// as it returns an error instead of panicking.
-    fn from_bytes(bytes: &[u8]) -> Result<Self, io::Error> {
-        let val = from_varint_bytes(bytes)?;
-
-        Ok(VarInt {
-            bytes: VarInt::from_i32(val).to_bytes(),
-        })
+    /// See `VarInt::from_bytes`.
+    fn from_bytes(bytes: &[u8]) -> Result<Self, McError> {
+        VarInt::from_bytes(bytes)
     }
 
     fn size(&self) -> i32 {
-        self.bytes.len().try_into().unwrap()
+        self.len as i32
     }
 }
 
@@ -196,7 +296,7 @@ impl VarInt {
     /// # Errors
     /// This function may error if the head of the vector cannot represent a
     /// `VarInt` type.
-    pub fn from_vec_front(vec: &mut Vec<u8>) -> Result<Self, io::Error> {
+    pub fn from_vec_front(vec: &mut Vec<u8>) -> Result<Self, McError> {
         let v = VarInt::from_bytes(vec.as_slice())?;
         vec.drain(0..v.len() as usize);
         Ok(v)
@@ -204,9 +304,9 @@ impl VarInt {
 
     /// Creates a `VarInt` representation of `value`.
     pub fn from_i32(value: i32) -> Self {
-        VarInt {
-            bytes: to_varint(value),
-        }
+        let mut bytes = [0; 5];
+        let len = to_varint(value, &mut bytes);
+        VarInt { bytes, len: len as u8 }
     }
 
     /// Creates a `VarInt` from a slice `&[u8]` whose leading bytes represent
@@ -217,24 +317,41 @@ impl VarInt {
     ///
     /// # Errors
     ///
-    /// The parsing of the leading bytes to a `VarInt` will return an `InvalidData` error
+    /// The parsing of the leading bytes to a `VarInt` will return a `Malformed` error
     /// if the number is evaluated to greater than 5 bytes in size. This can be caused
     /// by either the wrong data type being read or the bytes being badly formatted.
-    pub fn from_bytes(bytes: &[u8]) -> Result<Self, io::Error> {
-        let val = from_varint_bytes(bytes)?;
-        Ok(VarInt {
-            bytes: VarInt::from_i32(val).to_bytes(),
-        })
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, McError> {
+        let (_, len) = from_varint_bytes(bytes)?;
+        let mut varint = VarInt { bytes: [0; 5], len: len as u8 };
+        varint.bytes[..len].copy_from_slice(&bytes[..len]);
+        Ok(varint)
+    }
+
+    /// Computes the number of bytes `value` takes once encoded, without encoding it.
+    pub fn encoded_len(value: i32) -> i32 {
+        match value as u32 {
+            0..=0x7F => 1,
+            0x80..=0x3FFF => 2,
+            0x4000..=0x1F_FFFF => 3,
+            0x20_0000..=0xFFF_FFFF => 4,
+            _ => 5,
+        }
     }
 
     /// Retrieves the byte size of the `VarInt`.
     pub fn len(&self) -> i32 {
-        self.bytes.len() as i32
+        self.len as i32
+    }
+
+    /// Returns `true` if this `VarInt` holds no bytes. An encoded `VarInt` is
+    /// always at least one byte long, so this only exists for API parity with `len`.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
     }
 
     /// Returns a slice of this `VarInt`'s byte array representation.
     pub fn bytes(&self) -> &[u8] {
-        &self.bytes
+        &self.bytes[..self.len as usize]
     }
 
     /// Sets the value of this `VarInt` to represent the `value` passed. This function
@@ -242,91 +359,776 @@ impl VarInt {
     /// is not favorable.
     #[allow(unused)]
     pub fn set(&mut self, value: i32) {
-        self.bytes = to_varint(value);
+        *self = VarInt::from_i32(value);
     }
 }
 
-impl Into<i32> for VarInt {
-    fn into(self) -> i32 {
-        from_varint_bytes(&self.bytes).unwrap()
+impl From<VarInt> for i32 {
+    /// Decodes the value of `value`. A `VarInt` can only be constructed from a
+    /// complete encoding of at most 5 bytes, so this cannot fail.
+    fn from(value: VarInt) -> Self {
+        value.bytes().iter().rev().fold(0, |acc, b| (acc << 7) | (b & 0x7F) as i32)
     }
 }
 
-/// Parses a VarInt from the front of the provided iterator range.
+/// Parses a VarInt from the front of the provided slice.
 /// # Returns
-/// A pair containing the parsed i32 value, and the range excluding the parsed VarInt.
+/// A pair containing the parsed i32 value, and the number of bytes it took.
 /// # Note
 /// This is meant to be used internally.
-fn from_varint_bytes(bytes: &[u8]) -> Result<i32, io::Error> {
+fn from_varint_bytes(bytes: &[u8]) -> Result<(i32, usize), McError> {
     let mut value = 0;
     let mut pos = 0;
 
     const SEGMENT_BITS: i32 = 0x7F;
     const CONTINUE_BIT: i32 = 0x80;
 
-    for b in bytes.iter() {
+    for (i, b) in bytes.iter().enumerate() {
         value |= ((*b as i32) & SEGMENT_BITS) << pos;
 
         if (*b as i32) & CONTINUE_BIT == 0 {
-            break;
+            return Ok((value, i + 1));
         }
 
         pos += 7;
 
         if pos >= 32 {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "VarInt descriptor exceeds >5 bytes",
-            ));
+            return Err(McError::invalid("VarInt descriptor exceeds >5 bytes"));
         }
     }
 
-    Ok(value)
+    Err(truncated(bytes))
 }
 
-/// Parses an i32 to a serialized VarInt byte array.
+/// Serializes an i32 into `bytes` as a VarInt.
 /// # Returns
-/// The serialized VarInt array.
+/// The number of bytes written.
 /// # Note
 /// This is meant to be used internally.
-fn to_varint(mut value: i32) -> Vec<u8> {
-    let mut bytes = Vec::<u8>::new();
-
+fn to_varint(mut value: i32, bytes: &mut [u8; 5]) -> usize {
     const SEGMENT_BITS: i32 = 0x7F;
     const CONTINUE_BIT: i32 = 0x80;
 
+    let mut len = 0;
     loop {
         if (value & !SEGMENT_BITS) == 0 {
-            bytes.push(value as u8);
-            break;
+            bytes[len] = value as u8;
+            return len + 1;
         }
 
-        bytes.push(((value & SEGMENT_BITS) | CONTINUE_BIT) as u8);
+        bytes[len] = ((value & SEGMENT_BITS) | CONTINUE_BIT) as u8;
+        len += 1;
 
         // https://stackoverflow.com/a/70212287
         value = ((value as u32) >> 7) as i32;
     }
+}
+
+/// A `VarLong` is a variable-length data type encoding a two's
+/// complement signed 64-bit integer. A `VarLong` can be anywhere
+/// between 1 and 10 bytes. <https://wiki.vg/Protocol#VarInt_and_VarLong>
+/// <br>
+/// Like `VarInt`, this structure is meant purely for data I/O and does
+/// not allocate.
+#[derive(Clone, Copy)]
+pub struct VarLong {
+    bytes: [u8; 10],
+    len: u8,
+}
+
+impl From<i64> for VarLong {
+    /// Creates a `VarLong` representation of `value`.
+    fn from(value: i64) -> Self {
+        let mut bytes = [0; 10];
+        let len = to_varlong(value, &mut bytes);
+        VarLong { bytes, len: len as u8 }
+    }
+}
+
+impl From<VarLong> for i64 {
+    /// Decodes the value of `value`. See `From<VarInt> for i32`.
+    fn from(value: VarLong) -> Self {
+        value.bytes().iter().rev().fold(0, |acc, b| (acc << 7) | (b & 0x7F) as i64)
+    }
+}
+
+impl MCType for VarLong {
+    fn write_to(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(self.bytes());
+    }
+
+    /// Parses the leading bytes of `bytes` as a `VarLong`.
+    /// # Errors
+    /// This function will return a `Malformed` error if the `VarLong` is longer
+    /// than 10 bytes.
+    fn from_bytes(bytes: &[u8]) -> Result<Self, McError> {
+        let (_, len) = from_varlong_bytes(bytes)?;
+        let mut varlong = VarLong { bytes: [0; 10], len: len as u8 };
+        varlong.bytes[..len].copy_from_slice(&bytes[..len]);
+        Ok(varlong)
+    }
+
+    fn size(&self) -> i32 {
+        self.len as i32
+    }
+}
+
+impl VarLong {
+    /// Creates a `VarLong` representation of `value`.
+    pub fn from_i64(value: i64) -> Self {
+        VarLong::from(value)
+    }
+
+    /// Computes the number of bytes `value` takes once encoded, without encoding it.
+    pub fn encoded_len(value: i64) -> i32 {
+        let bits = 64 - (value as u64).leading_zeros() as i32;
+        1.max((bits + 6) / 7)
+    }
+
+    /// Retrieves the byte size of the `VarLong`.
+    pub fn len(&self) -> i32 {
+        self.len as i32
+    }
+
+    /// Returns `true` if this `VarLong` holds no bytes. See `VarInt::is_empty`.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns a slice of this `VarLong`'s byte array representation.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+}
+
+/// Parses a VarLong from the front of the provided slice.
+/// # Returns
+/// A pair containing the parsed i64 value, and the number of bytes it took.
+/// # Note
+/// This is meant to be used internally.
+fn from_varlong_bytes(bytes: &[u8]) -> Result<(i64, usize), McError> {
+    let mut value = 0;
+    let mut pos = 0;
+
+    for (i, b) in bytes.iter().enumerate() {
+        value |= ((*b as i64) & 0x7F) << pos;
+
+        if (*b as i64) & 0x80 == 0 {
+            return Ok((value, i + 1));
+        }
+
+        pos += 7;
+
+        if pos >= 64 {
+            return Err(McError::invalid("VarLong descriptor exceeds >10 bytes"));
+        }
+    }
+
+    Err(truncated(bytes))
+}
+
+/// Describes a variable-length number whose final byte is missing from `bytes`.
+fn truncated(bytes: &[u8]) -> McError {
+    McError::malformed(DataError::Truncated {
+        needed: bytes.len() + 1,
+        remaining: bytes.len(),
+        position: 0,
+    })
+}
+
+/// Serializes an i64 into `bytes` as a VarLong.
+/// # Returns
+/// The number of bytes written.
+/// # Note
+/// This is meant to be used internally.
+fn to_varlong(mut value: i64, bytes: &mut [u8; 10]) -> usize {
+    let mut len = 0;
+    loop {
+        if (value & !0x7F) == 0 {
+            bytes[len] = value as u8;
+            return len + 1;
+        }
+
+        bytes[len] = ((value & 0x7F) | 0x80) as u8;
+        len += 1;
+        value = ((value as u64) >> 7) as i64;
+    }
+}
+
+/// A signed 8-bit integer. <https://wiki.vg/Protocol#Data_types>
+pub type Byte = i8;
+/// An unsigned 8-bit integer.
+pub type UByte = u8;
+/// A signed 16-bit integer, encoded in Big Endian.
+pub type Short = i16;
+/// An unsigned 16-bit integer, encoded in Big Endian.
+pub type UShort = u16;
+/// A signed 32-bit integer, encoded in Big Endian.
+pub type Int = i32;
+/// A signed 64-bit integer, encoded in Big Endian.
+pub type Long = i64;
+/// A single-precision IEEE 754 float, encoded in Big Endian.
+pub type Float = f32;
+/// A double-precision IEEE 754 float, encoded in Big Endian.
+pub type Double = f64;
+
+/// Implements `MCType` for a fixed-width Rust primitive using its Big Endian
+/// byte representation.
+macro_rules! fixed_width_mctype {
+    ($($t:ty => $read:ident),* $(,)?) => {
+        $(
+            impl MCType for $t {
+                const FIXED_SIZE: Option<i32> = Some(std::mem::size_of::<$t>() as i32);
+
+                fn write_to(&self, buf: &mut Vec<u8>) {
+                    buf.extend_from_slice(&self.to_be_bytes());
+                }
+
+                fn from_bytes(bytes: &[u8]) -> Result<Self, McError> {
+                    BufferedPacketReader::new(bytes).$read()
+                }
+
+                fn size(&self) -> i32 {
+                    std::mem::size_of::<$t>() as i32
+                }
+            }
+        )*
+    };
+}
+
+fixed_width_mctype! {
+    i8 => read_i8,
+    u8 => read_u8,
+    i16 => read_i16,
+    u16 => read_u16,
+    i32 => read_i32,
+    i64 => read_i64,
+    f32 => read_f32,
+    f64 => read_f64,
+}
+
+impl MCType for bool {
+    const FIXED_SIZE: Option<i32> = Some(1);
+
+    fn write_to(&self, buf: &mut Vec<u8>) {
+        buf.push(*self as u8);
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, McError> {
+        BufferedPacketReader::new(bytes).read_bool()
+    }
+
+    fn size(&self) -> i32 {
+        1
+    }
+}
+
+impl MCType for Uuid {
+    const FIXED_SIZE: Option<i32> = Some(16);
+
+    /// Encodes the UUID as a 128-bit Big Endian integer.
+    fn write_to(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(self.as_bytes());
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, McError> {
+        BufferedPacketReader::new(bytes).read_uuid()
+    }
+
+    fn size(&self) -> i32 {
+        16
+    }
+}
+
+/// An array prefixed with its element count as a `VarInt`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PrefixedArray<T>(pub Vec<T>);
+
+impl<T> From<Vec<T>> for PrefixedArray<T> {
+    fn from(elements: Vec<T>) -> Self {
+        PrefixedArray(elements)
+    }
+}
+
+impl<T> From<PrefixedArray<T>> for Vec<T> {
+    fn from(array: PrefixedArray<T>) -> Self {
+        array.0
+    }
+}
+
+impl<T: MCType> MCType for PrefixedArray<T> {
+    fn write_to(&self, buf: &mut Vec<u8>) {
+        VarInt::from(self.0.len() as i32).write_to(buf);
+        for element in &self.0 {
+            element.write_to(buf);
+        }
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, McError> {
+        Self::read_from(&mut BufferedPacketReader::new(bytes))
+    }
+
+    fn read_from(reader: &mut BufferedPacketReader) -> Result<Self, McError> {
+        let len = reader.read_length()?;
+        // Every element takes at least one byte, which bounds a forged count.
+        let mut elements = Vec::with_capacity(len.min(reader.remaining()));
+        for _ in 0..len {
+            elements.push(reader.read()?);
+        }
+        Ok(PrefixedArray(elements))
+    }
+
+    fn size(&self) -> i32 {
+        let len = self.0.len() as i32;
+        match T::FIXED_SIZE {
+            Some(size) => VarInt::encoded_len(len) + len * size,
+            None => VarInt::encoded_len(len) + self.0.iter().map(T::size).sum::<i32>(),
+        }
+    }
+}
+
+/// An optional value prefixed with a boolean which is `true` if it is present.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PrefixedOption<T>(pub Option<T>);
+
+impl<T> From<Option<T>> for PrefixedOption<T> {
+    fn from(value: Option<T>) -> Self {
+        PrefixedOption(value)
+    }
+}
+
+impl<T> From<PrefixedOption<T>> for Option<T> {
+    fn from(value: PrefixedOption<T>) -> Self {
+        value.0
+    }
+}
+
+impl<T: MCType> MCType for PrefixedOption<T> {
+    fn write_to(&self, buf: &mut Vec<u8>) {
+        buf.push(self.0.is_some() as u8);
+        if let Some(value) = &self.0 {
+            value.write_to(buf);
+        }
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, McError> {
+        Self::read_from(&mut BufferedPacketReader::new(bytes))
+    }
+
+    fn read_from(reader: &mut BufferedPacketReader) -> Result<Self, McError> {
+        match reader.read_bool()? {
+            true => Ok(PrefixedOption(Some(reader.read()?))),
+            false => Ok(PrefixedOption(None)),
+        }
+    }
+
+    fn size(&self) -> i32 {
+        1 + self.0.as_ref().map_or(0, T::size)
+    }
+}
 
-    bytes
+/// A variable-length set of bits, encoded as a `VarInt`-prefixed array of longs.
+/// Bit `i` is bit `i % 64` of long `i / 64`. <https://wiki.vg/Protocol#BitSet>
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BitSet {
+    words: Vec<i64>,
 }
 
-#[derive(Debug)]
-#[allow(unused)]
-pub struct JsonResponse {
-    data: Value,
+impl BitSet {
+    /// Creates an empty `BitSet`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a `BitSet` from its encoded longs.
+    pub fn from_words(words: Vec<i64>) -> Self {
+        BitSet { words }
+    }
+
+    /// Returns the longs backing the set.
+    pub fn words(&self) -> &[i64] {
+        &self.words
+    }
+
+    /// Returns `true` if bit `index` is set.
+    pub fn get(&self, index: usize) -> bool {
+        self.words
+            .get(index / 64)
+            .is_some_and(|word| word & (1 << (index % 64)) != 0)
+    }
+
+    /// Sets bit `index` to `value`, growing the set if needed.
+    pub fn set(&mut self, index: usize, value: bool) {
+        let word = index / 64;
+        if word >= self.words.len() {
+            if !value {
+                return;
+            }
+            self.words.resize(word + 1, 0);
+        }
+        if value {
+            self.words[word] |= 1 << (index % 64);
+        } else {
+            self.words[word] &= !(1 << (index % 64));
+        }
+    }
 }
 
-impl JsonResponse {
-    // TODO: Optimize this. Lots of dirty copying goes on here, and it isn't just this function.
-    pub fn from_bytes(bytes: &[u8]) -> Result<Self, io::Error> {
-        //let value: Value = serde_json::from_slice(MCString::from_bytes(bytes)?.bytes())?;
+impl MCType for BitSet {
+    fn write_to(&self, buf: &mut Vec<u8>) {
+        VarInt::from(self.words.len() as i32).write_to(buf);
+        for word in &self.words {
+            buf.extend_from_slice(&word.to_be_bytes());
+        }
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, McError> {
+        Self::read_from(&mut BufferedPacketReader::new(bytes))
+    }
+
+    fn read_from(reader: &mut BufferedPacketReader) -> Result<Self, McError> {
+        let PrefixedArray(words) = reader.read::<PrefixedArray<i64>>()?;
+        Ok(BitSet { words })
+    }
 
-        // Optimized. Avoids using MCString which unnecessarily copies. The fact that I have to
-        // comment this is pretty telling, but I'm not really sure where I would put this.
-        let string_size = VarInt::from_bytes(bytes)?;
-        let bytes_excluding_size_prefix = &bytes[string_size.len() as usize..];
-        let value: Value = serde_json::from_slice(bytes_excluding_size_prefix)?;
+    fn size(&self) -> i32 {
+        VarInt::encoded_len(self.words.len() as i32) + 8 * self.words.len() as i32
+    }
+}
 
-        Ok(JsonResponse { data: value })
+/// A set of exactly `N` bits, encoded as `ceil(N / 8)` bytes without a length prefix.
+/// Bit `i` is bit `i % 8` of byte `i / 8`. <https://wiki.vg/Protocol#Fixed_BitSet>
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FixedBitSet<const N: usize> {
+    bytes: Vec<u8>,
+}
+
+impl<const N: usize> Default for FixedBitSet<N> {
+    fn default() -> Self {
+        FixedBitSet {
+            bytes: vec![0; N.div_ceil(8)],
+        }
+    }
+}
+
+impl<const N: usize> FixedBitSet<N> {
+    /// Creates a `FixedBitSet` with every bit cleared.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the encoded bytes of the set.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Returns `true` if bit `index` is set.
+    /// # Panics
+    /// This function will panic if `index` is not less than `N`.
+    pub fn get(&self, index: usize) -> bool {
+        assert!(index < N, "bit {} is out of range for a FixedBitSet<{}>", index, N);
+        self.bytes[index / 8] & (1 << (index % 8)) != 0
+    }
+
+    /// Sets bit `index` to `value`.
+    /// # Panics
+    /// This function will panic if `index` is not less than `N`.
+    pub fn set(&mut self, index: usize, value: bool) {
+        assert!(index < N, "bit {} is out of range for a FixedBitSet<{}>", index, N);
+        if value {
+            self.bytes[index / 8] |= 1 << (index % 8);
+        } else {
+            self.bytes[index / 8] &= !(1 << (index % 8));
+        }
+    }
+}
+
+impl<const N: usize> MCType for FixedBitSet<N> {
+    const FIXED_SIZE: Option<i32> = Some(N.div_ceil(8) as i32);
+
+    fn write_to(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.bytes);
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, McError> {
+        Self::read_from(&mut BufferedPacketReader::new(bytes))
+    }
+
+    fn read_from(reader: &mut BufferedPacketReader) -> Result<Self, McError> {
+        Ok(FixedBitSet {
+            bytes: reader.read_bytes(N.div_ceil(8))?.to_vec(),
+        })
+    }
+
+    fn size(&self) -> i32 {
+        N.div_ceil(8) as i32
+    }
+}
+
+/// The maximum length of an `Identifier`, in UTF-16 code units.
+pub const MAX_IDENTIFIER_LENGTH: usize = MAX_STRING_LENGTH;
+
+/// A namespaced location such as `minecraft:stone`, used to name registry
+/// entries, plugin channels, sounds and dimensions. The namespace defaults to
+/// `minecraft` when omitted. <https://wiki.vg/Protocol#Identifier>
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Identifier {
+    namespace: String,
+    path: String,
+}
+
+impl Identifier {
+    pub const DEFAULT_NAMESPACE: &'static str = "minecraft";
+
+    /// Creates an identifier from its namespace and path.
+    /// # Errors
+    /// This function will return a `Malformed` error if the namespace holds a
+    /// character other than `a-z0-9_.-`, the path one other than `a-z0-9_.-/`, or
+    /// the identifier is longer than `MAX_IDENTIFIER_LENGTH`.
+    pub fn new<N: Into<String>, P: Into<String>>(namespace: N, path: P) -> Result<Self, McError> {
+        let (namespace, path) = (namespace.into(), path.into());
+        if let Some(c) = namespace.chars().find(|c| !is_namespace_char(*c)) {
+            return Err(invalid_identifier(&namespace, &path, c));
+        }
+        if let Some(c) = path.chars().find(|c| *c != '/' && !is_namespace_char(*c)) {
+            return Err(invalid_identifier(&namespace, &path, c));
+        }
+        if namespace.len() + 1 + path.len() > MAX_IDENTIFIER_LENGTH {
+            return Err(McError::invalid(format!(
+                "Identifier exceeds the maximum length of {}",
+                MAX_IDENTIFIER_LENGTH
+            )));
+        }
+
+        Ok(Identifier { namespace, path })
+    }
+
+    /// Creates an identifier in the `minecraft` namespace. See `Identifier::new`.
+    pub fn minecraft<P: Into<String>>(path: P) -> Result<Self, McError> {
+        Identifier::new(Self::DEFAULT_NAMESPACE, path)
+    }
+
+    /// Parses `namespace:path`, or a lone `path` in the `minecraft` namespace.
+    /// See `Identifier::new`.
+    pub fn parse(s: &str) -> Result<Self, McError> {
+        match s.split_once(':') {
+            Some(("", path)) => Identifier::minecraft(path),
+            Some((namespace, path)) => Identifier::new(namespace, path),
+            None => Identifier::minecraft(s),
+        }
+    }
+
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+fn is_namespace_char(c: char) -> bool {
+    matches!(c, 'a'..='z' | '0'..='9' | '_' | '.' | '-')
+}
+
+fn invalid_identifier(namespace: &str, path: &str, c: char) -> McError {
+    McError::invalid(format!("Invalid character {:?} in identifier {}:{}", c, namespace, path))
+}
+
+impl fmt::Display for Identifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.namespace, self.path)
+    }
+}
+
+impl FromStr for Identifier {
+    type Err = McError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Identifier::parse(s)
+    }
+}
+
+impl TryFrom<&str> for Identifier {
+    type Error = McError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        Identifier::parse(s)
+    }
+}
+
+impl MCType for Identifier {
+    /// Encodes the identifier as a string, always including the namespace. The
+    /// length was checked against `MAX_IDENTIFIER_LENGTH` on creation.
+    fn write_to(&self, buf: &mut Vec<u8>) {
+        let len = (self.namespace.len() + 1 + self.path.len()) as i32;
+        VarInt::from(len).write_to(buf);
+        buf.extend_from_slice(self.namespace.as_bytes());
+        buf.push(b':');
+        buf.extend_from_slice(self.path.as_bytes());
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, McError> {
+        Self::read_from(&mut BufferedPacketReader::new(bytes))
+    }
+
+    fn read_from(reader: &mut BufferedPacketReader) -> Result<Self, McError> {
+        Identifier::parse(&reader.read_string(MAX_IDENTIFIER_LENGTH)?)
+    }
+
+    fn size(&self) -> i32 {
+        let len = (self.namespace.len() + 1 + self.path.len()) as i32;
+        VarInt::encoded_len(len) + len
+    }
+}
+
+/// A rotation angle in steps of 1/256 of a full turn, encoded as a single byte.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Angle(pub u8);
+
+impl Angle {
+    /// Creates the closest `Angle` to `degrees`. Angles wrap around, so `-90.0`
+    /// and `270.0` produce the same value.
+    pub fn from_degrees(degrees: f32) -> Self {
+        Angle((degrees / 360.0 * 256.0).round().rem_euclid(256.0) as u8)
+    }
+
+    /// Gets the angle in degrees, in the range `[0, 360)`.
+    pub fn degrees(&self) -> f32 {
+        self.0 as f32 * 360.0 / 256.0
+    }
+}
+
+impl MCType for Angle {
+    const FIXED_SIZE: Option<i32> = Some(1);
+
+    fn write_to(&self, buf: &mut Vec<u8>) {
+        buf.push(self.0);
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, McError> {
+        Ok(Angle(BufferedPacketReader::new(bytes).read_u8()?))
+    }
+
+    fn size(&self) -> i32 {
+        1
+    }
+}
+
+/// A block position packed into a 64-bit integer: x as a signed 26-bit
+/// integer in the most significant bits, followed by z (26 bits) and y (12 bits).
+/// <https://wiki.vg/Protocol#Position>
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Position {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl Position {
+    /// Creates a position from its block coordinates.
+    pub fn new(x: i32, y: i32, z: i32) -> Self {
+        Position { x, y, z }
+    }
+
+    /// Packs the position into its 64-bit protocol representation. Coordinates
+    /// outside the 26/12-bit ranges are truncated.
+    pub fn to_packed(&self) -> i64 {
+        ((self.x as i64 & 0x3FF_FFFF) << 38) | ((self.z as i64 & 0x3FF_FFFF) << 12) | (self.y as i64 & 0xFFF)
+    }
+
+    /// Unpacks a position from its 64-bit protocol representation.
+    pub fn from_packed(value: i64) -> Self {
+        Position {
+            x: (value >> 38) as i32,
+            y: ((value << 52) >> 52) as i32,
+            z: ((value << 26) >> 38) as i32,
+        }
+    }
+}
+
+impl MCType for Position {
+    const FIXED_SIZE: Option<i32> = Some(8);
+
+    fn write_to(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_packed().to_be_bytes());
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, McError> {
+        Ok(Position::from_packed(BufferedPacketReader::new(bytes).read_i64()?))
+    }
+
+    fn size(&self) -> i32 {
+        8
+    }
+}
+
+/// The number of fractional bits used by legacy fixed-point coordinates.
+const FIXED_POINT_FRACTION_BITS: u32 = 5;
+
+/// A fixed-point number with 5 fractional bits stored in an `Int`, used for
+/// entity coordinates by protocol versions before 1.9.
+/// <https://wiki.vg/Data_types#Fixed-point_numbers>
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FixedPointInt(pub i32);
+
+impl FixedPointInt {
+    /// Creates the closest fixed-point representation of `value`.
+    pub fn from_f64(value: f64) -> Self {
+        FixedPointInt((value * (1 << FIXED_POINT_FRACTION_BITS) as f64).round() as i32)
+    }
+
+    /// Gets the value as a float.
+    pub fn to_f64(&self) -> f64 {
+        self.0 as f64 / (1 << FIXED_POINT_FRACTION_BITS) as f64
+    }
+}
+
+impl MCType for FixedPointInt {
+    const FIXED_SIZE: Option<i32> = Some(4);
+
+    fn write_to(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.0.to_be_bytes());
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, McError> {
+        Ok(FixedPointInt(BufferedPacketReader::new(bytes).read_i32()?))
+    }
+
+    fn size(&self) -> i32 {
+        4
+    }
+}
+
+/// A fixed-point number with 5 fractional bits stored in a `Byte`, used for
+/// relative entity movement by protocol versions before 1.9.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FixedPointByte(pub i8);
+
+impl FixedPointByte {
+    /// Creates the closest fixed-point representation of `value`.
+    pub fn from_f64(value: f64) -> Self {
+        FixedPointByte((value * (1 << FIXED_POINT_FRACTION_BITS) as f64).round() as i8)
+    }
+
+    /// Gets the value as a float.
+    pub fn to_f64(&self) -> f64 {
+        self.0 as f64 / (1 << FIXED_POINT_FRACTION_BITS) as f64
+    }
+}
+
+impl MCType for FixedPointByte {
+    const FIXED_SIZE: Option<i32> = Some(1);
+
+    fn write_to(&self, buf: &mut Vec<u8>) {
+        buf.push(self.0 as u8);
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, McError> {
+        Ok(FixedPointByte(BufferedPacketReader::new(bytes).read_i8()?))
+    }
+
+    fn size(&self) -> i32 {
+        1
     }
 }
@@ -0,0 +1,875 @@
+//! Entity metadata, sent in Set Entity Metadata. <https://wiki.vg/Entity_metadata>
+//! <br>
+//! Metadata is a list of entries, each the index of one of the entity's data fields,
+//! the ID of the serializer used for its value, and the value, terminated by index
+//! `0xFF`. Serializer IDs were renumbered whenever a serializer was added, so they
+//! are looked up in the table of the protocol version, see `MetadataType::from_id`.
+
+use std::collections::BTreeMap;
+
+use uuid::Uuid;
+
+use super::{
+    error::McError,
+    item::{ItemStack, SlotFormat},
+    mctypes::{Identifier, Position},
+    nbt::{self, binary, Nbt, Tag},
+    packet::{builder::PacketBytesBuilder, reader::BufferedPacketReader},
+    text::TextComponent,
+};
+
+/// The index which terminates a metadata list.
+pub const METADATA_END: u8 = 0xFF;
+
+/// The serializer of a metadata value, i.e., how it is encoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MetadataType {
+    Byte,
+    VarInt,
+    VarLong,
+    Float,
+    String,
+    Chat,
+    OptChat,
+    Slot,
+    Boolean,
+    Rotations,
+    Position,
+    OptPosition,
+    Direction,
+    OptUuid,
+    BlockState,
+    OptBlockState,
+    Nbt,
+    Particle,
+    Particles,
+    VillagerData,
+    OptVarInt,
+    Pose,
+    CatVariant,
+    WolfVariant,
+    FrogVariant,
+    OptGlobalPos,
+    PaintingVariant,
+    SnifferState,
+    ArmadilloState,
+    Vector3,
+    Quaternion,
+}
+
+use MetadataType as M;
+
+/// Serializers of 1.19 to 1.19.2, which predate `VarLong`.
+const SERIALIZERS_1_19: &[MetadataType] = &[
+    M::Byte, M::VarInt, M::Float, M::String, M::Chat, M::OptChat, M::Slot, M::Boolean,
+    M::Rotations, M::Position, M::OptPosition, M::Direction, M::OptUuid, M::OptBlockState,
+    M::Nbt, M::Particle, M::VillagerData, M::OptVarInt, M::Pose, M::CatVariant,
+    M::FrogVariant, M::OptGlobalPos, M::PaintingVariant,
+];
+
+/// Serializers of 1.19.3.
+const SERIALIZERS_1_19_3: &[MetadataType] = &[
+    M::Byte, M::VarInt, M::VarLong, M::Float, M::String, M::Chat, M::OptChat, M::Slot,
+    M::Boolean, M::Rotations, M::Position, M::OptPosition, M::Direction, M::OptUuid,
+    M::OptBlockState, M::Nbt, M::Particle, M::VillagerData, M::OptVarInt, M::Pose,
+    M::CatVariant, M::FrogVariant, M::OptGlobalPos, M::PaintingVariant,
+];
+
+/// Serializers of 1.19.4 to 1.20.4.
+const SERIALIZERS_1_19_4: &[MetadataType] = &[
+    M::Byte, M::VarInt, M::VarLong, M::Float, M::String, M::Chat, M::OptChat, M::Slot,
+    M::Boolean, M::Rotations, M::Position, M::OptPosition, M::Direction, M::OptUuid,
+    M::BlockState, M::OptBlockState, M::Nbt, M::Particle, M::VillagerData, M::OptVarInt,
+    M::Pose, M::CatVariant, M::FrogVariant, M::OptGlobalPos, M::PaintingVariant,
+    M::SnifferState, M::Vector3, M::Quaternion,
+];
+
+/// Serializers of 1.20.5 to 1.21.1.
+const SERIALIZERS_1_20_5: &[MetadataType] = &[
+    M::Byte, M::VarInt, M::VarLong, M::Float, M::String, M::Chat, M::OptChat, M::Slot,
+    M::Boolean, M::Rotations, M::Position, M::OptPosition, M::Direction, M::OptUuid,
+    M::BlockState, M::OptBlockState, M::Nbt, M::Particle, M::Particles, M::VillagerData,
+    M::OptVarInt, M::Pose, M::CatVariant, M::WolfVariant, M::FrogVariant, M::OptGlobalPos,
+    M::PaintingVariant, M::SnifferState, M::ArmadilloState, M::Vector3, M::Quaternion,
+];
+
+impl MetadataType {
+    /// Gets the serializer table of `protocol_version`, indexed by serializer ID.
+    /// # Returns
+    /// `None` for versions before 1.19 or after 1.21.1, whose tables are not known.
+    pub fn serializers(protocol_version: i32) -> Option<&'static [MetadataType]> {
+        match protocol_version {
+            759..=760 => Some(SERIALIZERS_1_19),
+            761 => Some(SERIALIZERS_1_19_3),
+            762..=765 => Some(SERIALIZERS_1_19_4),
+            766..=767 => Some(SERIALIZERS_1_20_5),
+            _ => None,
+        }
+    }
+
+    /// Looks up the serializer with ID `id` in the table of `protocol_version`.
+    pub fn from_id(protocol_version: i32, id: i32) -> Option<Self> {
+        let table = MetadataType::serializers(protocol_version)?;
+        usize::try_from(id).ok().and_then(|id| table.get(id)).copied()
+    }
+
+    /// Gets the ID of this serializer in the table of `protocol_version`.
+    /// # Returns
+    /// `None` if the serializer does not exist in that version.
+    pub fn id(&self, protocol_version: i32) -> Option<i32> {
+        let table = MetadataType::serializers(protocol_version)?;
+        table.iter().position(|t| t == self).map(|id| id as i32)
+    }
+}
+
+/// The pitch, yaw and roll of an entity part, in degrees.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rotations {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+/// A block face or cardinal direction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Down,
+    Up,
+    North,
+    South,
+    West,
+    East,
+}
+
+const DIRECTIONS: [Direction; 6] = [
+    Direction::Down,
+    Direction::Up,
+    Direction::North,
+    Direction::South,
+    Direction::West,
+    Direction::East,
+];
+
+/// The type, profession and level of a villager.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct VillagerData {
+    pub villager_type: i32,
+    pub profession: i32,
+    pub level: i32,
+}
+
+/// The pose of an entity.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Pose {
+    Standing,
+    FallFlying,
+    Sleeping,
+    Swimming,
+    SpinAttack,
+    Sneaking,
+    LongJumping,
+    Dying,
+    Croaking,
+    UsingTongue,
+    Sitting,
+    Roaring,
+    Sniffing,
+    Emerging,
+    Digging,
+    Sliding,
+    Shooting,
+    Inhaling,
+}
+
+impl Pose {
+    /// Gets the poses of `protocol_version`, indexed by pose ID. 1.19.4 inserted
+    /// `Sitting` before `Roaring`, and 1.20.5 added the last three poses.
+    fn table(protocol_version: i32) -> &'static [Pose] {
+        use Pose as P;
+        const POSES_1_19: &[Pose] = &[
+            P::Standing, P::FallFlying, P::Sleeping, P::Swimming, P::SpinAttack, P::Sneaking,
+            P::LongJumping, P::Dying, P::Croaking, P::UsingTongue, P::Roaring, P::Sniffing,
+            P::Emerging, P::Digging,
+        ];
+        const POSES_1_20_5: &[Pose] = &[
+            P::Standing, P::FallFlying, P::Sleeping, P::Swimming, P::SpinAttack, P::Sneaking,
+            P::LongJumping, P::Dying, P::Croaking, P::UsingTongue, P::Sitting, P::Roaring,
+            P::Sniffing, P::Emerging, P::Digging, P::Sliding, P::Shooting, P::Inhaling,
+        ];
+
+        match protocol_version {
+            ..=761 => POSES_1_19,
+            762..=765 => &POSES_1_20_5[..15],
+            _ => POSES_1_20_5,
+        }
+    }
+
+    /// Looks up the pose with ID `id` in `protocol_version`.
+    pub fn from_id(protocol_version: i32, id: i32) -> Option<Self> {
+        let table = Pose::table(protocol_version);
+        usize::try_from(id).ok().and_then(|id| table.get(id)).copied()
+    }
+
+    /// Gets the ID of this pose in `protocol_version`, or `None` if it does not exist
+    /// in that version.
+    pub fn id(&self, protocol_version: i32) -> Option<i32> {
+        Pose::table(protocol_version).iter().position(|p| p == self).map(|id| id as i32)
+    }
+}
+
+/// A block position in a dimension.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct GlobalPos {
+    pub dimension: Identifier,
+    pub position: Position,
+}
+
+/// What a vibration particle travels towards.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VibrationSource {
+    Block(Position),
+    Entity { entity_id: i32, eye_height: f32 },
+}
+
+/// The extra data of particles which carry any.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParticleData {
+    None,
+    /// The block state shown by `block`, `block_marker`, `falling_dust` and, since
+    /// 1.20.5, `dust_pillar`.
+    Block(i32),
+    Dust {
+        color: [f32; 3],
+        scale: f32,
+    },
+    DustColorTransition {
+        from: [f32; 3],
+        scale: f32,
+        to: [f32; 3],
+    },
+    /// The ARGB color of `entity_effect`, which carries it since 1.20.5.
+    EntityEffect {
+        color: i32,
+    },
+    Item(ItemStack),
+    Vibration {
+        source: VibrationSource,
+        ticks: i32,
+    },
+    SculkCharge {
+        roll: f32,
+    },
+    Shriek {
+        delay: i32,
+    },
+}
+
+/// How the data of a particle type is encoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ParticleKind {
+    Block,
+    Dust,
+    DustColorTransition,
+    EntityEffect,
+    Item,
+    Vibration,
+    SculkCharge,
+    Shriek,
+}
+
+use ParticleKind as P;
+
+/// IDs of the particles of 1.19 to 1.19.3 which carry data.
+const PARTICLES_1_19: &[(i32, ParticleKind)] = &[
+    (2, P::Block), (3, P::Block), (14, P::Dust), (15, P::DustColorTransition), (25, P::Block),
+    (30, P::SculkCharge), (39, P::Item), (40, P::Vibration), (92, P::Shriek),
+];
+
+/// IDs of the particles of 1.19.4, which added three cherry leaves particles.
+const PARTICLES_1_19_4: &[(i32, ParticleKind)] = &[
+    (2, P::Block), (3, P::Block), (14, P::Dust), (15, P::DustColorTransition), (25, P::Block),
+    (33, P::SculkCharge), (42, P::Item), (43, P::Vibration), (95, P::Shriek),
+];
+
+/// IDs of the particles of 1.20 to 1.20.2, which merged the cherry leaves particles.
+const PARTICLES_1_20: &[(i32, ParticleKind)] = &[
+    (2, P::Block), (3, P::Block), (14, P::Dust), (15, P::DustColorTransition), (25, P::Block),
+    (31, P::SculkCharge), (40, P::Item), (41, P::Vibration), (93, P::Shriek),
+];
+
+/// IDs of the particles of 1.20.3 and 1.20.4, which added gusts and white smoke.
+const PARTICLES_1_20_3: &[(i32, ParticleKind)] = &[
+    (2, P::Block), (3, P::Block), (14, P::Dust), (15, P::DustColorTransition), (27, P::Block),
+    (33, P::SculkCharge), (42, P::Item), (43, P::Vibration), (96, P::Shriek),
+];
+
+/// IDs of the particles of 1.20.5 to 1.21.1, which removed `ambient_entity_effect`
+/// and gave `entity_effect` a color.
+const PARTICLES_1_20_5: &[(i32, ParticleKind)] = &[
+    (1, P::Block), (2, P::Block), (13, P::Dust), (14, P::DustColorTransition),
+    (20, P::EntityEffect), (28, P::Block), (35, P::SculkCharge), (44, P::Item),
+    (45, P::Vibration), (99, P::Shriek), (105, P::Block),
+];
+
+/// Gets the particles of `protocol_version` which carry data.
+/// # Errors
+/// This function will return an error for versions before 1.19 or after 1.21.1,
+/// whose particle registries are not known.
+fn particle_kinds(protocol_version: i32) -> Result<&'static [(i32, ParticleKind)], McError> {
+    match protocol_version {
+        759..=761 => Ok(PARTICLES_1_19),
+        762 => Ok(PARTICLES_1_19_4),
+        763..=764 => Ok(PARTICLES_1_20),
+        765 => Ok(PARTICLES_1_20_3),
+        766..=767 => Ok(PARTICLES_1_20_5),
+        _ => Err(McError::invalid(format!(
+            "The particle registry of protocol {} is not known",
+            protocol_version
+        ))),
+    }
+}
+
+/// Looks up how the data of particle `id` is encoded, or `None` if it carries none.
+fn particle_kind(kinds: &[(i32, ParticleKind)], id: i32) -> Option<ParticleKind> {
+    kinds.iter().find(|(kind_id, _)| *kind_id == id).map(|(_, kind)| *kind)
+}
+
+/// The first version which sends the type of a vibration source as a registry ID
+/// rather than an identifier.
+const VIBRATION_SOURCE_ID_PROTOCOL: i32 = 764;
+
+/// A particle type and its data. <https://wiki.vg/Particles>
+#[derive(Clone, Debug, PartialEq)]
+pub struct Particle {
+    pub id: i32,
+    pub data: ParticleData,
+}
+
+fn read_floats<const N: usize>(reader: &mut BufferedPacketReader) -> Result<[f32; N], McError> {
+    let mut floats = [0.0; N];
+    for f in &mut floats {
+        *f = reader.read_f32()?;
+    }
+    Ok(floats)
+}
+
+fn append_floats(builder: PacketBytesBuilder, floats: &[f32]) -> PacketBytesBuilder {
+    floats.iter().fold(builder, |builder, f| builder.append_f32(*f))
+}
+
+impl ParticleData {
+    fn kind(&self) -> Option<ParticleKind> {
+        match self {
+            ParticleData::None => None,
+            ParticleData::Block(_) => Some(P::Block),
+            ParticleData::Dust { .. } => Some(P::Dust),
+            ParticleData::DustColorTransition { .. } => Some(P::DustColorTransition),
+            ParticleData::EntityEffect { .. } => Some(P::EntityEffect),
+            ParticleData::Item(_) => Some(P::Item),
+            ParticleData::Vibration { .. } => Some(P::Vibration),
+            ParticleData::SculkCharge { .. } => Some(P::SculkCharge),
+            ParticleData::Shriek { .. } => Some(P::Shriek),
+        }
+    }
+}
+
+impl Particle {
+    /// Reads a particle as encoded by `protocol_version`.
+    /// # Errors
+    /// This function will return an error if the particle is truncated or malformed,
+    /// or the particle registry of `protocol_version` is not known. The layout of the
+    /// data depends on the particle ID, so it cannot be skipped.
+    pub fn read_with(reader: &mut BufferedPacketReader, protocol_version: i32) -> Result<Self, McError> {
+        let kinds = particle_kinds(protocol_version)?;
+        let id = reader.read_varint()?;
+        let data = match particle_kind(kinds, id) {
+            None => ParticleData::None,
+            Some(P::Block) => ParticleData::Block(reader.read_varint()?),
+            Some(P::Dust) => ParticleData::Dust {
+                color: read_floats(reader)?,
+                scale: reader.read_f32()?,
+            },
+            // 1.20.5 moved the scale after both colors.
+            Some(P::DustColorTransition) if protocol_version >= 766 => {
+                let from = read_floats(reader)?;
+                let to = read_floats(reader)?;
+                ParticleData::DustColorTransition { from, scale: reader.read_f32()?, to }
+            }
+            Some(P::DustColorTransition) => ParticleData::DustColorTransition {
+                from: read_floats(reader)?,
+                scale: reader.read_f32()?,
+                to: read_floats(reader)?,
+            },
+            Some(P::EntityEffect) => ParticleData::EntityEffect { color: reader.read_i32()? },
+            Some(P::Item) => ParticleData::Item(reader.read_slot(slot_format(protocol_version)?)?),
+            Some(P::Vibration) => {
+                let source = if protocol_version >= VIBRATION_SOURCE_ID_PROTOCOL {
+                    match reader.read_varint()? {
+                        0 => VibrationSource::Block(reader.read_position()?),
+                        1 => VibrationSource::Entity {
+                            entity_id: reader.read_varint()?,
+                            eye_height: reader.read_f32()?,
+                        },
+                        id => return Err(McError::invalid(format!("Unknown vibration source type {}", id))),
+                    }
+                } else {
+                    let source_type: Identifier = reader.read()?;
+                    match source_type.path() {
+                        "block" => VibrationSource::Block(reader.read_position()?),
+                        "entity" => VibrationSource::Entity {
+                            entity_id: reader.read_varint()?,
+                            eye_height: reader.read_f32()?,
+                        },
+                        _ => {
+                            return Err(McError::invalid(format!(
+                                "Unknown vibration source type {}",
+                                source_type
+                            )))
+                        }
+                    }
+                };
+                ParticleData::Vibration {
+                    source,
+                    ticks: reader.read_varint()?,
+                }
+            }
+            Some(P::SculkCharge) => ParticleData::SculkCharge { roll: reader.read_f32()? },
+            Some(P::Shriek) => ParticleData::Shriek { delay: reader.read_varint()? },
+        };
+
+        Ok(Particle { id, data })
+    }
+
+    /// Appends the particle as encoded by `protocol_version` to `builder`.
+    /// # Errors
+    /// This function will return an error if the particle registry of
+    /// `protocol_version` is not known, or `data` is not the data which particle
+    /// `id` carries in that version.
+    pub fn write_with(&self, builder: PacketBytesBuilder, protocol_version: i32) -> Result<PacketBytesBuilder, McError> {
+        if particle_kind(particle_kinds(protocol_version)?, self.id) != self.data.kind() {
+            return Err(McError::invalid(format!(
+                "Particle {} does not carry {:?} in protocol {}",
+                self.id, self.data, protocol_version
+            )));
+        }
+
+        let builder = builder.append_varint(&self.id.into());
+        let builder = match &self.data {
+            ParticleData::None => builder,
+            ParticleData::Block(state) => builder.append_varint(&(*state).into()),
+            ParticleData::Dust { color, scale } => append_floats(builder, color).append_f32(*scale),
+            ParticleData::DustColorTransition { from, scale, to } if protocol_version >= 766 => {
+                append_floats(append_floats(builder, from), to).append_f32(*scale)
+            }
+            ParticleData::DustColorTransition { from, scale, to } => {
+                append_floats(append_floats(builder, from).append_f32(*scale), to)
+            }
+            ParticleData::EntityEffect { color } => builder.append_i32(*color),
+            ParticleData::Item(item) => builder.try_append_slot(item, slot_format(protocol_version)?)?,
+            ParticleData::Vibration { source, ticks } => {
+                let id_types = protocol_version >= VIBRATION_SOURCE_ID_PROTOCOL;
+                let builder = match source {
+                    VibrationSource::Block(position) if id_types => {
+                        builder.append_varint(&0.into()).append_position(position)
+                    }
+                    VibrationSource::Block(position) => builder
+                        .append(&Identifier::minecraft("block")?)
+                        .append_position(position),
+                    VibrationSource::Entity { entity_id, eye_height } => {
+                        let builder = if id_types {
+                            builder.append_varint(&1.into())
+                        } else {
+                            builder.append(&Identifier::minecraft("entity")?)
+                        };
+                        builder.append_varint(&(*entity_id).into()).append_f32(*eye_height)
+                    }
+                };
+                builder.append_varint(&(*ticks).into())
+            }
+            ParticleData::SculkCharge { roll } => builder.append_f32(*roll),
+            ParticleData::Shriek { delay } => builder.append_varint(&(*delay).into()),
+        };
+
+        Ok(builder)
+    }
+}
+
+/// A metadata value, tagged with its serializer.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MetadataValue {
+    Byte(i8),
+    VarInt(i32),
+    VarLong(i64),
+    Float(f32),
+    String(String),
+    Chat(TextComponent),
+    OptChat(Option<TextComponent>),
+    Slot(ItemStack),
+    Boolean(bool),
+    Rotations(Rotations),
+    Position(Position),
+    OptPosition(Option<Position>),
+    Direction(Direction),
+    OptUuid(Option<Uuid>),
+    BlockState(i32),
+    /// A block state, where `None` is sent as air (0).
+    OptBlockState(Option<i32>),
+    Nbt(Option<Tag>),
+    Particle(Particle),
+    Particles(Vec<Particle>),
+    VillagerData(VillagerData),
+    OptVarInt(Option<i32>),
+    Pose(Pose),
+    CatVariant(i32),
+    WolfVariant(i32),
+    FrogVariant(i32),
+    OptGlobalPos(Option<GlobalPos>),
+    PaintingVariant(i32),
+    SnifferState(i32),
+    ArmadilloState(i32),
+    Vector3([f32; 3]),
+    Quaternion([f32; 4]),
+}
+
+fn slot_format(protocol_version: i32) -> Result<SlotFormat, McError> {
+    SlotFormat::for_protocol(protocol_version)
+        .ok_or_else(|| McError::invalid(format!("The slot format of protocol {} is not known", protocol_version)))
+}
+
+fn read_optional<T>(
+    reader: &mut BufferedPacketReader,
+    read: impl FnOnce(&mut BufferedPacketReader) -> Result<T, McError>,
+) -> Result<Option<T>, McError> {
+    if reader.read_bool()? {
+        Ok(Some(read(reader)?))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Reads a registry ID, which 1.21 sends as a holder that may inline the entry.
+fn read_registry_id(reader: &mut BufferedPacketReader, protocol_version: i32) -> Result<i32, McError> {
+    let id = reader.read_varint()?;
+    if protocol_version < 767 {
+        return Ok(id);
+    }
+    if id == 0 {
+        return Err(McError::invalid("Inline registry entries are not supported"));
+    }
+    Ok(id - 1)
+}
+
+fn append_registry_id(builder: PacketBytesBuilder, id: i32, protocol_version: i32) -> PacketBytesBuilder {
+    let id = if protocol_version < 767 { id } else { id + 1 };
+    builder.append_varint(&id.into())
+}
+
+impl MetadataValue {
+    /// Gets the serializer of this value.
+    pub fn kind(&self) -> MetadataType {
+        match self {
+            MetadataValue::Byte(_) => M::Byte,
+            MetadataValue::VarInt(_) => M::VarInt,
+            MetadataValue::VarLong(_) => M::VarLong,
+            MetadataValue::Float(_) => M::Float,
+            MetadataValue::String(_) => M::String,
+            MetadataValue::Chat(_) => M::Chat,
+            MetadataValue::OptChat(_) => M::OptChat,
+            MetadataValue::Slot(_) => M::Slot,
+            MetadataValue::Boolean(_) => M::Boolean,
+            MetadataValue::Rotations(_) => M::Rotations,
+            MetadataValue::Position(_) => M::Position,
+            MetadataValue::OptPosition(_) => M::OptPosition,
+            MetadataValue::Direction(_) => M::Direction,
+            MetadataValue::OptUuid(_) => M::OptUuid,
+            MetadataValue::BlockState(_) => M::BlockState,
+            MetadataValue::OptBlockState(_) => M::OptBlockState,
+            MetadataValue::Nbt(_) => M::Nbt,
+            MetadataValue::Particle(_) => M::Particle,
+            MetadataValue::Particles(_) => M::Particles,
+            MetadataValue::VillagerData(_) => M::VillagerData,
+            MetadataValue::OptVarInt(_) => M::OptVarInt,
+            MetadataValue::Pose(_) => M::Pose,
+            MetadataValue::CatVariant(_) => M::CatVariant,
+            MetadataValue::WolfVariant(_) => M::WolfVariant,
+            MetadataValue::FrogVariant(_) => M::FrogVariant,
+            MetadataValue::OptGlobalPos(_) => M::OptGlobalPos,
+            MetadataValue::PaintingVariant(_) => M::PaintingVariant,
+            MetadataValue::SnifferState(_) => M::SnifferState,
+            MetadataValue::ArmadilloState(_) => M::ArmadilloState,
+            MetadataValue::Vector3(_) => M::Vector3,
+            MetadataValue::Quaternion(_) => M::Quaternion,
+        }
+    }
+
+    /// Reads a value of serializer `kind` as encoded by `protocol_version`.
+    /// # Errors
+    /// This function will return an error if the value is truncated or malformed.
+    pub fn read_with(
+        reader: &mut BufferedPacketReader,
+        kind: MetadataType,
+        protocol_version: i32,
+    ) -> Result<Self, McError> {
+        let value = match kind {
+            M::Byte => MetadataValue::Byte(reader.read_i8()?),
+            M::VarInt => MetadataValue::VarInt(reader.read_varint()?),
+            M::VarLong => MetadataValue::VarLong(reader.read_varlong()?),
+            M::Float => MetadataValue::Float(reader.read_f32()?),
+            M::String => MetadataValue::String(reader.read()?),
+            M::Chat => MetadataValue::Chat(TextComponent::read_with(reader, protocol_version)?),
+            M::OptChat => MetadataValue::OptChat(read_optional(reader, |r| TextComponent::read_with(r, protocol_version))?),
+            M::Slot => MetadataValue::Slot(reader.read_slot(slot_format(protocol_version)?)?),
+            M::Boolean => MetadataValue::Boolean(reader.read_bool()?),
+            M::Rotations => {
+                let [x, y, z] = read_floats(reader)?;
+                MetadataValue::Rotations(Rotations { x, y, z })
+            }
+            M::Position => MetadataValue::Position(reader.read_position()?),
+            M::OptPosition => MetadataValue::OptPosition(read_optional(reader, |r| r.read_position())?),
+            M::Direction => {
+                let id = reader.read_varint()?;
+                let direction = usize::try_from(id)
+                    .ok()
+                    .and_then(|id| DIRECTIONS.get(id))
+                    .ok_or_else(|| McError::invalid(format!("Unknown direction {}", id)))?;
+                MetadataValue::Direction(*direction)
+            }
+            M::OptUuid => MetadataValue::OptUuid(read_optional(reader, |r| r.read_uuid())?),
+            M::BlockState => MetadataValue::BlockState(reader.read_varint()?),
+            M::OptBlockState => MetadataValue::OptBlockState(Some(reader.read_varint()?).filter(|s| *s != 0)),
+            M::Nbt => MetadataValue::Nbt(if protocol_version >= 764 {
+                binary::read_nameless(reader)?
+            } else {
+                binary::read_named(reader)?.map(|(_, tag)| tag)
+            }),
+            M::Particle => MetadataValue::Particle(Particle::read_with(reader, protocol_version)?),
+            M::Particles => {
+                let len = reader.read_length()?;
+                let mut particles = Vec::with_capacity(len.min(reader.remaining()));
+                for _ in 0..len {
+                    particles.push(Particle::read_with(reader, protocol_version)?);
+                }
+                MetadataValue::Particles(particles)
+            }
+            M::VillagerData => MetadataValue::VillagerData(VillagerData {
+                villager_type: reader.read_varint()?,
+                profession: reader.read_varint()?,
+                level: reader.read_varint()?,
+            }),
+            M::OptVarInt => {
+                let value = reader.read_varint()?;
+                MetadataValue::OptVarInt((value != 0).then(|| value - 1))
+            }
+            M::Pose => {
+                let id = reader.read_varint()?;
+                let pose = Pose::from_id(protocol_version, id)
+                    .ok_or_else(|| McError::invalid(format!("Unknown pose {}", id)))?;
+                MetadataValue::Pose(pose)
+            }
+            M::CatVariant => MetadataValue::CatVariant(reader.read_varint()?),
+            M::WolfVariant => MetadataValue::WolfVariant(read_registry_id(reader, protocol_version)?),
+            M::FrogVariant => MetadataValue::FrogVariant(reader.read_varint()?),
+            M::OptGlobalPos => MetadataValue::OptGlobalPos(read_optional(reader, |r| {
+                Ok(GlobalPos {
+                    dimension: r.read()?,
+                    position: r.read_position()?,
+                })
+            })?),
+            M::PaintingVariant => MetadataValue::PaintingVariant(read_registry_id(reader, protocol_version)?),
+            M::SnifferState => MetadataValue::SnifferState(reader.read_varint()?),
+            M::ArmadilloState => MetadataValue::ArmadilloState(reader.read_varint()?),
+            M::Vector3 => MetadataValue::Vector3(read_floats(reader)?),
+            M::Quaternion => MetadataValue::Quaternion(read_floats(reader)?),
+        };
+
+        Ok(value)
+    }
+
+    /// Appends the value as encoded by `protocol_version` to `builder`, without
+    /// its serializer ID.
+    /// # Errors
+    /// This function will return an error if the value cannot be expressed in
+    /// `protocol_version`, e.g., a pose added in a later version, or its NBT cannot
+    /// be encoded.
+    pub fn write_with(&self, builder: PacketBytesBuilder, protocol_version: i32) -> Result<PacketBytesBuilder, McError> {
+        let builder = match self {
+            MetadataValue::Byte(v) => builder.append_i8(*v),
+            MetadataValue::VarInt(v)
+            | MetadataValue::BlockState(v)
+            | MetadataValue::CatVariant(v)
+            | MetadataValue::FrogVariant(v)
+            | MetadataValue::SnifferState(v)
+            | MetadataValue::ArmadilloState(v) => builder.append_varint(&(*v).into()),
+            MetadataValue::VarLong(v) => builder.append_varlong(&(*v).into()),
+            MetadataValue::Float(v) => builder.append_f32(*v),
+            MetadataValue::String(v) => builder.append_string(v),
+            MetadataValue::Chat(chat) => chat.write_with(builder, protocol_version)?,
+            MetadataValue::OptChat(chat) => match chat {
+                Some(chat) => chat.write_with(builder.append_bool(true), protocol_version)?,
+                None => builder.append_bool(false),
+            },
+            MetadataValue::Slot(item) => builder.try_append_slot(item, slot_format(protocol_version)?)?,
+            MetadataValue::Boolean(v) => builder.append_bool(*v),
+            MetadataValue::Rotations(r) => append_floats(builder, &[r.x, r.y, r.z]),
+            MetadataValue::Position(position) => builder.append_position(position),
+            MetadataValue::OptPosition(position) => match position {
+                Some(position) => builder.append_bool(true).append_position(position),
+                None => builder.append_bool(false),
+            },
+            MetadataValue::Direction(direction) => builder.append_varint(&(*direction as i32).into()),
+            MetadataValue::OptUuid(uuid) => match uuid {
+                Some(uuid) => builder.append_bool(true).append_uuid(uuid),
+                None => builder.append_bool(false),
+            },
+            MetadataValue::OptBlockState(state) => builder.append_varint(&state.unwrap_or(0).into()),
+            MetadataValue::Nbt(tag) => {
+                if protocol_version >= 764 {
+                    builder.try_append_network_nbt(tag.clone().map(nbt::NetworkNbt).as_ref())?
+                } else {
+                    builder.try_append_nbt(tag.clone().map(|tag| Nbt::new("", tag)).as_ref())?
+                }
+            }
+            MetadataValue::Particle(particle) => particle.write_with(builder, protocol_version)?,
+            MetadataValue::Particles(particles) => {
+                let builder = builder.append_varint(&(particles.len() as i32).into());
+                particles
+                    .iter()
+                    .try_fold(builder, |builder, particle| particle.write_with(builder, protocol_version))?
+            }
+            MetadataValue::VillagerData(data) => builder
+                .append_varint(&data.villager_type.into())
+                .append_varint(&data.profession.into())
+                .append_varint(&data.level.into()),
+            MetadataValue::OptVarInt(v) => builder.append_varint(&v.map_or(0, |v| v + 1).into()),
+            MetadataValue::Pose(pose) => {
+                let id = pose.id(protocol_version).ok_or_else(|| {
+                    McError::invalid(format!("Pose {:?} does not exist in protocol {}", pose, protocol_version))
+                })?;
+                builder.append_varint(&id.into())
+            }
+            MetadataValue::WolfVariant(id) | MetadataValue::PaintingVariant(id) => {
+                append_registry_id(builder, *id, protocol_version)
+            }
+            MetadataValue::OptGlobalPos(pos) => match pos {
+                Some(pos) => builder
+                    .append_bool(true)
+                    .append(&pos.dimension)
+                    .append_position(&pos.position),
+                None => builder.append_bool(false),
+            },
+            MetadataValue::Vector3(v) => append_floats(builder, v),
+            MetadataValue::Quaternion(v) => append_floats(builder, v),
+        };
+
+        Ok(builder)
+    }
+}
+
+/// The metadata of an entity, mapping data field indices to their values.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EntityMetadata {
+    entries: BTreeMap<u8, MetadataValue>,
+}
+
+impl EntityMetadata {
+    /// Constructs empty metadata.
+    pub fn new() -> Self {
+        EntityMetadata::default()
+    }
+
+    /// Gets the value of the data field at `index`.
+    pub fn get(&self, index: u8) -> Option<&MetadataValue> {
+        self.entries.get(&index)
+    }
+
+    /// Sets the value of the data field at `index`, returning the previous value.
+    /// # Panics
+    /// This function panics if `index` is `METADATA_END`, which terminates the list.
+    pub fn insert(&mut self, index: u8, value: MetadataValue) -> Option<MetadataValue> {
+        assert_ne!(index, METADATA_END, "metadata index {:#04x} is reserved", METADATA_END);
+        self.entries.insert(index, value)
+    }
+
+    /// Removes the value of the data field at `index`.
+    pub fn remove(&mut self, index: u8) -> Option<MetadataValue> {
+        self.entries.remove(&index)
+    }
+
+    /// Iterates over the entries in index order.
+    pub fn iter(&self) -> impl Iterator<Item = (u8, &MetadataValue)> {
+        self.entries.iter().map(|(index, value)| (*index, value))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Reads a metadata list as encoded by `protocol_version`. An index sent more
+    /// than once keeps its last value.
+    /// # Errors
+    /// This function will return an error if the list is truncated or malformed, a
+    /// serializer ID is not in the table of `protocol_version`, or the table of
+    /// `protocol_version` is not known.
+    pub fn read_with(reader: &mut BufferedPacketReader, protocol_version: i32) -> Result<Self, McError> {
+        if MetadataType::serializers(protocol_version).is_none() {
+            return Err(unknown_protocol(protocol_version));
+        }
+
+        let mut metadata = EntityMetadata::new();
+        loop {
+            let index = reader.read_u8()?;
+            if index == METADATA_END {
+                return Ok(metadata);
+            }
+            let id = reader.read_varint()?;
+            let kind = MetadataType::from_id(protocol_version, id)
+                .ok_or_else(|| McError::invalid(format!("Unknown metadata serializer {} at index {}", id, index)))?;
+            metadata.entries.insert(index, MetadataValue::read_with(reader, kind, protocol_version)?);
+        }
+    }
+
+    /// Appends the metadata list as encoded by `protocol_version` to `builder`.
+    /// # Errors
+    /// This function will return an error if a value cannot be expressed in
+    /// `protocol_version`, e.g., its serializer was added in a later version.
+    pub fn write_with(&self, builder: PacketBytesBuilder, protocol_version: i32) -> Result<PacketBytesBuilder, McError> {
+        if MetadataType::serializers(protocol_version).is_none() {
+            return Err(unknown_protocol(protocol_version));
+        }
+
+        let builder = self
+            .entries
+            .iter()
+            .try_fold(builder, |builder, (index, value)| write_entry(builder, *index, value, protocol_version))?;
+
+        Ok(builder.append_u8(METADATA_END))
+    }
+}
+
+/// Appends one entry, i.e., its index, serializer ID and value.
+fn write_entry(
+    builder: PacketBytesBuilder,
+    index: u8,
+    value: &MetadataValue,
+    protocol_version: i32,
+) -> Result<PacketBytesBuilder, McError> {
+    let kind = value.kind();
+    let id = kind.id(protocol_version).ok_or_else(|| {
+        McError::invalid(format!(
+            "Metadata serializer {:?} does not exist in protocol {}",
+            kind, protocol_version
+        ))
+    })?;
+    value.write_with(builder.append_u8(index).append_varint(&id.into()), protocol_version)
+}
+
+fn unknown_protocol(protocol_version: i32) -> McError {
+    McError::invalid(format!(
+        "The entity metadata serializers of protocol {} are not known",
+        protocol_version
+    ))
+}
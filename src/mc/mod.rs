@@ -1,7 +1,22 @@
+pub mod client;
+pub mod compression;
+pub mod configuration;
 pub mod connection;
+pub mod encryption;
+pub mod error;
+pub mod frame;
+pub mod item;
+pub mod legacy;
+pub mod metadata;
 pub mod mctypes;
+pub mod nbt;
+pub mod options;
 pub mod packet;
+pub mod resolver;
+pub mod session;
+pub mod status;
 pub mod stream;
+pub mod text;
 
 // TODO: The eventual goal is to support multiple versions using macros to generate
 // packet structures per-version, though this may or may not be feasible. External
@@ -0,0 +1,295 @@
+//! The big-endian binary NBT encoding. <https://wiki.vg/NBT#Specification>
+
+use crate::mc::{
+    error::{DataError, McError},
+    packet::reader::BufferedPacketReader,
+};
+
+use super::*;
+
+/// The deepest nesting of lists and compounds accepted when decoding. This
+/// matches the limit enforced by the vanilla client and server.
+pub const MAX_DEPTH: usize = 512;
+
+/// Reads a named root tag, i.e., a type ID, a name and a payload.
+/// # Returns
+/// `None` if the root is `TAG_End`, which the network uses to mark absent NBT.
+/// # Errors
+/// This function will return an error if the data is truncated, a tag type is
+/// unknown, or the nesting is deeper than `MAX_DEPTH`.
+pub fn read_named(reader: &mut BufferedPacketReader) -> Result<Option<(String, Tag)>, McError> {
+    let id = reader.read_u8()?;
+    if id == TAG_END {
+        return Ok(None);
+    }
+
+    let name = read_mutf8(reader)?;
+    let tag = read_payload(reader, id, 0)?;
+    Ok(Some((name, tag)))
+}
+
+/// Reads a nameless root tag, i.e., a type ID followed by a payload.
+/// # Returns
+/// `None` if the root is `TAG_End`.
+/// # Errors
+/// See `read_named`.
+pub fn read_nameless(reader: &mut BufferedPacketReader) -> Result<Option<Tag>, McError> {
+    let id = reader.read_u8()?;
+    if id == TAG_END {
+        return Ok(None);
+    }
+
+    Ok(Some(read_payload(reader, id, 0)?))
+}
+
+/// Checks that `len` elements of at least `min_size` bytes each can still be read,
+/// so that a forged length cannot trigger a huge allocation.
+fn check_len(reader: &BufferedPacketReader, len: i32, min_size: usize) -> Result<usize, McError> {
+    let len = usize::try_from(len).map_err(|_| McError::invalid(format!("Negative NBT length {}", len)))?;
+    if len.saturating_mul(min_size) > reader.remaining() {
+        return Err(McError::malformed(DataError::Truncated {
+            needed: len.saturating_mul(min_size),
+            remaining: reader.remaining(),
+            position: reader.position(),
+        }));
+    }
+    Ok(len)
+}
+
+/// Reads the payload of a tag of type `id`.
+pub fn read_payload(reader: &mut BufferedPacketReader, id: u8, depth: usize) -> Result<Tag, McError> {
+    if depth > MAX_DEPTH {
+        return Err(McError::invalid(format!("NBT is nested deeper than {}", MAX_DEPTH)));
+    }
+
+    // Only lists and compounds recurse. Every other tag is read by `read_flat_payload`,
+    // which keeps this function's stack frame small enough for `MAX_DEPTH` levels.
+    let tag = match id {
+        TAG_LIST => {
+            let element_id = reader.read_u8()?;
+            let len = reader.read_i32()?;
+            // Empty lists are commonly written with TAG_End and any length <= 0.
+            if element_id == TAG_END || len <= 0 {
+                if element_id == TAG_END && len > 0 {
+                    return Err(McError::invalid("Non-empty NBT list of TAG_End"));
+                }
+                return Ok(Tag::List(Vec::new()));
+            }
+            let len = check_len(reader, len, 1)?;
+            let mut list = Vec::with_capacity(len);
+            for _ in 0..len {
+                list.push(read_payload(reader, element_id, depth + 1)?);
+            }
+            Tag::List(list)
+        }
+        TAG_COMPOUND => {
+            let mut compound = Compound::new();
+            loop {
+                let id = reader.read_u8()?;
+                if id == TAG_END {
+                    break;
+                }
+                let name = read_mutf8(reader)?;
+                let tag = read_payload(reader, id, depth + 1)?;
+                compound.insert(name, tag);
+            }
+            Tag::Compound(compound)
+        }
+        _ => read_flat_payload(reader, id)?,
+    };
+
+    Ok(tag)
+}
+
+/// Reads the payload of a tag of type `id` which cannot hold other tags.
+#[inline(never)]
+fn read_flat_payload(reader: &mut BufferedPacketReader, id: u8) -> Result<Tag, McError> {
+    let tag = match id {
+        TAG_BYTE => Tag::Byte(reader.read_i8()?),
+        TAG_SHORT => Tag::Short(reader.read_i16()?),
+        TAG_INT => Tag::Int(reader.read_i32()?),
+        TAG_LONG => Tag::Long(reader.read_i64()?),
+        TAG_FLOAT => Tag::Float(reader.read_f32()?),
+        TAG_DOUBLE => Tag::Double(reader.read_f64()?),
+        TAG_BYTE_ARRAY => {
+            let len = reader.read_i32()?;
+            let len = check_len(reader, len, 1)?;
+            Tag::ByteArray(reader.read_bytes(len)?.iter().map(|b| *b as i8).collect())
+        }
+        TAG_STRING => Tag::String(read_mutf8(reader)?),
+        TAG_INT_ARRAY => {
+            let len = reader.read_i32()?;
+            let len = check_len(reader, len, 4)?;
+            let mut array = Vec::with_capacity(len);
+            for _ in 0..len {
+                array.push(reader.read_i32()?);
+            }
+            Tag::IntArray(array)
+        }
+        TAG_LONG_ARRAY => {
+            let len = reader.read_i32()?;
+            let len = check_len(reader, len, 8)?;
+            let mut array = Vec::with_capacity(len);
+            for _ in 0..len {
+                array.push(reader.read_i64()?);
+            }
+            Tag::LongArray(array)
+        }
+        _ => return Err(McError::invalid(format!("Unknown NBT tag type {}", id))),
+    };
+
+    Ok(tag)
+}
+
+/// Writes a named root tag.
+/// # Errors
+/// This function will return a `Malformed` error if a list holds tags of different
+/// types, or a string is longer than 65535 bytes once encoded.
+pub fn write_named(buf: &mut Vec<u8>, name: &str, tag: &Tag) -> Result<(), McError> {
+    buf.push(tag.id());
+    write_mutf8(buf, name)?;
+    write_payload(buf, tag)
+}
+
+/// Writes a nameless root tag. See `write_named`.
+pub fn write_nameless(buf: &mut Vec<u8>, tag: &Tag) -> Result<(), McError> {
+    buf.push(tag.id());
+    write_payload(buf, tag)
+}
+
+/// Writes the payload of `tag`, without its type ID or name.
+pub fn write_payload(buf: &mut Vec<u8>, tag: &Tag) -> Result<(), McError> {
+    match tag {
+        Tag::Byte(v) => buf.push(*v as u8),
+        Tag::Short(v) => buf.extend(v.to_be_bytes()),
+        Tag::Int(v) => buf.extend(v.to_be_bytes()),
+        Tag::Long(v) => buf.extend(v.to_be_bytes()),
+        Tag::Float(v) => buf.extend(v.to_be_bytes()),
+        Tag::Double(v) => buf.extend(v.to_be_bytes()),
+        Tag::ByteArray(array) => {
+            buf.extend((array.len() as i32).to_be_bytes());
+            buf.extend(array.iter().map(|b| *b as u8));
+        }
+        Tag::String(s) => write_mutf8(buf, s)?,
+        Tag::List(list) => {
+            let element_id = list.first().map_or(TAG_END, Tag::id);
+            if list.iter().any(|t| t.id() != element_id) {
+                return Err(McError::invalid("NBT list holds tags of different types"));
+            }
+            buf.push(element_id);
+            buf.extend((list.len() as i32).to_be_bytes());
+            for element in list {
+                write_payload(buf, element)?;
+            }
+        }
+        Tag::Compound(compound) => {
+            for (name, tag) in compound.iter() {
+                write_named(buf, name, tag)?;
+            }
+            buf.push(TAG_END);
+        }
+        Tag::IntArray(array) => {
+            buf.extend((array.len() as i32).to_be_bytes());
+            for v in array {
+                buf.extend(v.to_be_bytes());
+            }
+        }
+        Tag::LongArray(array) => {
+            buf.extend((array.len() as i32).to_be_bytes());
+            for v in array {
+                buf.extend(v.to_be_bytes());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Computes the encoded byte size of the payload of `tag`.
+pub fn payload_size(tag: &Tag) -> usize {
+    match tag {
+        Tag::Byte(_) => 1,
+        Tag::Short(_) => 2,
+        Tag::Int(_) | Tag::Float(_) => 4,
+        Tag::Long(_) | Tag::Double(_) => 8,
+        Tag::ByteArray(array) => 4 + array.len(),
+        Tag::String(s) => 2 + mutf8_len(s),
+        Tag::List(list) => 1 + 4 + list.iter().map(payload_size).sum::<usize>(),
+        Tag::Compound(compound) => {
+            compound
+                .iter()
+                .map(|(name, tag)| 1 + 2 + mutf8_len(name) + payload_size(tag))
+                .sum::<usize>()
+                + 1
+        }
+        Tag::IntArray(array) => 4 + 4 * array.len(),
+        Tag::LongArray(array) => 4 + 8 * array.len(),
+    }
+}
+
+/// Computes the byte length of `s` in Java's modified UTF-8, which encodes NUL as
+/// two bytes and supplementary characters as two 3-byte surrogates.
+pub fn mutf8_len(s: &str) -> usize {
+    s.encode_utf16()
+        .map(|unit| match unit {
+            0x0001..=0x007F => 1,
+            0x0000 | 0x0080..=0x07FF => 2,
+            _ => 3,
+        })
+        .sum()
+}
+
+/// Reads a string prefixed with its `u16` byte length and encoded in modified UTF-8.
+pub fn read_mutf8(reader: &mut BufferedPacketReader) -> Result<String, McError> {
+    let len = reader.read_u16()? as usize;
+    let bytes = reader.read_bytes(len)?;
+
+    // Plain ASCII is identical in both encodings.
+    if bytes.iter().all(|b| (0x01..0x80).contains(b)) {
+        return Ok(String::from_utf8(bytes.to_vec()).unwrap_or_default());
+    }
+
+    let mut units = Vec::with_capacity(len);
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i] as u16;
+        let (unit, width) = match b {
+            0x01..=0x7F => (b, 1),
+            0xC0..=0xDF if i + 1 < bytes.len() => (((b & 0x1F) << 6) | (bytes[i + 1] as u16 & 0x3F), 2),
+            0xE0..=0xEF if i + 2 < bytes.len() => (
+                ((b & 0x0F) << 12) | ((bytes[i + 1] as u16 & 0x3F) << 6) | (bytes[i + 2] as u16 & 0x3F),
+                3,
+            ),
+            _ => return Err(McError::invalid(format!("Invalid modified UTF-8 byte {:#04x}", b))),
+        };
+        units.push(unit);
+        i += width;
+    }
+
+    String::from_utf16(&units).map_err(|e| McError::invalid(e.to_string()))
+}
+
+/// Writes `s` prefixed with its `u16` byte length, encoded in modified UTF-8.
+pub fn write_mutf8(buf: &mut Vec<u8>, s: &str) -> Result<(), McError> {
+    let len = mutf8_len(s);
+    let len = u16::try_from(len)
+        .map_err(|_| McError::invalid(format!("NBT string of {} bytes exceeds the maximum of 65535", len)))?;
+    buf.extend(len.to_be_bytes());
+
+    for unit in s.encode_utf16() {
+        match unit {
+            0x0001..=0x007F => buf.push(unit as u8),
+            0x0000 | 0x0080..=0x07FF => {
+                buf.push(0xC0 | (unit >> 6) as u8);
+                buf.push(0x80 | (unit & 0x3F) as u8);
+            }
+            _ => {
+                buf.push(0xE0 | (unit >> 12) as u8);
+                buf.push(0x80 | ((unit >> 6) & 0x3F) as u8);
+                buf.push(0x80 | (unit & 0x3F) as u8);
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,409 @@
+//! Named Binary Tag (NBT) support. <https://wiki.vg/NBT>
+//! <br>
+//! The tag tree is represented by `Tag` and `Compound`. `Nbt` is a root tag with
+//! a name, as used in files and on the network before 1.20.2, and `NetworkNbt`
+//! is the nameless root used on the network since 1.20.2. They are read with
+//! `BufferedPacketReader::read_nbt` and `read_network_nbt`, and written with
+//! `PacketBytesBuilder::try_append_nbt` and `try_append_network_nbt`, which fail
+//! on a list mixing tag types or an over-long string instead of writing invalid
+//! data. Absent NBT (a lone `TAG_End`) is expressed as `Option::None`.
+
+use std::{
+    fmt,
+    fs::File,
+    io::{Read, Write},
+    path::Path,
+};
+
+use flate2::{read::GzDecoder, read::ZlibDecoder, write::GzEncoder, write::ZlibEncoder};
+use indexmap::IndexMap;
+
+use super::{error::McError, packet::reader::BufferedPacketReader};
+
+pub mod binary;
+pub mod snbt;
+
+pub const TAG_END: u8 = 0;
+pub const TAG_BYTE: u8 = 1;
+pub const TAG_SHORT: u8 = 2;
+pub const TAG_INT: u8 = 3;
+pub const TAG_LONG: u8 = 4;
+pub const TAG_FLOAT: u8 = 5;
+pub const TAG_DOUBLE: u8 = 6;
+pub const TAG_BYTE_ARRAY: u8 = 7;
+pub const TAG_STRING: u8 = 8;
+pub const TAG_LIST: u8 = 9;
+pub const TAG_COMPOUND: u8 = 10;
+pub const TAG_INT_ARRAY: u8 = 11;
+pub const TAG_LONG_ARRAY: u8 = 12;
+
+/// A single NBT tag and, for lists and compounds, its children.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    /// A list of tags which must all be of the same type.
+    List(Vec<Tag>),
+    Compound(Compound),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Tag {
+    /// Gets the type ID of this tag as written in the binary format.
+    pub fn id(&self) -> u8 {
+        match self {
+            Tag::Byte(_) => TAG_BYTE,
+            Tag::Short(_) => TAG_SHORT,
+            Tag::Int(_) => TAG_INT,
+            Tag::Long(_) => TAG_LONG,
+            Tag::Float(_) => TAG_FLOAT,
+            Tag::Double(_) => TAG_DOUBLE,
+            Tag::ByteArray(_) => TAG_BYTE_ARRAY,
+            Tag::String(_) => TAG_STRING,
+            Tag::List(_) => TAG_LIST,
+            Tag::Compound(_) => TAG_COMPOUND,
+            Tag::IntArray(_) => TAG_INT_ARRAY,
+            Tag::LongArray(_) => TAG_LONG_ARRAY,
+        }
+    }
+
+    /// Gets the value of a numeric tag widened to an `i64`. Floating point values
+    /// are truncated.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Tag::Byte(v) => Some(*v as i64),
+            Tag::Short(v) => Some(*v as i64),
+            Tag::Int(v) => Some(*v as i64),
+            Tag::Long(v) => Some(*v),
+            Tag::Float(v) => Some(*v as i64),
+            Tag::Double(v) => Some(*v as i64),
+            _ => None,
+        }
+    }
+
+    /// Gets the value of a numeric tag as an `f64`.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Tag::Float(v) => Some(*v as f64),
+            Tag::Double(v) => Some(*v),
+            _ => self.as_i64().map(|v| v as f64),
+        }
+    }
+
+    /// Gets the value of a string tag.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Tag::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Gets the elements of a list tag.
+    pub fn as_list(&self) -> Option<&[Tag]> {
+        match self {
+            Tag::List(list) => Some(list),
+            _ => None,
+        }
+    }
+
+    /// Gets the entries of a compound tag.
+    pub fn as_compound(&self) -> Option<&Compound> {
+        match self {
+            Tag::Compound(compound) => Some(compound),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Tag {
+    /// Formats the tag as SNBT.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        snbt::write_tag(f, self)
+    }
+}
+
+impl From<Compound> for Tag {
+    fn from(value: Compound) -> Self {
+        Tag::Compound(value)
+    }
+}
+
+impl From<&str> for Tag {
+    fn from(value: &str) -> Self {
+        Tag::String(value.to_owned())
+    }
+}
+
+impl From<String> for Tag {
+    fn from(value: String) -> Self {
+        Tag::String(value)
+    }
+}
+
+macro_rules! tag_from_primitive {
+    ($($t:ty => $variant:ident),* $(,)?) => {
+        $(
+            impl From<$t> for Tag {
+                fn from(value: $t) -> Self {
+                    Tag::$variant(value)
+                }
+            }
+        )*
+    };
+}
+
+tag_from_primitive! {
+    i8 => Byte,
+    i16 => Short,
+    i32 => Int,
+    i64 => Long,
+    f32 => Float,
+    f64 => Double,
+}
+
+/// An NBT compound: a set of uniquely named tags. Entries keep the order in
+/// which they were inserted or decoded, so re-encoding a decoded compound
+/// produces the same bytes. They are indexed by name, so lookups and inserts
+/// take constant time however large the compound is. Like NBT itself, equality
+/// does not depend on the order.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Compound {
+    entries: IndexMap<String, Tag>,
+}
+
+impl Compound {
+    /// Constructs an empty compound.
+    pub fn new() -> Self {
+        Compound::default()
+    }
+
+    /// Gets the number of entries in the compound.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the compound has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Gets the tag named `key`.
+    pub fn get(&self, key: &str) -> Option<&Tag> {
+        self.entries.get(key)
+    }
+
+    /// Gets a mutable reference to the tag named `key`.
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Tag> {
+        self.entries.get_mut(key)
+    }
+
+    /// Returns `true` if the compound has an entry named `key`.
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Inserts `value` under `key`, returning the tag it replaced, if any. A replaced
+    /// entry keeps its position.
+    pub fn insert<K: Into<String>, V: Into<Tag>>(&mut self, key: K, value: V) -> Option<Tag> {
+        self.entries.insert(key.into(), value.into())
+    }
+
+    /// Removes the entry named `key`, returning its tag. The entries after it keep
+    /// their order.
+    pub fn remove(&mut self, key: &str) -> Option<Tag> {
+        self.entries.shift_remove(key)
+    }
+
+    /// Iterates over the entries of the compound in order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Tag)> {
+        self.entries.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    /// Gets the string tag named `key`.
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.get(key).and_then(Tag::as_str)
+    }
+
+    /// Gets the numeric tag named `key`, widened to an `i64`.
+    pub fn get_i64(&self, key: &str) -> Option<i64> {
+        self.get(key).and_then(Tag::as_i64)
+    }
+
+    /// Gets the compound tag named `key`.
+    pub fn get_compound(&self, key: &str) -> Option<&Compound> {
+        self.get(key).and_then(Tag::as_compound)
+    }
+
+    /// Gets the list tag named `key`.
+    pub fn get_list(&self, key: &str) -> Option<&[Tag]> {
+        self.get(key).and_then(Tag::as_list)
+    }
+}
+
+impl<K: Into<String>, V: Into<Tag>> FromIterator<(K, V)> for Compound {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut compound = Compound::new();
+        for (k, v) in iter {
+            compound.insert(k, v);
+        }
+        compound
+    }
+}
+
+impl IntoIterator for Compound {
+    type Item = (String, Tag);
+    type IntoIter = indexmap::map::IntoIter<String, Tag>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+/// The compression applied to an NBT file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NbtCompression {
+    None,
+    Gzip,
+    Zlib,
+}
+
+impl NbtCompression {
+    /// Guesses the compression of `bytes` from its leading magic bytes.
+    pub fn detect(bytes: &[u8]) -> Self {
+        match bytes {
+            [0x1F, 0x8B, ..] => NbtCompression::Gzip,
+            // A zlib header's first byte is 0x78 for the default 32K window, and the
+            // header as a whole is a multiple of 31.
+            [0x78, b, ..] if (0x7800_u16 | *b as u16).is_multiple_of(31) => NbtCompression::Zlib,
+            _ => NbtCompression::None,
+        }
+    }
+}
+
+/// An NBT root tag together with its name. This is the format of NBT files, and of
+/// NBT on the network up to protocol 1.20.1.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Nbt {
+    pub name: String,
+    pub tag: Tag,
+}
+
+impl Nbt {
+    /// Creates a root tag named `name`.
+    pub fn new<S: Into<String>>(name: S, tag: Tag) -> Self {
+        Nbt { name: name.into(), tag }
+    }
+
+    /// Reads an NBT file from `reader`, decompressing it first if it is gzip or zlib
+    /// compressed.
+    /// # Errors
+    /// This function will return an error if the data cannot be read, decompressed,
+    /// or parsed, or if the file holds no root tag.
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Self, McError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+
+        let bytes = decompress(bytes)?;
+        let mut reader = BufferedPacketReader::new(&bytes);
+        let nbt = reader.read_nbt()?.ok_or_else(|| McError::invalid("NBT file has no root tag"))?;
+        reader.finish()?;
+
+        Ok(nbt)
+    }
+
+    /// Reads the NBT file at `path`. See `Nbt::from_reader`.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, McError> {
+        Nbt::from_reader(File::open(path)?)
+    }
+
+    /// Writes the NBT to `writer` as a file, compressed with `compression`.
+    /// # Errors
+    /// This function will return an error if the tag tree cannot be encoded or
+    /// the writer fails.
+    pub fn to_writer<W: Write>(&self, writer: W, compression: NbtCompression) -> Result<(), McError> {
+        let mut bytes = Vec::new();
+        binary::write_named(&mut bytes, &self.name, &self.tag)?;
+
+        match compression {
+            NbtCompression::None => {
+                let mut writer = writer;
+                writer.write_all(&bytes)?;
+            }
+            NbtCompression::Gzip => {
+                let mut encoder = GzEncoder::new(writer, flate2::Compression::default());
+                encoder.write_all(&bytes)?;
+                encoder.finish()?;
+            }
+            NbtCompression::Zlib => {
+                let mut encoder = ZlibEncoder::new(writer, flate2::Compression::default());
+                encoder.write_all(&bytes)?;
+                encoder.finish()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the NBT to a file at `path`. See `Nbt::to_writer`.
+    pub fn to_file<P: AsRef<Path>>(&self, path: P, compression: NbtCompression) -> Result<(), McError> {
+        self.to_writer(File::create(path)?, compression)
+    }
+}
+
+/// Decompresses `bytes` according to `NbtCompression::detect`.
+fn decompress(bytes: Vec<u8>) -> Result<Vec<u8>, McError> {
+    let mut decompressed = Vec::new();
+    match NbtCompression::detect(&bytes) {
+        NbtCompression::None => return Ok(bytes),
+        NbtCompression::Gzip => GzDecoder::new(bytes.as_slice()).read_to_end(&mut decompressed),
+        NbtCompression::Zlib => ZlibDecoder::new(bytes.as_slice()).read_to_end(&mut decompressed),
+    }
+    .map_err(|e| McError::Compression(format!("Could not decompress NBT file: {}", e)))?;
+
+    Ok(decompressed)
+}
+
+/// An NBT root tag without a name, as sent on the network since protocol 1.20.2.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NetworkNbt(pub Tag);
+
+/// Runs `write` on `buf`, truncating `buf` back to its length before if it fails.
+fn write_or_restore(buf: &mut Vec<u8>, write: impl FnOnce(&mut Vec<u8>) -> Result<(), McError>) -> Result<(), McError> {
+    let start = buf.len();
+    write(buf).inspect_err(|_| buf.truncate(start))
+}
+
+/// Appends `nbt` with a named root to `buf`, or a lone `TAG_End` for `None`. If the
+/// tag tree cannot be encoded, `buf` is left unchanged.
+/// # Errors
+/// See `binary::write_named`.
+pub(crate) fn write_named_or_end(buf: &mut Vec<u8>, nbt: Option<&Nbt>) -> Result<(), McError> {
+    match nbt {
+        Some(nbt) => write_or_restore(buf, |buf| binary::write_named(buf, &nbt.name, &nbt.tag)),
+        None => {
+            buf.push(TAG_END);
+            Ok(())
+        }
+    }
+}
+
+/// Appends `tag` as a nameless root to `buf`, or a lone `TAG_End` for `None`. If the
+/// tag tree cannot be encoded, `buf` is left unchanged.
+/// # Errors
+/// See `binary::write_named`.
+pub(crate) fn write_nameless_or_end(buf: &mut Vec<u8>, tag: Option<&Tag>) -> Result<(), McError> {
+    match tag {
+        Some(tag) => write_or_restore(buf, |buf| binary::write_nameless(buf, tag)),
+        None => {
+            buf.push(TAG_END);
+            Ok(())
+        }
+    }
+}
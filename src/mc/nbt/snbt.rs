@@ -0,0 +1,363 @@
+//! Stringified NBT (SNBT), the text format used by commands and data packs,
+//! e.g. `{name:"Makoto",level:3b,pos:[I;1,64,-2]}`.
+
+use std::{fmt, str::FromStr};
+
+use crate::mc::error::McError;
+
+use super::{Compound, Tag};
+
+/// Parses a single SNBT value. Surrounding whitespace is ignored.
+/// # Errors
+/// This function will return a `Malformed` error describing the position of
+/// the first syntax error, or of trailing characters after the value.
+pub fn parse(input: &str) -> Result<Tag, McError> {
+    let mut parser = Parser { input, pos: 0 };
+    let tag = parser.parse_value(0)?;
+    parser.skip_whitespace();
+    if parser.pos != input.len() {
+        return Err(parser.error("trailing characters after value"));
+    }
+    Ok(tag)
+}
+
+impl FromStr for Tag {
+    type Err = McError;
+
+    /// Parses SNBT. See `snbt::parse`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse(s)
+    }
+}
+
+/// Writes `tag` as SNBT.
+pub fn write_tag<W: fmt::Write>(w: &mut W, tag: &Tag) -> fmt::Result {
+    match tag {
+        Tag::Byte(v) => write!(w, "{}b", v),
+        Tag::Short(v) => write!(w, "{}s", v),
+        Tag::Int(v) => write!(w, "{}", v),
+        Tag::Long(v) => write!(w, "{}L", v),
+        Tag::Float(v) => write!(w, "{:?}f", v),
+        Tag::Double(v) => write!(w, "{:?}d", v),
+        Tag::ByteArray(array) => write_array(w, "B", array.iter().map(|v| format!("{}b", v))),
+        Tag::String(s) => write_quoted(w, s),
+        Tag::List(list) => {
+            w.write_char('[')?;
+            for (i, element) in list.iter().enumerate() {
+                if i > 0 {
+                    w.write_char(',')?;
+                }
+                write_tag(w, element)?;
+            }
+            w.write_char(']')
+        }
+        Tag::Compound(compound) => write_compound(w, compound),
+        Tag::IntArray(array) => write_array(w, "I", array.iter().map(|v| v.to_string())),
+        Tag::LongArray(array) => write_array(w, "L", array.iter().map(|v| format!("{}L", v))),
+    }
+}
+
+/// Writes `compound` as SNBT.
+pub fn write_compound<W: fmt::Write>(w: &mut W, compound: &Compound) -> fmt::Result {
+    w.write_char('{')?;
+    for (i, (key, tag)) in compound.iter().enumerate() {
+        if i > 0 {
+            w.write_char(',')?;
+        }
+        if !key.is_empty() && key.chars().all(is_unquoted_char) {
+            w.write_str(key)?;
+        } else {
+            write_quoted(w, key)?;
+        }
+        w.write_char(':')?;
+        write_tag(w, tag)?;
+    }
+    w.write_char('}')
+}
+
+impl fmt::Display for Compound {
+    /// Formats the compound as SNBT.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_compound(f, self)
+    }
+}
+
+fn write_array<W: fmt::Write, I: Iterator<Item = String>>(w: &mut W, prefix: &str, values: I) -> fmt::Result {
+    write!(w, "[{};", prefix)?;
+    for (i, value) in values.enumerate() {
+        if i > 0 {
+            w.write_char(',')?;
+        }
+        w.write_str(&value)?;
+    }
+    w.write_char(']')
+}
+
+fn write_quoted<W: fmt::Write>(w: &mut W, s: &str) -> fmt::Result {
+    let quote = if s.contains('"') && !s.contains('\'') { '\'' } else { '"' };
+    w.write_char(quote)?;
+    for c in s.chars() {
+        if c == quote || c == '\\' {
+            w.write_char('\\')?;
+        }
+        w.write_char(c)?;
+    }
+    w.write_char(quote)
+}
+
+fn is_unquoted_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '+')
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, msg: &str) -> McError {
+        McError::invalid(format!("Invalid SNBT at position {}: {}", self.pos, msg))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.pos += c.len_utf8();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), McError> {
+        self.skip_whitespace();
+        if self.peek() == Some(expected) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", expected)))
+        }
+    }
+
+    fn parse_value(&mut self, depth: usize) -> Result<Tag, McError> {
+        if depth > super::binary::MAX_DEPTH {
+            return Err(self.error("nested too deeply"));
+        }
+
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => Ok(Tag::Compound(self.parse_compound(depth)?)),
+            Some('[') => self.parse_list_or_array(depth),
+            Some('"') | Some('\'') => Ok(Tag::String(self.parse_quoted()?)),
+            Some(_) => {
+                let token = self.parse_unquoted();
+                if token.is_empty() {
+                    return Err(self.error("expected a value"));
+                }
+                Ok(interpret_unquoted(token))
+            }
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn parse_compound(&mut self, depth: usize) -> Result<Compound, McError> {
+        self.expect('{')?;
+        let mut compound = Compound::new();
+
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Ok(compound);
+        }
+
+        loop {
+            self.skip_whitespace();
+            let key = match self.peek() {
+                Some('"') | Some('\'') => self.parse_quoted()?,
+                _ => match self.parse_unquoted() {
+                    "" => return Err(self.error("expected a key")),
+                    key => key.to_owned(),
+                },
+            };
+            self.expect(':')?;
+            let value = self.parse_value(depth + 1)?;
+            compound.insert(key, value);
+
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some('}') => {
+                    self.pos += 1;
+                    return Ok(compound);
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn parse_list_or_array(&mut self, depth: usize) -> Result<Tag, McError> {
+        self.expect('[')?;
+
+        let rest = &self.input[self.pos..];
+        let array_type = match rest.as_bytes() {
+            [t @ (b'B' | b'I' | b'L'), b';', ..] => Some(*t),
+            _ => None,
+        };
+
+        let mut elements = Vec::new();
+        if array_type.is_some() {
+            self.pos += 2;
+        }
+
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.pos += 1;
+        } else {
+            loop {
+                let element = self.parse_value(depth + 1)?;
+                if let Some(first) = elements.first() {
+                    if Tag::id(first) != element.id() {
+                        return Err(self.error("list elements must all be of the same type"));
+                    }
+                }
+                elements.push(element);
+
+                self.skip_whitespace();
+                match self.peek() {
+                    Some(',') => self.pos += 1,
+                    Some(']') => {
+                        self.pos += 1;
+                        break;
+                    }
+                    _ => return Err(self.error("expected ',' or ']'")),
+                }
+            }
+        }
+
+        match array_type {
+            None => Ok(Tag::List(elements)),
+            Some(b'B') => elements
+                .iter()
+                .map(|t| match t {
+                    Tag::Byte(v) => Ok(*v),
+                    _ => Err(self.error("byte arrays may only hold bytes")),
+                })
+                .collect::<Result<_, _>>()
+                .map(Tag::ByteArray),
+            Some(b'I') => elements
+                .iter()
+                .map(|t| match t {
+                    Tag::Int(v) => Ok(*v),
+                    _ => Err(self.error("int arrays may only hold ints")),
+                })
+                .collect::<Result<_, _>>()
+                .map(Tag::IntArray),
+            Some(_) => elements
+                .iter()
+                .map(|t| match t {
+                    Tag::Long(v) => Ok(*v),
+                    _ => Err(self.error("long arrays may only hold longs")),
+                })
+                .collect::<Result<_, _>>()
+                .map(Tag::LongArray),
+        }
+    }
+
+    fn parse_quoted(&mut self) -> Result<String, McError> {
+        let quote = self.peek().ok_or_else(|| self.error("expected a string"))?;
+        self.pos += 1;
+
+        let mut s = String::new();
+        let mut escaped = false;
+        while let Some(c) = self.peek() {
+            self.pos += c.len_utf8();
+            if escaped {
+                if c != quote && c != '\\' {
+                    return Err(self.error(&format!("invalid escape '\\{}'", c)));
+                }
+                s.push(c);
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == quote {
+                return Ok(s);
+            } else {
+                s.push(c);
+            }
+        }
+
+        Err(self.error("unterminated string"))
+    }
+
+    fn parse_unquoted(&mut self) -> &'a str {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if !is_unquoted_char(c) {
+                break;
+            }
+            self.pos += 1;
+        }
+        &self.input[start..self.pos]
+    }
+}
+
+/// Determines the type of an unquoted token from its shape and suffix, falling
+/// back to a string like the vanilla parser does.
+fn interpret_unquoted(token: &str) -> Tag {
+    match token {
+        "true" => return Tag::Byte(1),
+        "false" => return Tag::Byte(0),
+        _ => {}
+    }
+
+    let (body, suffix) = token.split_at(token.len() - 1);
+    let parsed = match suffix {
+        "b" | "B" if is_integer(body) => body.parse().ok().map(Tag::Byte),
+        "s" | "S" if is_integer(body) => body.parse().ok().map(Tag::Short),
+        "l" | "L" if is_integer(body) => body.parse().ok().map(Tag::Long),
+        "f" | "F" if is_float(body, false) => body.parse().ok().map(Tag::Float),
+        "d" | "D" if is_float(body, false) => body.parse().ok().map(Tag::Double),
+        _ if is_integer(token) => token.parse().ok().map(Tag::Int),
+        _ if is_float(token, true) => token.parse().ok().map(Tag::Double),
+        _ => None,
+    };
+
+    parsed.unwrap_or_else(|| Tag::String(token.to_owned()))
+}
+
+fn strip_sign(s: &str) -> &str {
+    s.strip_prefix(['-', '+']).unwrap_or(s)
+}
+
+fn is_integer(s: &str) -> bool {
+    let digits = strip_sign(s);
+    !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit())
+}
+
+fn is_float(s: &str, require_dot: bool) -> bool {
+    let s = strip_sign(s);
+    let (mantissa, exponent) = match s.find(['e', 'E']) {
+        Some(i) => (&s[..i], Some(&s[i + 1..])),
+        None => (s, None),
+    };
+
+    let (int_part, frac_part) = match mantissa.find('.') {
+        Some(i) => (&mantissa[..i], Some(&mantissa[i + 1..])),
+        None => (mantissa, None),
+    };
+    if require_dot && frac_part.is_none() {
+        return false;
+    }
+    let digits_ok = |d: &str| d.bytes().all(|b| b.is_ascii_digit());
+    if int_part.is_empty() && frac_part.is_none_or(str::is_empty) {
+        return false;
+    }
+    if !digits_ok(int_part) || !frac_part.is_none_or(digits_ok) {
+        return false;
+    }
+
+    exponent.is_none_or(is_integer)
+}
@@ -0,0 +1,184 @@
+//! Options for the TCP connection to a server, and the policy for re-establishing it.
+
+use std::{net::SocketAddr, thread, time::Duration};
+
+use super::error::McError;
+
+/// How a connection is established and tuned. Every option is off by default, i.e.,
+/// connecting and I/O wait as long as the operating system lets them.
+/// # Example
+/// ```no_run
+/// use std::time::Duration;
+/// use mcclient::mc::{
+///     connection::{Connection, OfflineConnection},
+///     options::{ConnectionOptions, ReconnectPolicy},
+/// };
+///
+/// let options = ConnectionOptions::new()
+///     .with_connect_timeout(Duration::from_secs(5))
+///     .with_read_timeout(Duration::from_secs(30))
+///     .with_nodelay(true)
+///     .with_reconnect_policy(ReconnectPolicy::exponential(5, Duration::from_millis(250), Duration::from_secs(8)));
+/// let mut connection = OfflineConnection::connect_with("localhost", 25565, options)?;
+/// # Ok::<(), mcclient::mc::error::McError>(())
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConnectionOptions {
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    nodelay: bool,
+    keepalive: Option<Duration>,
+    bind_address: Option<SocketAddr>,
+    reconnect_policy: ReconnectPolicy,
+}
+
+impl ConnectionOptions {
+    pub fn new() -> Self {
+        ConnectionOptions::default()
+    }
+
+    /// Limits how long establishing the connection to each address of the server may take.
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Limits how long a read may wait for data. A read which times out fails with an
+    /// `Io` error of kind `WouldBlock` or `TimedOut`, depending on the platform.
+    pub fn with_read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Limits how long a write may wait for the server to accept data.
+    pub fn with_write_timeout(mut self, timeout: Duration) -> Self {
+        self.write_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets `TCP_NODELAY`, which sends small packets at once instead of batching them.
+    /// The vanilla client enables it.
+    pub fn with_nodelay(mut self, nodelay: bool) -> Self {
+        self.nodelay = nodelay;
+        self
+    }
+
+    /// Enables TCP keepalive, probing the server after the connection is idle for `idle`.
+    pub fn with_keepalive(mut self, idle: Duration) -> Self {
+        self.keepalive = Some(idle);
+        self
+    }
+
+    /// Binds the local end of the connection to `address` before connecting, e.g., to
+    /// pick the network interface. Use port 0 to let the system pick the port.
+    pub fn with_bind_address(mut self, address: SocketAddr) -> Self {
+        self.bind_address = Some(address);
+        self
+    }
+
+    /// Sets the policy `Connection::reset` follows when the server cannot be reached.
+    pub fn with_reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect_policy = policy;
+        self
+    }
+
+    pub fn connect_timeout(&self) -> Option<Duration> {
+        self.connect_timeout
+    }
+
+    pub fn read_timeout(&self) -> Option<Duration> {
+        self.read_timeout
+    }
+
+    pub fn write_timeout(&self) -> Option<Duration> {
+        self.write_timeout
+    }
+
+    pub fn nodelay(&self) -> bool {
+        self.nodelay
+    }
+
+    pub fn keepalive(&self) -> Option<Duration> {
+        self.keepalive
+    }
+
+    pub fn bind_address(&self) -> Option<SocketAddr> {
+        self.bind_address
+    }
+
+    pub fn reconnect_policy(&self) -> &ReconnectPolicy {
+        &self.reconnect_policy
+    }
+}
+
+/// How often, and how patiently, a connection is re-established. The wait before
+/// each retry doubles, starting from `initial_backoff`, up to `max_backoff`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+    /// The number of connection attempts, including the first. At least one attempt
+    /// is always made.
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl ReconnectPolicy {
+    /// Makes a single attempt.
+    pub fn none() -> Self {
+        ReconnectPolicy {
+            max_attempts: 1,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+        }
+    }
+
+    /// Makes up to `max_attempts` attempts with exponential backoff.
+    pub fn exponential(max_attempts: u32, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        ReconnectPolicy {
+            max_attempts,
+            initial_backoff,
+            max_backoff,
+        }
+    }
+
+    /// Gets the wait before retry `retry`, counting from 1 for the second attempt.
+    /// # Example
+    /// ```
+    /// use std::time::Duration;
+    /// use mcclient::mc::options::ReconnectPolicy;
+    ///
+    /// let policy = ReconnectPolicy::exponential(5, Duration::from_secs(1), Duration::from_secs(5));
+    /// assert_eq!(policy.backoff(1), Duration::from_secs(1));
+    /// assert_eq!(policy.backoff(3), Duration::from_secs(4));
+    /// assert_eq!(policy.backoff(4), Duration::from_secs(5));
+    /// ```
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = 2_u32.saturating_pow(retry.saturating_sub(1));
+        self.initial_backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+
+    /// Calls `connect` until it succeeds or the attempts run out, waiting between
+    /// attempts. Only `Io` errors are retried, since no other error goes away by
+    /// trying again.
+    /// # Errors
+    /// This function will return the error of the last attempt.
+    pub fn retry<T>(&self, mut connect: impl FnMut() -> Result<T, McError>) -> Result<T, McError> {
+        let mut retry = 0;
+        loop {
+            match connect() {
+                Err(McError::Io(_)) if retry + 1 < self.max_attempts => {
+                    retry += 1;
+                    thread::sleep(self.backoff(retry));
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy::none()
+    }
+}
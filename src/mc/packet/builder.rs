@@ -1,13 +1,14 @@
-// TODO: Add from_bytes to MCType instead of implementing for individual types uniquely
-
-// TODO: Rewrite MCString to simply build string bytes
-//       from a string view.
-
-use crate::mc::mctypes::{MCString, VarInt, MCType};
+use crate::mc::{
+    error::McError,
+    item::{ItemStack, SlotFormat},
+    mctypes::{self, Angle, MCString, MCType, Position, StringError, VarInt, VarLong},
+    nbt::{self, NetworkNbt, Nbt},
+};
 
 /// A byte vec builder which helps construct a Minecraft packet that
 /// corresponds to the specification of each data type defined by the
 /// Minecraft protocol.
+#[derive(Default)]
 pub struct PacketBytesBuilder {
     byte_buffer: Vec<u8>,
 }
@@ -23,26 +24,40 @@ impl PacketBytesBuilder {
         }
     }
 
-    /// Appends a UUID encoded in Big Endian to the buffer.
+    /// Constructs a packet builder which appends to `buffer`, e.g. a buffer reused
+    /// across packets. `build` returns it with the appended bytes.
+    pub fn with_buffer(buffer: Vec<u8>) -> Self {
+        PacketBytesBuilder {
+            byte_buffer: buffer,
+        }
+    }
+
+    /// Appends a UUID encoded as a 128-bit Big Endian integer to the buffer.
     pub fn append_uuid(mut self, uuid: &uuid::Uuid) -> Self {
-        let uuid_pair = uuid.as_u64_pair();
-        self.byte_buffer
-            .extend_from_slice(&uuid_pair.1.to_be_bytes());
-        self.byte_buffer
-            .extend_from_slice(&uuid_pair.0.to_be_bytes());
+        self.byte_buffer.extend_from_slice(uuid.as_bytes());
 
         self
     }
 
-    // TODO: Why not just make this take a Into<MCString> ? MCStrings end up doing
-    //       redundant copies.
-    /// Appends a string as a MC-encoded string to the buffer.
-    pub fn append_string<S: Into<String>>(mut self, to_string: S) -> Self {
-        self.byte_buffer.extend(MCString::from(to_string.into()).to_bytes());
+    /// Appends a string as a MC-encoded string to the buffer. The string is
+    /// copied straight into the buffer, so `&str` and `String` work equally well.
+    /// Use `try_append_string` for fields with a maximum length.
+    pub fn append_string<S: AsRef<str>>(mut self, string: S) -> Self {
+        mctypes::write_str(&mut self.byte_buffer, string.as_ref());
 
         self
     }
 
+    /// Appends a string of at most `max_len` UTF-16 code units to the buffer.
+    /// # Errors
+    /// This function will return `StringError::TooLong` if the string is longer
+    /// than `max_len`.
+    pub fn try_append_string<S: AsRef<str>>(self, string: S, max_len: usize) -> Result<Self, StringError> {
+        let string = MCString::new(string.as_ref(), max_len)?;
+
+        Ok(self.append(&string))
+    }
+
     /// Appends a bool encoded as a single byte to the buffer.
     pub fn append_bool(mut self, value: bool) -> Self {
         self.byte_buffer.push(value as u8);
@@ -57,9 +72,114 @@ impl PacketBytesBuilder {
         self
     }
 
+    /// Appends a MC-encoded VarLong to the buffer.
+    pub fn append_varlong(mut self, value: &VarLong) -> Self {
+        self.byte_buffer.extend(value.bytes());
+
+        self
+    }
+
+    /// Appends a signed byte to the buffer.
+    pub fn append_i8(mut self, value: i8) -> Self {
+        self.byte_buffer.push(value as u8);
+
+        self
+    }
+
+    /// Appends an unsigned byte to the buffer.
+    pub fn append_u8(mut self, value: u8) -> Self {
+        self.byte_buffer.push(value);
+
+        self
+    }
+
+    /// Appends an `i16` encoded in Big Endian to the buffer.
+    pub fn append_i16(mut self, value: i16) -> Self {
+        self.byte_buffer.extend(value.to_be_bytes());
+
+        self
+    }
+
     /// Appends a `u16` encoded in Big Endian to the buffer.
     pub fn append_u16(mut self, value: u16) -> Self {
-        self.byte_buffer.extend(value.to_be_bytes().to_vec());
+        self.byte_buffer.extend(value.to_be_bytes());
+
+        self
+    }
+
+    /// Appends an `i32` encoded in Big Endian to the buffer.
+    pub fn append_i32(mut self, value: i32) -> Self {
+        self.byte_buffer.extend(value.to_be_bytes());
+
+        self
+    }
+
+    /// Appends an `i64` encoded in Big Endian to the buffer.
+    pub fn append_i64(mut self, value: i64) -> Self {
+        self.byte_buffer.extend(value.to_be_bytes());
+
+        self
+    }
+
+    /// Appends an `f32` encoded in Big Endian to the buffer.
+    pub fn append_f32(mut self, value: f32) -> Self {
+        self.byte_buffer.extend(value.to_be_bytes());
+
+        self
+    }
+
+    /// Appends an `f64` encoded in Big Endian to the buffer.
+    pub fn append_f64(mut self, value: f64) -> Self {
+        self.byte_buffer.extend(value.to_be_bytes());
+
+        self
+    }
+
+    /// Appends an angle encoded as a single byte to the buffer.
+    pub fn append_angle(mut self, value: Angle) -> Self {
+        self.byte_buffer.push(value.0);
+
+        self
+    }
+
+    /// Appends a block position packed into a 64-bit integer to the buffer.
+    pub fn append_position(mut self, value: &Position) -> Self {
+        self.byte_buffer.extend(value.to_packed().to_be_bytes());
+
+        self
+    }
+
+    /// Appends NBT with a named root to the buffer, or a lone `TAG_End` for `None`.
+    /// This is the format used by protocol versions before 1.20.2.
+    /// # Errors
+    /// This function will return a `Malformed` error if the tag tree cannot be
+    /// encoded, see `nbt::binary::write_named`.
+    pub fn try_append_nbt(mut self, nbt: Option<&Nbt>) -> Result<Self, McError> {
+        nbt::write_named_or_end(&mut self.byte_buffer, nbt)?;
+
+        Ok(self)
+    }
+
+    /// Appends NBT with a nameless root to the buffer, or a lone `TAG_End` for `None`.
+    /// This is the format used by protocol 1.20.2 and later.
+    /// # Errors
+    /// See `try_append_nbt`.
+    pub fn try_append_network_nbt(mut self, nbt: Option<&NetworkNbt>) -> Result<Self, McError> {
+        nbt::write_nameless_or_end(&mut self.byte_buffer, nbt.map(|nbt| &nbt.0))?;
+
+        Ok(self)
+    }
+
+    /// Appends a Slot encoded in `format` to the buffer.
+    /// # Errors
+    /// See `ItemStack::write_with`.
+    pub fn try_append_slot(self, item: &ItemStack, format: SlotFormat) -> Result<Self, McError> {
+        item.write_with(self, format)
+    }
+
+    /// Appends any `MCType` to the buffer using its protocol encoding.
+    pub fn append<T: MCType>(mut self, value: &T) -> Self {
+        value.write_to(&mut self.byte_buffer);
 
         self
     }
@@ -0,0 +1,64 @@
+use crate::mc::{
+    error::McError,
+    packet::{packet_ids, reader::BufferedPacketReader, ClientboundRawPacket, ConnectionState, InboundPacket, Packet},
+};
+
+/// The protocol version from which Encryption Request ends with the should authenticate
+/// flag, 1.20.5.
+pub const SHOULD_AUTHENTICATE_PROTOCOL: i32 = 766;
+
+/// Sent by online-mode servers to start the encryption handshake.
+/// <https://wiki.vg/Protocol_Encryption>
+#[derive(Debug, Packet)]
+#[packet(id = packet_ids::clientbound::ENCRYPTION_REQUEST, state = Login, direction = Clientbound)]
+pub struct EncryptionRequest {
+    /// Always empty on vanilla servers, but still part of the server hash.
+    #[packet(max_len = 20)]
+    pub server_id: String,
+    /// The server's RSA public key, DER-encoded.
+    #[packet(length_prefixed)]
+    pub public_key: Vec<u8>,
+    #[packet(length_prefixed)]
+    pub verify_token: Vec<u8>,
+    /// Whether the client must join the server on the session server, sent from 1.20.5.
+    /// It depends on the protocol version, so it is only read by `from_data_with`, and
+    /// written if present.
+    #[packet(condition = false)]
+    pub should_authenticate: Option<bool>,
+}
+
+impl EncryptionRequest {
+    /// Decodes the packet as sent by `protocol_version`.
+    /// # Errors
+    /// This function will return a `Malformed` error if the packet data is ill-formed.
+    pub fn from_data_with(packet: &ClientboundRawPacket, protocol_version: i32) -> Result<Self, McError> {
+        if protocol_version < SHOULD_AUTHENTICATE_PROTOCOL {
+            return EncryptionRequest::from_data(packet);
+        }
+
+        let in_packet = |e: McError| e.in_packet(ConnectionState::Login, packet_ids::clientbound::ENCRYPTION_REQUEST);
+        let (flag, data) = packet
+            .data
+            .split_last()
+            .ok_or_else(|| in_packet(McError::invalid("Encryption Request is empty")))?;
+        let should_authenticate = BufferedPacketReader::new(std::slice::from_ref(flag))
+            .read_bool()
+            .map_err(|e| in_packet(e.in_field("should_authenticate")))?;
+
+        // The flag is the last field, so the rest decodes like any other version.
+        let request = EncryptionRequest::from_data(&ClientboundRawPacket {
+            header: packet.header,
+            data: data.to_vec(),
+        })?;
+        Ok(EncryptionRequest {
+            should_authenticate: Some(should_authenticate),
+            ..request
+        })
+    }
+
+    /// Returns `true` if the client must join the server on the session server before
+    /// answering, which servers before 1.20.5 always require.
+    pub fn should_authenticate(&self) -> bool {
+        self.should_authenticate.unwrap_or(true)
+    }
+}
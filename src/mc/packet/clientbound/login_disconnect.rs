@@ -0,0 +1,12 @@
+use crate::mc::{
+    packet::{packet_ids, Packet},
+    text::TextComponent,
+};
+
+/// Sent by the server to refuse a login, e.g., because the server is full or the
+/// client is banned.
+#[derive(Debug, Packet)]
+#[packet(id = packet_ids::clientbound::LOGIN_DISCONNECT, state = Login, direction = Clientbound)]
+pub struct LoginDisconnect {
+    pub reason: TextComponent,
+}
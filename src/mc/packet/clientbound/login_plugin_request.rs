@@ -0,0 +1,17 @@
+use crate::mc::{
+    mctypes::Identifier,
+    packet::{packet_ids, Packet},
+};
+
+/// A custom query on a plugin `channel`, which the client must answer with a
+/// Login Plugin Response carrying the same `message_id`.
+/// <https://wiki.vg/Protocol#Login_Plugin_Request>
+#[derive(Debug, Packet)]
+#[packet(id = packet_ids::clientbound::LOGIN_PLUGIN_REQUEST, state = Login, direction = Clientbound)]
+pub struct LoginPluginRequest {
+    #[packet(varint)]
+    pub message_id: i32,
+    pub channel: Identifier,
+    #[packet(remaining)]
+    pub data: Vec<u8>,
+}
@@ -1 +1,87 @@
-pub struct LoginSuccess {}
+use uuid::Uuid;
+
+use crate::mc::{
+    error::McError,
+    mctypes::{str_size, MCType, MAX_USERNAME_LENGTH},
+    packet::{packet_ids, reader::BufferedPacketReader, ClientboundRawPacket, ConnectionState, InboundPacket, Packet},
+};
+
+/// A property of the player's profile, e.g., the skin under `textures`. Online-mode
+/// servers forward the properties signed by the session server.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Property {
+    pub name: String,
+    pub value: String,
+    pub signature: Option<String>,
+}
+
+impl MCType for Property {
+    fn write_to(&self, buf: &mut Vec<u8>) {
+        self.name.write_to(buf);
+        self.value.write_to(buf);
+        buf.push(self.signature.is_some() as u8);
+        if let Some(signature) = &self.signature {
+            signature.write_to(buf);
+        }
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, McError> {
+        Self::read_from(&mut BufferedPacketReader::new(bytes))
+    }
+
+    fn read_from(reader: &mut BufferedPacketReader) -> Result<Self, McError> {
+        let name = reader.read()?;
+        let value = reader.read()?;
+        let signature = if reader.read_bool()? { Some(reader.read()?) } else { None };
+
+        Ok(Property { name, value, signature })
+    }
+
+    fn size(&self) -> i32 {
+        str_size(&self.name) + str_size(&self.value) + 1 + self.signature.as_deref().map_or(0, str_size)
+    }
+}
+
+/// Ends the Login state. The server may assign a different UUID and username
+/// than the client asked for.
+#[derive(Debug, Packet)]
+#[packet(id = packet_ids::clientbound::LOGIN_SUCCESS, state = Login, direction = Clientbound)]
+pub struct LoginSuccess {
+    pub uuid: Uuid,
+    #[packet(max_len = MAX_USERNAME_LENGTH)]
+    pub username: String,
+    #[packet(length_prefixed)]
+    pub properties: Vec<Property>,
+}
+
+/// The protocol versions whose Login Success ends with the strict error handling flag,
+/// 1.20.5 to 1.21.1.
+pub const STRICT_ERROR_HANDLING_PROTOCOLS: std::ops::RangeInclusive<i32> = 766..=767;
+
+impl LoginSuccess {
+    /// Decodes the packet as sent by `protocol_version`. The strict error handling flag
+    /// of 1.20.5 and 1.21 only tells the vanilla client how to treat bad packets, so it
+    /// is read and ignored.
+    /// # Errors
+    /// This function will return a `Malformed` error if the packet data is ill-formed.
+    pub fn from_data_with(packet: &ClientboundRawPacket, protocol_version: i32) -> Result<Self, McError> {
+        if !STRICT_ERROR_HANDLING_PROTOCOLS.contains(&protocol_version) {
+            return LoginSuccess::from_data(packet);
+        }
+
+        let in_packet = |e: McError| e.in_packet(ConnectionState::Login, packet_ids::clientbound::LOGIN_SUCCESS);
+        let (flag, data) = packet
+            .data
+            .split_last()
+            .ok_or_else(|| in_packet(McError::invalid("Login Success is empty")))?;
+        BufferedPacketReader::new(std::slice::from_ref(flag))
+            .read_bool()
+            .map_err(|e| in_packet(e.in_field("strict_error_handling")))?;
+
+        // The flag is the last field, so the rest decodes like any other version.
+        LoginSuccess::from_data(&ClientboundRawPacket {
+            header: packet.header,
+            data: data.to_vec(),
+        })
+    }
+}
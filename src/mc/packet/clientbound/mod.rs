@@ -1,3 +1,7 @@
+pub mod encryption_request;
+pub mod login_disconnect;
+pub mod login_plugin_request;
 pub mod login_success;
 pub mod ping_response;
+pub mod set_compression;
 pub mod status_response;
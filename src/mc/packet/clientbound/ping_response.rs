@@ -1,2 +1,8 @@
-#[derive(Debug)]
-pub struct PingResponse {}
+use crate::mc::packet::{packet_ids, Packet};
+
+/// Answers a Ping Request, echoing its payload.
+#[derive(Debug, Packet)]
+#[packet(id = packet_ids::clientbound::PING_RESPONSE, state = Status, direction = Clientbound)]
+pub struct PingResponse {
+    pub payload: i64,
+}
@@ -0,0 +1,10 @@
+use crate::mc::packet::{packet_ids, Packet};
+
+/// Enables compression for the rest of the connection. Packets of at least
+/// `threshold` bytes are compressed; a negative threshold disables compression.
+#[derive(Debug, Packet)]
+#[packet(id = packet_ids::clientbound::SET_COMPRESSION, state = Login, direction = Clientbound)]
+pub struct SetCompression {
+    #[packet(varint)]
+    pub threshold: i32,
+}
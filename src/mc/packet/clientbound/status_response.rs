@@ -1,20 +1,26 @@
-use std::io;
-
 use crate::mc::{
-    mctypes::JsonResponse,
-    packet::{ClientboundRawPacket, InboundPacket, packet_ids},
+    error::McError,
+    mctypes::MAX_STRING_LENGTH,
+    packet::{state::Status, ClientboundPacket, ClientboundRawPacket, ConnectionState, InboundPacket, packet_ids},
+    status::ServerStatus,
 };
 
+#[derive(Debug)]
 pub struct StatusResponse {
-    pub json_response: JsonResponse,
+    pub status: ServerStatus,
 }
 
-#[allow(unused)]
 impl InboundPacket for StatusResponse {
-    fn from_data(packet: &ClientboundRawPacket) -> Result<Self, io::Error> {
-        let res = JsonResponse::from_bytes(&packet.data).expect("failed here");
+    fn from_data(packet: &ClientboundRawPacket) -> Result<Self, McError> {
+        let in_packet = |e: McError| e.in_packet(ConnectionState::Status, packet_ids::clientbound::STATUS_RESPONSE);
+        let in_field = |e: McError| in_packet(e.in_field("json_response"));
+
+        let mut reader = packet.reader();
+        let json = reader.read_string(MAX_STRING_LENGTH).map_err(in_field)?;
+        reader.finish().map_err(in_packet)?;
+
         Ok(StatusResponse {
-            json_response: res,
+            status: ServerStatus::from_json(&json).map_err(in_field)?,
         })
     }
 
@@ -22,3 +28,5 @@ impl InboundPacket for StatusResponse {
         packet_ids::clientbound::STATUS_RESPONSE
     }
 }
+
+impl ClientboundPacket<Status> for StatusResponse {}
@@ -1,20 +1,63 @@
-use std::io;
+use self::reader::BufferedPacketReader;
 
-use self::builder::PacketBytesBuilder;
-
-use super::mctypes::VarInt;
+use super::{
+    error::McError,
+    mctypes::{MCType, VarInt},
+};
 
 pub mod clientbound;
 pub mod packet_ids;
 pub mod serverbound;
 pub mod builder;
+pub mod reader;
+pub mod state;
+
+pub use mcclient_derive::Packet;
+
+/// The state of a connection, which determines how packet IDs are interpreted.
+/// <https://wiki.vg/Protocol#Definitions>
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ConnectionState {
+    Handshaking,
+    Status,
+    Login,
+    Configuration,
+    Play,
+}
+
+/// The direction a packet travels in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PacketDirection {
+    Serverbound,
+    Clientbound,
+}
+
+/// Static information about a packet type. This is normally implemented with
+/// `#[derive(Packet)]`, which also implements `OutboundPacket` and `InboundPacket`
+/// from the field types. See the `mcclient-derive` crate for the attributes.
+pub trait Packet {
+    const ID: i32;
+    const STATE: ConnectionState;
+    const DIRECTION: PacketDirection;
+}
 
 /// Interfaces serverbound packets. Structs implementing this trait are
 /// expected to be mcproto-compliant packets; transfering malformatted
 /// packets will result in undefined behavior.
 pub trait OutboundPacket {
     /// Serializes the internal packet data into an array of bytes.
-    fn to_bytes(&self) -> Vec<u8>;
+    /// # Errors
+    /// This function will return a `Malformed` error if a field cannot be encoded,
+    /// e.g., a string is longer than the field permits.
+    fn to_bytes(&self) -> Result<Vec<u8>, McError>;
+    /// Appends the serialized packet data to `buf`. The default implementation
+    /// copies the result of `to_bytes`; derived packets encode straight into `buf`.
+    /// # Errors
+    /// See `to_bytes`. On error, `buf` may hold part of the packet.
+    fn write_to(&self, buf: &mut Vec<u8>) -> Result<(), McError> {
+        buf.extend(self.to_bytes()?);
+        Ok(())
+    }
     /// Retrieves the ID of this packet. Largely references compile-time constants.
     fn packet_id(&self) -> i32;
 }
@@ -29,19 +72,29 @@ pub trait InboundPacket: Sized {
     /// # Errors
     /// This function may return an error when the provided packet data
     /// is ill-formed or the internal types are not properly parsed.
-    fn from_data(packet: &ClientboundRawPacket) -> Result<Self, io::Error>;
+    fn from_data(packet: &ClientboundRawPacket) -> Result<Self, McError>;
 
     /// Retrieves the ID of this inbound packet.
     fn packet_id(&self) -> i32;
 }
 
+/// Marks serverbound packets which may be sent in the state `S`. This is implemented
+/// by `#[derive(Packet)]`.
+pub trait ServerboundPacket<S: state::ProtocolState>: OutboundPacket {}
+
+/// Marks clientbound packets which may be received in the state `S`. This is implemented
+/// by `#[derive(Packet)]`.
+pub trait ClientboundPacket<S: state::ProtocolState>: InboundPacket {}
+
 pub struct OutboundPacketBuffer {
     packet_data: Vec<u8>
 }
 
-impl From<&dyn OutboundPacket> for OutboundPacketBuffer {
-    fn from(value: &dyn OutboundPacket) -> Self {
-        OutboundPacketBuffer { packet_data: serialize_packet(value) }
+impl TryFrom<&dyn OutboundPacket> for OutboundPacketBuffer {
+    type Error = McError;
+
+    fn try_from(value: &dyn OutboundPacket) -> Result<Self, Self::Error> {
+        Ok(OutboundPacketBuffer { packet_data: serialize_packet(value)? })
     }
 }
 
@@ -53,18 +106,32 @@ impl OutboundPacketBuffer {
 
 /// Serialize a serverbound packet to be sent to a server.
 /// Packet structure: https://wiki.vg/Protocol#Packet_format
-fn serialize_packet(data: &dyn OutboundPacket) -> Vec<u8> {
-    let packet_id_serialized = VarInt::from_i32(data.packet_id());
-    let packet_data_bytes = data.to_bytes();
-    let packet_size = packet_id_serialized.len() + packet_data_bytes.len() as i32;
+fn serialize_packet(data: &dyn OutboundPacket) -> Result<Vec<u8>, McError> {
+    let mut bytes = Vec::new();
+    write_packet(data, &mut bytes)?;
+    Ok(bytes)
+}
 
-    PacketBytesBuilder::new()
-        .append_varint(&VarInt::from_i32(packet_size))
-        .append_varint(&packet_id_serialized)
-        .append_bytes(&packet_data_bytes)
-    .build()
+/// Appends `packet` to `buf` with its length prefix and packet ID. The packet is
+/// encoded in place, so the body is never held in a buffer of its own.
+/// # Errors
+/// See `OutboundPacket::to_bytes`. On error, `buf` is left unchanged.
+pub fn write_packet(packet: &dyn OutboundPacket, buf: &mut Vec<u8>) -> Result<(), McError> {
+    let start = buf.len();
+    VarInt::from(packet.packet_id()).write_to(buf);
+    if let Err(e) = packet.write_to(buf) {
+        buf.truncate(start);
+        return Err(e);
+    }
+
+    // The length is only known once the body is written, so it is inserted in
+    // front of it, which moves the body in place rather than copying it.
+    let len = VarInt::from((buf.len() - start) as i32);
+    buf.splice(start..start, len.bytes().iter().copied());
+    Ok(())
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct MCPacketHeader {
     pub size: i32,
     pub id: i32,
@@ -73,9 +140,9 @@ pub struct MCPacketHeader {
 /// Attempts to parse a packet header from bytes, consuming the `VarInt` elements of
 /// the `Vec<u8>` passed.
 /// # Errors
-/// This function may return an `InvalidData` error if the `VarInt` bytes cannot be
+/// This function may return a `Malformed` error if the `VarInt` bytes cannot be
 /// properly parsed.
-fn read_packet_header(bytes: &mut Vec<u8>) -> Result<MCPacketHeader, io::Error> {
+fn read_packet_header(bytes: &mut Vec<u8>) -> Result<MCPacketHeader, McError> {
     let packet_size = VarInt::from_vec_front(bytes)?;
     let packet_id = VarInt::from_vec_front(bytes)?;
 
@@ -98,15 +165,16 @@ impl MCPacketHeader {
     /// the packet header and returning a parsed packet header object, or
     /// an error.
     /// # Errors
-    /// This function may return an `InvalidData` error if the `VarInt` bytes cannot
+    /// This function may return a `Malformed` error if the `VarInt` bytes cannot
     /// be properly parsed.
-    pub fn from_bytes(bytes: &mut Vec<u8>) -> Result<Self, io::Error> {
+    pub fn from_bytes(bytes: &mut Vec<u8>) -> Result<Self, McError> {
         read_packet_header(bytes)
     }
 }
 
 // A structured container for a Minecraft network packet. This is primarily
 // used to box and parse incoming packets.
+#[derive(Debug)]
 pub struct ClientboundRawPacket {
     pub header: MCPacketHeader,
     pub data: Vec<u8>,
@@ -115,12 +183,62 @@ pub struct ClientboundRawPacket {
 impl ClientboundRawPacket {
     /// Constructs a Minecraft packet object from a set of bytes, consuming the `bytes` passed.
     /// # Errors
-    /// This function will return `io::Error` if the bytes cannot be properly parsed.
-    pub fn from_bytes(bytes: &mut Vec<u8>) -> Result<ClientboundRawPacket, io::Error> {
+    /// This function will return a `Malformed` error if the bytes cannot be properly parsed.
+    pub fn from_bytes(bytes: &mut Vec<u8>) -> Result<ClientboundRawPacket, McError> {
         let header = MCPacketHeader::from_bytes(bytes)?;
         Ok(ClientboundRawPacket {
             header,
             data: std::mem::take(bytes),
         })
     }
+
+    /// Constructs a Minecraft packet object from a single frame, i.e., the packet ID
+    /// followed by the packet data, without the leading length prefix.
+    /// # Errors
+    /// This function will return a `Malformed` error if the packet ID cannot be parsed.
+    pub fn from_frame(mut frame: Vec<u8>) -> Result<ClientboundRawPacket, McError> {
+        if frame.is_empty() {
+            return Err(McError::invalid("Empty packet frame"));
+        }
+
+        let size = frame.len() as i32;
+        let mut reader = BufferedPacketReader::new(&frame);
+        let id = reader.read_varint()?;
+        let id_len = reader.position();
+        frame.drain(..id_len);
+
+        Ok(ClientboundRawPacket {
+            header: MCPacketHeader { size, id },
+            data: frame,
+        })
+    }
+
+    /// Creates a reader positioned at the start of the packet data, i.e., just
+    /// after the packet ID.
+    pub fn reader(&self) -> BufferedPacketReader<'_> {
+        BufferedPacketReader::new(&self.data)
+    }
+}
+
+/// A serverbound packet encoded ahead of time. This carries packets whose ID or
+/// layout depends on the protocol version, which a derived packet cannot express.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServerboundRawPacket {
+    pub id: i32,
+    pub data: Vec<u8>,
+}
+
+impl OutboundPacket for ServerboundRawPacket {
+    fn to_bytes(&self) -> Result<Vec<u8>, McError> {
+        Ok(self.data.clone())
+    }
+
+    fn write_to(&self, buf: &mut Vec<u8>) -> Result<(), McError> {
+        buf.extend_from_slice(&self.data);
+        Ok(())
+    }
+
+    fn packet_id(&self) -> i32 {
+        self.id
+    }
 }
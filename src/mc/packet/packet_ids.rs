@@ -1,9 +1,19 @@
 pub mod serverbound {
     pub const HANDSHAKE_PACKET_ID: i32 =        0x00;
     pub const STATUS_REQUEST: i32 =             0x00;
+    pub const PING_REQUEST: i32 =               0x01;
     pub const LOGIN_START: i32 =                0x00;
+    pub const ENCRYPTION_RESPONSE: i32 =        0x01;
+    pub const LOGIN_PLUGIN_RESPONSE: i32 =      0x02;
+    pub const LOGIN_ACKNOWLEDGED: i32 =         0x03;
 }
 
 pub mod clientbound {
     pub const STATUS_RESPONSE: i32 =            0x00;
+    pub const PING_RESPONSE: i32 =              0x01;
+    pub const LOGIN_DISCONNECT: i32 =           0x00;
+    pub const ENCRYPTION_REQUEST: i32 =         0x01;
+    pub const LOGIN_SUCCESS: i32 =              0x02;
+    pub const SET_COMPRESSION: i32 =            0x03;
+    pub const LOGIN_PLUGIN_REQUEST: i32 =       0x04;
 }
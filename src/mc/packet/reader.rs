@@ -0,0 +1,288 @@
+use uuid::Uuid;
+
+use crate::mc::{
+    error::{DataError, McError},
+    item::{ItemStack, SlotFormat},
+    metadata::EntityMetadata,
+    mctypes::{check_string_length, MCType, Position, StringError},
+    nbt::{binary, NetworkNbt, Nbt},
+};
+
+/// A cursor over the body of a Minecraft packet which decodes the data types
+/// defined by the Minecraft protocol. Every read advances the cursor past the
+/// bytes it consumed, so fields are read in the order they appear in the packet.
+/// <br>
+/// This is the counterpart of `PacketBytesBuilder`.
+/// # Example
+/// ```
+/// use mcclient::mc::packet::reader::BufferedPacketReader;
+///
+/// let bytes = [0x05, b'h', b'e', b'l', b'l', b'o', 0x01];
+/// let mut reader = BufferedPacketReader::new(&bytes);
+/// assert_eq!(reader.read_string(16).unwrap(), "hello");
+/// assert!(reader.read_bool().unwrap());
+/// assert!(reader.finish().is_ok());
+/// ```
+pub struct BufferedPacketReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BufferedPacketReader<'a> {
+    /// Constructs a reader positioned at the start of `bytes`.
+    pub fn new(bytes: &'a [u8]) -> Self {
+        BufferedPacketReader { bytes, position: 0 }
+    }
+
+    /// Gets the number of bytes consumed so far.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Gets the number of bytes which have not been consumed yet.
+    pub fn remaining(&self) -> usize {
+        self.bytes.len() - self.position
+    }
+
+    /// Returns `true` if every byte has been consumed.
+    pub fn is_empty(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    /// Returns the bytes which have not been consumed yet, without consuming them.
+    pub fn peek_remaining(&self) -> &'a [u8] {
+        &self.bytes[self.position..]
+    }
+
+    /// Consumes the reader, checking that the whole packet body was read.
+    /// # Errors
+    /// This function will return a `DataError::TrailingBytes` error reporting how many
+    /// trailing bytes were left unread.
+    pub fn finish(self) -> Result<(), McError> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(McError::malformed(DataError::TrailingBytes {
+                count: self.remaining(),
+                position: self.position,
+            }))
+        }
+    }
+
+    /// Consumes `len` bytes, returning them as a slice of the underlying buffer.
+    /// # Errors
+    /// This function will return a `DataError::Truncated` error if fewer than `len`
+    /// bytes remain.
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], McError> {
+        if len > self.remaining() {
+            return Err(McError::malformed(DataError::Truncated {
+                needed: len,
+                remaining: self.remaining(),
+                position: self.position,
+            }));
+        }
+
+        let bytes = &self.bytes[self.position..self.position + len];
+        self.position += len;
+        Ok(bytes)
+    }
+
+    /// Consumes every remaining byte.
+    pub fn read_remaining(&mut self) -> &'a [u8] {
+        let bytes = &self.bytes[self.position..];
+        self.position = self.bytes.len();
+        bytes
+    }
+
+    /// Consumes exactly `N` bytes as a fixed-size array.
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], McError> {
+        let mut array = [0_u8; N];
+        array.copy_from_slice(self.read_bytes(N)?);
+        Ok(array)
+    }
+
+    /// Reads an unsigned byte.
+    pub fn read_u8(&mut self) -> Result<u8, McError> {
+        Ok(self.read_array::<1>()?[0])
+    }
+
+    /// Reads a signed byte.
+    pub fn read_i8(&mut self) -> Result<i8, McError> {
+        Ok(self.read_u8()? as i8)
+    }
+
+    /// Reads a bool encoded as a single byte. Any value other than `0x00` or
+    /// `0x01` is rejected.
+    /// # Errors
+    /// This function will return a `Malformed` error if the byte is not a valid bool.
+    pub fn read_bool(&mut self) -> Result<bool, McError> {
+        match self.read_u8()? {
+            0x00 => Ok(false),
+            0x01 => Ok(true),
+            b => Err(McError::invalid(format!(
+                "Invalid bool byte {:#04x} at position {}",
+                b,
+                self.position - 1
+            ))),
+        }
+    }
+
+    /// Reads a `u16` encoded in Big Endian.
+    pub fn read_u16(&mut self) -> Result<u16, McError> {
+        Ok(u16::from_be_bytes(self.read_array()?))
+    }
+
+    /// Reads an `i16` encoded in Big Endian.
+    pub fn read_i16(&mut self) -> Result<i16, McError> {
+        Ok(i16::from_be_bytes(self.read_array()?))
+    }
+
+    /// Reads an `i32` encoded in Big Endian.
+    pub fn read_i32(&mut self) -> Result<i32, McError> {
+        Ok(i32::from_be_bytes(self.read_array()?))
+    }
+
+    /// Reads an `i64` encoded in Big Endian.
+    pub fn read_i64(&mut self) -> Result<i64, McError> {
+        Ok(i64::from_be_bytes(self.read_array()?))
+    }
+
+    /// Reads a `u64` encoded in Big Endian.
+    pub fn read_u64(&mut self) -> Result<u64, McError> {
+        Ok(u64::from_be_bytes(self.read_array()?))
+    }
+
+    /// Reads an IEEE 754 single-precision float encoded in Big Endian.
+    pub fn read_f32(&mut self) -> Result<f32, McError> {
+        Ok(f32::from_be_bytes(self.read_array()?))
+    }
+
+    /// Reads an IEEE 754 double-precision float encoded in Big Endian.
+    pub fn read_f64(&mut self) -> Result<f64, McError> {
+        Ok(f64::from_be_bytes(self.read_array()?))
+    }
+
+    /// Reads a UUID encoded as a 128-bit Big Endian integer.
+    pub fn read_uuid(&mut self) -> Result<Uuid, McError> {
+        Ok(Uuid::from_bytes(self.read_array()?))
+    }
+
+    /// Reads a `VarInt`. <https://wiki.vg/Protocol#VarInt_and_VarLong>
+    /// # Errors
+    /// This function will return a `Malformed` error if the `VarInt` is longer
+    /// than 5 bytes or the data ends mid-`VarInt`.
+    pub fn read_varint(&mut self) -> Result<i32, McError> {
+        let mut value = 0_i32;
+
+        for i in 0..5 {
+            let b = self.read_u8()?;
+            value |= ((b & 0x7F) as i32) << (7 * i);
+
+            if b & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err(McError::invalid(format!("VarInt exceeds 5 bytes at position {}", self.position)))
+    }
+
+    /// Reads a `VarLong`. <https://wiki.vg/Protocol#VarInt_and_VarLong>
+    /// # Errors
+    /// This function will return a `Malformed` error if the `VarLong` is longer
+    /// than 10 bytes or the data ends mid-`VarLong`.
+    pub fn read_varlong(&mut self) -> Result<i64, McError> {
+        let mut value = 0_i64;
+
+        for i in 0..10 {
+            let b = self.read_u8()?;
+            value |= ((b & 0x7F) as i64) << (7 * i);
+
+            if b & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err(McError::invalid(format!("VarLong exceeds 10 bytes at position {}", self.position)))
+    }
+
+    /// Reads a block position packed into a 64-bit integer.
+    pub fn read_position(&mut self) -> Result<Position, McError> {
+        Ok(Position::from_packed(self.read_i64()?))
+    }
+
+    /// Reads NBT with a named root, as sent by protocol versions before 1.20.2.
+    /// # Returns
+    /// `None` if the server sent a lone `TAG_End`, i.e., no NBT.
+    /// # Errors
+    /// This function will return an error if the NBT is truncated or malformed.
+    pub fn read_nbt(&mut self) -> Result<Option<Nbt>, McError> {
+        Ok(binary::read_named(self)?.map(|(name, tag)| Nbt { name, tag }))
+    }
+
+    /// Reads NBT with a nameless root, as sent by protocol 1.20.2 and later.
+    /// # Returns
+    /// `None` if the server sent a lone `TAG_End`, i.e., no NBT.
+    /// # Errors
+    /// This function will return an error if the NBT is truncated or malformed.
+    pub fn read_network_nbt(&mut self) -> Result<Option<NetworkNbt>, McError> {
+        Ok(binary::read_nameless(self)?.map(NetworkNbt))
+    }
+
+    /// Reads a Slot encoded in `format`. An empty slot is read as an empty `ItemStack`.
+    /// # Errors
+    /// See `ItemStack::read_with`.
+    pub fn read_slot(&mut self, format: SlotFormat) -> Result<ItemStack, McError> {
+        ItemStack::read_with(self, format)
+    }
+
+    /// Reads an entity metadata list as encoded by `protocol_version`.
+    /// # Errors
+    /// See `EntityMetadata::read_with`.
+    pub fn read_entity_metadata(&mut self, protocol_version: i32) -> Result<EntityMetadata, McError> {
+        EntityMetadata::read_with(self, protocol_version)
+    }
+
+    /// Reads a `VarInt` which describes a length or count, rejecting negative values.
+    /// # Errors
+    /// This function will return a `Malformed` error if the value is negative, or
+    /// any error returned by `read_varint`.
+    pub fn read_length(&mut self) -> Result<usize, McError> {
+        let len = self.read_varint()?;
+        usize::try_from(len)
+            .map_err(|_| McError::invalid(format!("Negative length {} at position {}", len, self.position)))
+    }
+
+    /// Reads a UTF-8 string prefixed with its byte length as a `VarInt`. `max_len` is the
+    /// maximum length the protocol permits for the field, counted in UTF-16 code units.
+    /// # Errors
+    /// This function will return a `DataError::String` error if the string is longer
+    /// than `max_len` or is not valid UTF-8, or a `DataError::Truncated` error if the
+    /// data ends before the declared length.
+    pub fn read_string(&mut self, max_len: usize) -> Result<String, McError> {
+        Ok(self.read_str(max_len)?.to_owned())
+    }
+
+    /// Reads a string like `read_string`, but borrows it from the packet buffer
+    /// instead of copying it.
+    /// # Errors
+    /// See `read_string`.
+    pub fn read_str(&mut self, max_len: usize) -> Result<&'a str, McError> {
+        let byte_len = self.read_length()?;
+        // A UTF-16 code unit takes at most 3 bytes in UTF-8.
+        if byte_len > max_len.saturating_mul(3) {
+            return Err(StringError::TooManyBytes { bytes: byte_len, max_len }.into());
+        }
+
+        let string = std::str::from_utf8(self.read_bytes(byte_len)?).map_err(StringError::InvalidUtf8)?;
+        check_string_length(string, max_len)?;
+
+        Ok(string)
+    }
+
+    /// Reads any `MCType` from the front of the remaining bytes.
+    /// # Errors
+    /// This function will return any error returned by the type's `read_from`.
+    pub fn read<T: MCType>(&mut self) -> Result<T, McError> {
+        T::read_from(self)
+    }
+}
@@ -0,0 +1,28 @@
+use crate::mc::{
+    encryption,
+    error::McError,
+    packet::{packet_ids, Packet},
+};
+
+/// Answers Encryption Request. Both fields are encrypted with the server's public key.
+#[derive(Debug, Packet)]
+#[packet(id = packet_ids::serverbound::ENCRYPTION_RESPONSE, state = Login, direction = Serverbound)]
+pub struct EncryptionResponse {
+    #[packet(length_prefixed)]
+    pub shared_secret: Vec<u8>,
+    #[packet(length_prefixed)]
+    pub verify_token: Vec<u8>,
+}
+
+impl EncryptionResponse {
+    /// Encrypts `shared_secret` and the `verify_token` of an Encryption Request with
+    /// the server's DER-encoded `public_key`.
+    /// # Errors
+    /// This function will return an `Encryption` error if the key is invalid.
+    pub fn encrypt(public_key: &[u8], shared_secret: &[u8], verify_token: &[u8]) -> Result<Self, McError> {
+        Ok(EncryptionResponse {
+            shared_secret: encryption::encrypt_with_public_key(public_key, shared_secret)?,
+            verify_token: encryption::encrypt_with_public_key(public_key, verify_token)?,
+        })
+    }
+}
@@ -1,43 +1,66 @@
-use crate::mc::{mctypes::VarInt, packet::{OutboundPacket, builder::PacketBytesBuilder, packet_ids}};
+use crate::mc::{
+    error::McError,
+    mctypes::{MCType, VarInt, MAX_SERVER_ADDRESS_LENGTH},
+    packet::{Packet, packet_ids, reader::BufferedPacketReader},
+};
 
 #[repr(i32)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[allow(dead_code)]
 pub enum NextState {
     STATUS = 1,
     LOGIN = 2,
 }
 
-impl Into<i32> for NextState {
-    fn into(self) -> i32 {
-        self as i32
+impl From<NextState> for i32 {
+    fn from(value: NextState) -> Self {
+        value as i32
     }
 }
 
-impl Into<VarInt> for NextState {
-    fn into(self) -> VarInt {
-        VarInt::from_i32(self as i32)
+impl From<NextState> for VarInt {
+    fn from(value: NextState) -> Self {
+        VarInt::from_i32(value as i32)
     }
 }
 
-pub struct Handshake {
-    pub protocol_version: i32,
-    pub server_addr: String,
-    pub port: u16,
-    pub next_state: NextState,
+impl TryFrom<i32> for NextState {
+    type Error = McError;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(NextState::STATUS),
+            2 => Ok(NextState::LOGIN),
+            _ => Err(McError::invalid(format!("Invalid handshake next state {}", value))),
+        }
+    }
 }
 
-impl OutboundPacket for Handshake {
-    fn to_bytes(&self) -> Vec<u8> {
-        PacketBytesBuilder::new()
-            .append_varint(&VarInt::from_i32(self.protocol_version))
-            .append_string(self.server_addr.clone())
-            .append_u16(self.port)
-            .append_varint(&self.next_state.into())
-        .build()
+impl MCType for NextState {
+    fn write_to(&self, buf: &mut Vec<u8>) {
+        VarInt::from(*self).write_to(buf)
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, McError> {
+        Self::read_from(&mut BufferedPacketReader::new(bytes))
     }
 
-    fn packet_id(&self) -> i32 {
-        packet_ids::serverbound::HANDSHAKE_PACKET_ID
+    fn read_from(reader: &mut BufferedPacketReader) -> Result<Self, McError> {
+        NextState::try_from(reader.read_varint()?)
     }
+
+    fn size(&self) -> i32 {
+        1
+    }
+}
+
+#[derive(Debug, Packet)]
+#[packet(id = packet_ids::serverbound::HANDSHAKE_PACKET_ID, state = Handshaking, direction = Serverbound)]
+pub struct Handshake {
+    #[packet(varint)]
+    pub protocol_version: i32,
+    #[packet(max_len = MAX_SERVER_ADDRESS_LENGTH)]
+    pub server_addr: String,
+    pub port: u16,
+    pub next_state: NextState,
 }
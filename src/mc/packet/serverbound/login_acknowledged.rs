@@ -0,0 +1,10 @@
+use crate::mc::packet::{packet_ids, Packet};
+
+/// Acknowledges Login Success, switching the connection to the Configuration
+/// state. Only sent from protocol `LOGIN_ACKNOWLEDGED_PROTOCOL` onwards.
+#[derive(Debug, Packet)]
+#[packet(id = packet_ids::serverbound::LOGIN_ACKNOWLEDGED, state = Login, direction = Serverbound)]
+pub struct LoginAcknowledged;
+
+/// The first protocol version with Login Acknowledged and the Configuration state, 1.20.2.
+pub const LOGIN_ACKNOWLEDGED_PROTOCOL: i32 = 764;
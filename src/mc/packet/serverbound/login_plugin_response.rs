@@ -0,0 +1,34 @@
+use crate::mc::packet::{packet_ids, Packet};
+
+/// Answers a Login Plugin Request. `data` is only sent when `successful` is set,
+/// i.e., the client understood the channel.
+#[derive(Debug, Packet)]
+#[packet(id = packet_ids::serverbound::LOGIN_PLUGIN_RESPONSE, state = Login, direction = Serverbound)]
+pub struct LoginPluginResponse {
+    #[packet(varint)]
+    pub message_id: i32,
+    pub successful: bool,
+    #[packet(remaining)]
+    pub data: Vec<u8>,
+}
+
+impl LoginPluginResponse {
+    /// Answers the request `message_id` with `data`.
+    pub fn understood(message_id: i32, data: Vec<u8>) -> Self {
+        LoginPluginResponse {
+            message_id,
+            successful: true,
+            data,
+        }
+    }
+
+    /// Tells the server that the client does not know the channel of the request
+    /// `message_id`, as the vanilla client does for every channel.
+    pub fn not_understood(message_id: i32) -> Self {
+        LoginPluginResponse {
+            message_id,
+            successful: false,
+            data: Vec::new(),
+        }
+    }
+}
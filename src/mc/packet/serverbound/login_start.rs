@@ -1,25 +1,37 @@
 use uuid::Uuid;
 
-use crate::mc::packet::{OutboundPacket, builder::PacketBytesBuilder, packet_ids};
+use crate::mc::{
+    error::McError,
+    mctypes::MAX_USERNAME_LENGTH,
+    packet::{builder::PacketBytesBuilder, OutboundPacket, Packet, packet_ids},
+};
 
+use super::login_acknowledged::LOGIN_ACKNOWLEDGED_PROTOCOL;
+
+#[derive(Debug, Packet)]
+#[packet(id = packet_ids::serverbound::LOGIN_START, state = Login, direction = Serverbound)]
 pub struct LoginStart {
+    #[packet(max_len = MAX_USERNAME_LENGTH)]
     pub username: String,
+    #[packet(bool_prefixed)]
     pub uuid: Option<Uuid>,
 }
 
-impl OutboundPacket for LoginStart {
-    fn to_bytes(&self) -> Vec<u8> {
-        let mut builder = PacketBytesBuilder::new()
-            .append_string(self.username.clone())
-            .append_bool(self.uuid.is_some());
-        if self.uuid.is_some() {
-            builder = builder.append_uuid(&self.uuid.unwrap());
+impl LoginStart {
+    /// Encodes the packet data as sent by `protocol_version`. From 1.20.2, the UUID is
+    /// no longer optional, so the nil UUID is sent in place of `None`.
+    /// # Errors
+    /// This function will return a `Malformed` error if the username is longer than
+    /// `MAX_USERNAME_LENGTH`.
+    pub fn to_bytes_with(&self, protocol_version: i32) -> Result<Vec<u8>, McError> {
+        if protocol_version < LOGIN_ACKNOWLEDGED_PROTOCOL {
+            return self.to_bytes();
         }
 
-        builder.build()
-    }
-
-    fn packet_id(&self) -> i32 {
-        packet_ids::serverbound::LOGIN_START
+        Ok(PacketBytesBuilder::new()
+            .try_append_string(&self.username, MAX_USERNAME_LENGTH)
+            .map_err(|e| McError::from(e).in_field("username").in_packet(Self::STATE, Self::ID))?
+            .append_uuid(&self.uuid.unwrap_or_default())
+            .build())
     }
 }
@@ -1,3 +1,7 @@
+pub mod encryption_response;
 pub mod handshake;
+pub mod login_acknowledged;
+pub mod login_plugin_response;
 pub mod login_start;
+pub mod ping_request;
 pub mod status_request;
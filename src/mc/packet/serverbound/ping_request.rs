@@ -0,0 +1,9 @@
+use crate::mc::packet::{packet_ids, Packet};
+
+/// Asks the server to echo `payload` in a Ping Response, which measures latency.
+/// The vanilla client sends a timestamp, but any value is allowed.
+#[derive(Debug, Packet)]
+#[packet(id = packet_ids::serverbound::PING_REQUEST, state = Status, direction = Serverbound)]
+pub struct PingRequest {
+    pub payload: i64,
+}
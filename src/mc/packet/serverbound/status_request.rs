@@ -1,13 +1,5 @@
-use crate::mc::packet::{OutboundPacket, packet_ids};
+use crate::mc::packet::{Packet, packet_ids};
 
+#[derive(Packet)]
+#[packet(id = packet_ids::serverbound::STATUS_REQUEST, state = Status, direction = Serverbound)]
 pub struct StatusRequest;
-
-impl OutboundPacket for StatusRequest {
-    fn to_bytes(&self) -> Vec<u8> {
-        vec![]
-    }
-
-    fn packet_id(&self) -> i32 {
-        packet_ids::serverbound::STATUS_REQUEST
-    }
-}
@@ -0,0 +1,32 @@
+//! Marker types for the connection states, which let `Client` and the packet types
+//! check at compile time that a packet belongs to the current state.
+
+use super::ConnectionState;
+
+/// A connection state known at compile time.
+pub trait ProtocolState {
+    const STATE: ConnectionState;
+}
+
+/// Declares a marker type for each connection state.
+macro_rules! protocol_states {
+    ($($name:ident),* $(,)?) => {
+        $(
+            #[doc = concat!("The `", stringify!($name), "` state as a type.")]
+            #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+            pub struct $name;
+
+            impl ProtocolState for $name {
+                const STATE: ConnectionState = ConnectionState::$name;
+            }
+        )*
+    };
+}
+
+protocol_states! {
+    Handshaking,
+    Status,
+    Login,
+    Configuration,
+    Play,
+}
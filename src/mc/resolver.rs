@@ -0,0 +1,191 @@
+//! Resolution of server addresses, as typed into the multiplayer screen.
+//! <br>
+//! A server address without a port may name its real endpoint in a
+//! `_minecraft._tcp` SRV record, e.g., `play.example.net` served by
+//! `node3.example.net:25570`. Without such a record, the host itself is connected
+//! to on port 25565. An address with a port skips the SRV lookup. Lookups go through
+//! a `Resolver`, so that they can be replaced, e.g., by a stub in tests.
+
+use std::{
+    fmt, io,
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+    str::FromStr,
+};
+
+use super::error::McError;
+
+/// The service and protocol labels of the SRV records naming Minecraft servers.
+pub const MINECRAFT_SRV_PREFIX: &str = "_minecraft._tcp";
+
+/// The port of a server address which has none, and no SRV record.
+pub const DEFAULT_PORT: u16 = 25565;
+
+/// An SRV record. <https://www.rfc-editor.org/rfc/rfc2782>
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SrvRecord {
+    /// Lower priorities are tried first.
+    pub priority: u16,
+    /// Among records of the same priority, heavier ones are tried first.
+    pub weight: u16,
+    pub port: u16,
+    pub target: String,
+}
+
+/// Looks up the records needed to resolve server addresses.
+pub trait Resolver {
+    /// Looks up the SRV records of `name`, e.g., `_minecraft._tcp.play.example.net`.
+    /// # Errors
+    /// This function may return an error if the lookup fails, including if `name` has
+    /// no SRV records.
+    fn lookup_srv(&self, name: &str) -> Result<Vec<SrvRecord>, McError>;
+
+    /// Looks up the IPv4 and IPv6 addresses of `host`.
+    /// # Errors
+    /// This function may return an error if the lookup fails.
+    fn lookup_ip(&self, host: &str) -> Result<Vec<IpAddr>, McError>;
+}
+
+/// Resolves with the DNS servers of the system configuration. Addresses are looked up
+/// like `ToSocketAddrs` does, so hosts files are honored.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    fn lookup_srv(&self, name: &str) -> Result<Vec<SrvRecord>, McError> {
+        let resolver = hickory_resolver::Resolver::from_system_conf()?;
+        let lookup = resolver.srv_lookup(name).map_err(io::Error::from)?;
+
+        Ok(lookup
+            .iter()
+            .map(|srv| SrvRecord {
+                priority: srv.priority(),
+                weight: srv.weight(),
+                port: srv.port(),
+                target: srv.target().to_utf8(),
+            })
+            .collect())
+    }
+
+    fn lookup_ip(&self, host: &str) -> Result<Vec<IpAddr>, McError> {
+        Ok((host, 0).to_socket_addrs()?.map(|addr| addr.ip()).collect())
+    }
+}
+
+/// A server address, a host with an optional port.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ServerAddress {
+    pub host: String,
+    pub port: Option<u16>,
+}
+
+impl ServerAddress {
+    pub fn new<T: Into<String>>(host: T, port: Option<u16>) -> Self {
+        ServerAddress {
+            host: host.into(),
+            port,
+        }
+    }
+
+    /// Parses `host`, `host:port`, or an IPv6 address, in brackets if it has a port.
+    /// # Example
+    /// ```
+    /// use mcclient::mc::resolver::ServerAddress;
+    ///
+    /// assert_eq!(ServerAddress::parse("play.example.net").unwrap().port, None);
+    /// assert_eq!(ServerAddress::parse("play.example.net:25566").unwrap().port, Some(25566));
+    /// assert_eq!(ServerAddress::parse("[::1]:25566").unwrap().host, "::1");
+    /// ```
+    /// # Errors
+    /// This function will return a `Malformed` error if the host is empty or the port is
+    /// not a number from 0 to 65535.
+    pub fn parse(address: &str) -> Result<Self, McError> {
+        let (host, port) = match address.strip_prefix('[') {
+            Some(rest) => {
+                let (host, rest) = rest
+                    .split_once(']')
+                    .ok_or_else(|| McError::invalid(format!("Unclosed bracket in server address {:?}", address)))?;
+                match rest.strip_prefix(':') {
+                    Some(port) => (host, Some(port)),
+                    None if rest.is_empty() => (host, None),
+                    None => return Err(McError::invalid(format!("Invalid server address {:?}", address))),
+                }
+            }
+            // More than one colon means an IPv6 address without a port.
+            None => match address.split_once(':') {
+                Some((host, port)) if !port.contains(':') => (host, Some(port)),
+                _ => (address, None),
+            },
+        };
+
+        if host.is_empty() {
+            return Err(McError::invalid(format!("Server address {:?} has no host", address)));
+        }
+        let port = port
+            .map(|port| {
+                port.parse()
+                    .map_err(|_| McError::invalid(format!("Invalid port in server address {:?}", address)))
+            })
+            .transpose()?;
+
+        Ok(ServerAddress::new(host, port))
+    }
+
+    /// Resolves the address to the socket addresses to try, in order. Without a port,
+    /// the SRV records of the host are looked up first, ordered by priority and then
+    /// weight, and the host itself on `DEFAULT_PORT` is the fallback if there are none
+    /// or the lookup fails.
+    /// # Errors
+    /// This function will return any error `Resolver::lookup_ip` returns, or an `Io`
+    /// error of kind `NotFound` if the address resolves to no addresses.
+    pub fn resolve(&self, resolver: &dyn Resolver) -> Result<Vec<SocketAddr>, McError> {
+        if let Ok(ip) = self.host.parse::<IpAddr>() {
+            return Ok(vec![SocketAddr::new(ip, self.port.unwrap_or(DEFAULT_PORT))]);
+        }
+
+        let mut addrs = Vec::new();
+        if let Some(port) = self.port {
+            addrs.extend(resolver.lookup_ip(&self.host)?.into_iter().map(|ip| SocketAddr::new(ip, port)));
+        } else {
+            let mut records = resolver
+                .lookup_srv(&format!("{}.{}", MINECRAFT_SRV_PREFIX, self.host))
+                .unwrap_or_default();
+            records.sort_by_key(|record| (record.priority, u16::MAX - record.weight));
+            for record in records {
+                // Targets which cannot be resolved are skipped, like unreachable ones.
+                if let Ok(ips) = resolver.lookup_ip(record.target.trim_end_matches('.')) {
+                    addrs.extend(ips.into_iter().map(|ip| SocketAddr::new(ip, record.port)));
+                }
+            }
+            if addrs.is_empty() {
+                addrs.extend(resolver.lookup_ip(&self.host)?.into_iter().map(|ip| SocketAddr::new(ip, DEFAULT_PORT)));
+            }
+        }
+
+        if addrs.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Server address {} resolved to no addresses", self),
+            )
+            .into());
+        }
+        Ok(addrs)
+    }
+}
+
+impl fmt::Display for ServerAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.port, self.host.contains(':')) {
+            (Some(port), true) => write!(f, "[{}]:{}", self.host, port),
+            (Some(port), false) => write!(f, "{}:{}", self.host, port),
+            (None, _) => write!(f, "{}", self.host),
+        }
+    }
+}
+
+impl FromStr for ServerAddress {
+    type Err = McError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ServerAddress::parse(s)
+    }
+}
@@ -0,0 +1,195 @@
+//! A Play-state session, which keeps a logged-in client online.
+//! <br>
+//! Servers send Keep Alive every 15 seconds and disconnect clients which do not echo
+//! it in time, so a session answers Keep Alive and Ping itself while handing every
+//! other packet to the application. From 1.19.4, servers group packets which must
+//! be applied in the same tick between two Bundle Delimiters; a session collects
+//! them and hands them over together.
+
+use std::ops::ControlFlow;
+
+use super::{
+    client::Client,
+    error::McError,
+    packet::{state::Play, ClientboundRawPacket, ConnectionState, ServerboundRawPacket},
+    text::TextComponent,
+};
+
+/// The maximum number of packets in a bundle. The vanilla client disconnects from
+/// servers which send more.
+pub const MAX_BUNDLE_SIZE: usize = 4096;
+
+/// The IDs of the Play packets a session handles, which were renumbered between
+/// versions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PlayPacketIds {
+    /// `None` before 1.19.4, which had no bundles.
+    pub bundle_delimiter: Option<i32>,
+    pub disconnect: i32,
+    pub keep_alive: i32,
+    pub ping: i32,
+    pub serverbound_keep_alive: i32,
+    pub pong: i32,
+}
+
+impl PlayPacketIds {
+    /// Gets the IDs used by `protocol_version`.
+    /// # Returns
+    /// `None` for versions before 1.19.3 or after 1.21.1, whose IDs are not known.
+    pub fn for_protocol(protocol_version: i32) -> Option<Self> {
+        let (bundle_delimiter, disconnect, keep_alive, ping, serverbound_keep_alive, pong) = match protocol_version {
+            761 => (None, 0x17, 0x1F, 0x2E, 0x11, 0x1F),
+            762..=763 => (Some(0x00), 0x1A, 0x23, 0x32, 0x12, 0x20),
+            764 => (Some(0x00), 0x1B, 0x24, 0x33, 0x14, 0x23),
+            765 => (Some(0x00), 0x1B, 0x24, 0x33, 0x15, 0x24),
+            766..=767 => (Some(0x00), 0x1D, 0x26, 0x35, 0x18, 0x27),
+            _ => return None,
+        };
+
+        Some(PlayPacketIds {
+            bundle_delimiter,
+            disconnect,
+            keep_alive,
+            ping,
+            serverbound_keep_alive,
+            pong,
+        })
+    }
+}
+
+/// What a session hands to the application.
+#[derive(Debug)]
+pub enum SessionEvent {
+    Packet(ClientboundRawPacket),
+    /// The packets between two Bundle Delimiters, which must be applied together.
+    Bundle(Vec<ClientboundRawPacket>),
+    /// The server sent Start Configuration. The session cannot read further; call
+    /// `Client::reconfigure` on `PlaySession::into_client`.
+    StartConfiguration,
+}
+
+/// Reads the packets of a client in Play, answering Keep Alive and Ping.
+/// # Example
+/// ```no_run
+/// use std::ops::ControlFlow;
+/// use mcclient::mc::{client::Client, session::{PlaySession, SessionEvent}};
+///
+/// let (play, _) = Client::connect("localhost", 25565)?.into_login()?.login("Makoto")?;
+/// let mut session = PlaySession::new(play)?;
+/// session.run(|_client, event| {
+///     if let SessionEvent::Packet(packet) = event {
+///         println!("Received packet {:#04x}", packet.header.id);
+///     }
+///     ControlFlow::Continue(())
+/// })?;
+/// # Ok::<(), mcclient::mc::error::McError>(())
+/// ```
+pub struct PlaySession {
+    client: Client<Play>,
+    ids: PlayPacketIds,
+}
+
+impl PlaySession {
+    /// Starts a session on `client`.
+    /// # Errors
+    /// This function will return a `Malformed` error if the Play packet IDs of the
+    /// client's protocol version are not known.
+    pub fn new(client: Client<Play>) -> Result<Self, McError> {
+        let ids = PlayPacketIds::for_protocol(client.protocol_version()).ok_or_else(|| {
+            McError::invalid(format!(
+                "The Play packet IDs of protocol {} are not known",
+                client.protocol_version()
+            ))
+        })?;
+        Ok(PlaySession { client, ids })
+    }
+
+    /// Reads until a packet for the application arrives, answering Keep Alive and Ping
+    /// on the way.
+    /// # Errors
+    /// This function will return a `Disconnected` error with the reason if the server
+    /// sends Disconnect, a `Malformed` error if a bundle holds more than
+    /// `MAX_BUNDLE_SIZE` packets, or any error raised while reading or answering.
+    pub fn next_event(&mut self) -> Result<SessionEvent, McError> {
+        let packet = self.read_packet()?;
+        if Some(packet.header.id) == self.ids.bundle_delimiter {
+            return Ok(SessionEvent::Bundle(self.read_bundle()?));
+        }
+        if self.client.is_start_configuration(&packet) {
+            return Ok(SessionEvent::StartConfiguration);
+        }
+        Ok(SessionEvent::Packet(packet))
+    }
+
+    /// Runs the read loop, passing every event to `handler` along with the client, which
+    /// it may use to send packets. The loop ends when the handler breaks, or after it
+    /// is passed `StartConfiguration`.
+    /// # Errors
+    /// This function will return any error `next_event` returns.
+    pub fn run<F>(&mut self, mut handler: F) -> Result<(), McError>
+    where
+        F: FnMut(&mut Client<Play>, SessionEvent) -> ControlFlow<()>,
+    {
+        loop {
+            let event = self.next_event()?;
+            let reconfiguring = matches!(event, SessionEvent::StartConfiguration);
+            if handler(&mut self.client, event).is_break() || reconfiguring {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Gets the client, e.g., to send packets.
+    pub fn client(&mut self) -> &mut Client<Play> {
+        &mut self.client
+    }
+
+    /// Ends the session, returning the client.
+    pub fn into_client(self) -> Client<Play> {
+        self.client
+    }
+
+    /// Reads the packets up to the closing Bundle Delimiter.
+    fn read_bundle(&mut self) -> Result<Vec<ClientboundRawPacket>, McError> {
+        let mut bundle = Vec::new();
+        loop {
+            let packet = self.read_packet()?;
+            if Some(packet.header.id) == self.ids.bundle_delimiter {
+                return Ok(bundle);
+            }
+            if bundle.len() == MAX_BUNDLE_SIZE {
+                return Err(McError::invalid(format!(
+                    "Bundle holds more than {} packets",
+                    MAX_BUNDLE_SIZE
+                )));
+            }
+            bundle.push(packet);
+        }
+    }
+
+    /// Reads the next packet which is not Keep Alive or Ping, answering those.
+    fn read_packet(&mut self) -> Result<ClientboundRawPacket, McError> {
+        loop {
+            let packet = self.client.read()?;
+            let id = packet.header.id;
+            let in_field = |name| move |e: McError| e.in_field(name).in_packet(ConnectionState::Play, id);
+            if id == self.ids.keep_alive {
+                let keep_alive_id = packet.reader().read_i64().map_err(in_field("keep_alive_id"))?;
+                self.answer(self.ids.serverbound_keep_alive, keep_alive_id.to_be_bytes().to_vec())?;
+            } else if id == self.ids.ping {
+                let ping_id = packet.reader().read_i32().map_err(in_field("id"))?;
+                self.answer(self.ids.pong, ping_id.to_be_bytes().to_vec())?;
+            } else if id == self.ids.disconnect {
+                let reason = TextComponent::read_with(&mut packet.reader(), self.client.protocol_version())
+                    .map_err(in_field("reason"))?;
+                return Err(McError::Disconnected(Box::new(reason)));
+            } else {
+                return Ok(packet);
+            }
+        }
+    }
+
+    fn answer(&mut self, id: i32, data: Vec<u8>) -> Result<(), McError> {
+        self.client.send_raw_packet(&ServerboundRawPacket { id, data })
+    }
+}
@@ -0,0 +1,210 @@
+//! The server status shown in the multiplayer server list, as sent in Status Response.
+//! <https://wiki.vg/Server_List_Ping#Status_Response>
+//! <br>
+//! Every field besides the version is optional, since servers and proxies commonly
+//! leave some out. Fields which are not part of the model, e.g., from plugins, are
+//! kept in `extra` so that a status can be serialized again without losing them.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Map, Value};
+
+use super::{error::McError, text::TextComponent};
+
+/// The prefix of the data URI holding a favicon.
+pub const FAVICON_PREFIX: &str = "data:image/png;base64,";
+
+/// A server status.
+/// # Example
+/// ```
+/// use mcclient::mc::status::ServerStatus;
+///
+/// let status = ServerStatus::from_json(r#"{
+///     "version": { "name": "1.19.3", "protocol": 761 },
+///     "players": { "max": 20, "online": 1 },
+///     "description": "A Minecraft Server"
+/// }"#).unwrap();
+/// assert_eq!(status.players.unwrap().online, 1);
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerStatus {
+    pub version: Version,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub players: Option<Players>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<TextComponent>,
+    /// `None` if the server sent no favicon, or one which is not a valid PNG data URI.
+    #[serde(default, deserialize_with = "deserialize_favicon", skip_serializing_if = "Option::is_none")]
+    pub favicon: Option<Favicon>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enforces_secure_chat: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previews_chat: Option<bool>,
+    /// The mod list of Forge servers before 1.13.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modinfo: Option<ModInfo>,
+    /// The mod list of Forge servers from 1.13 onwards.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forge_data: Option<ForgeData>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl ServerStatus {
+    /// Parses a status from the JSON string of a Status Response.
+    /// # Errors
+    /// This function will return a `Malformed` error if `json` is not valid JSON or
+    /// does not describe a status.
+    pub fn from_json(json: &str) -> Result<Self, McError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Serializes the status to a compact JSON string.
+    /// # Errors
+    /// This function will return a `Malformed` error if the status cannot be serialized.
+    pub fn to_json_string(&self) -> Result<String, McError> {
+        Ok(serde_json::to_string(self)?)
+    }
+}
+
+/// The game version of the server.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Version {
+    pub name: String,
+    pub protocol: i32,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// The player count of the server, with a sample of the players online.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Players {
+    pub max: i32,
+    pub online: i32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sample: Vec<PlayerSample>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// A player listed in the sample. Servers often put arbitrary lines of text here,
+/// so the ID is not required to be a valid UUID.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlayerSample {
+    pub name: String,
+    pub id: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// The icon of the server, a 64x64 PNG image.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Favicon {
+    pub png: Vec<u8>,
+}
+
+impl Favicon {
+    /// Decodes a favicon from its data URI, `data:image/png;base64,` followed by the
+    /// Base64-encoded image. Line breaks in the Base64 data, which some servers send,
+    /// are ignored.
+    /// # Errors
+    /// This function will return a `Malformed` error if `uri` does not have the PNG
+    /// data URI prefix or the data is not valid Base64.
+    pub fn from_data_uri(uri: &str) -> Result<Self, McError> {
+        let data = uri
+            .strip_prefix(FAVICON_PREFIX)
+            .ok_or_else(|| McError::invalid("Favicon is not a PNG data URI"))?;
+        let data: String = data.chars().filter(|c| !c.is_ascii_whitespace()).collect();
+        let png = STANDARD
+            .decode(data)
+            .map_err(|e| McError::invalid(format!("Invalid favicon data: {}", e)))?;
+
+        Ok(Favicon { png })
+    }
+
+    /// Encodes the favicon as a data URI.
+    pub fn to_data_uri(&self) -> String {
+        format!("{}{}", FAVICON_PREFIX, STANDARD.encode(&self.png))
+    }
+}
+
+impl Serialize for Favicon {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_data_uri())
+    }
+}
+
+impl<'de> Deserialize<'de> for Favicon {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let uri = String::deserialize(deserializer)?;
+        Favicon::from_data_uri(&uri).map_err(de::Error::custom)
+    }
+}
+
+/// Reads a favicon, falling back to `None` if it is malformed, so that one bad icon
+/// does not cost the rest of the status.
+fn deserialize_favicon<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Favicon>, D::Error> {
+    let uri = Option::<Value>::deserialize(deserializer)?;
+    Ok(uri.as_ref().and_then(Value::as_str).and_then(|uri| Favicon::from_data_uri(uri).ok()))
+}
+
+/// The `modinfo` of Forge Mod Loader servers before 1.13.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModInfo {
+    /// The server type, `FML` for Forge.
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(rename = "modList", default)]
+    pub mod_list: Vec<ModInfoEntry>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// A mod in `modinfo`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModInfoEntry {
+    pub modid: String,
+    pub version: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// The `forgeData` of Forge servers from 1.13 onwards. From 1.18, Forge packs the
+/// lists into the `d` field, which is kept in `extra`, and leaves them empty here.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ForgeData {
+    #[serde(default)]
+    pub channels: Vec<ForgeChannel>,
+    #[serde(default)]
+    pub mods: Vec<ForgeMod>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fml_network_version: Option<i32>,
+    /// Whether the lists were cut short to fit into the response.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub truncated: Option<bool>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// A network channel registered by a Forge mod.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForgeChannel {
+    pub res: String,
+    pub version: String,
+    pub required: bool,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// A mod in `forgeData`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForgeMod {
+    #[serde(rename = "modId")]
+    pub mod_id: String,
+    /// The mod version, or a marker such as `OHNOES` for mods which accept any version.
+    pub modmarker: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
@@ -1,19 +1,34 @@
 use std::{
     io::{self, BufReader, BufWriter, Read, Write},
-    net::{TcpStream, ToSocketAddrs},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
 };
 
-use crate::mc::mctypes::VarInt;
+use socket2::{Domain, Protocol, SockAddr, Socket, TcpKeepalive, Type};
 
-use super::packet::{ClientboundRawPacket, OutboundPacket, OutboundPacketBuffer};
+use super::{
+    compression,
+    encryption::{DecryptingReader, EncryptingWriter, SHARED_SECRET_LENGTH},
+    error::McError,
+    frame::{FrameDecoder, MAX_FRAME_SIZE},
+    mctypes::{MCType, VarInt},
+    options::ConnectionOptions,
+    packet::{ClientboundRawPacket, OutboundPacket},
+};
 
 /// Describes a two-way TCP connection to a Minecraft server. The internal
 /// buffer bytes are handled by a high-level serdes which encapsulates the
 /// Minecraft packets. No byte manipulation is necessary to send packets
 /// using a MinecraftStream.
 pub struct MinecraftStream {
-    writer: BufWriter<TcpStream>,
-    reader: BufReader<TcpStream>,
+    writer: BufWriter<EncryptingWriter<TcpStream>>,
+    reader: BufReader<DecryptingReader<TcpStream>>,
+    frames: FrameDecoder,
+    /// Reused to encode outbound packets, so that writing does not allocate.
+    scratch: Vec<u8>,
+    /// Reused to hold the compressed frame of an outbound packet.
+    compressed: Vec<u8>,
+    /// The size from which packets are compressed, or `None` if compression is off.
+    compression_threshold: Option<usize>,
 }
 
 impl MinecraftStream {
@@ -22,32 +37,132 @@ impl MinecraftStream {
     /// # Returns
     /// A stream to a Minecraft server is returned if the connection is successfully established.
     /// # Errors
-    /// Any `io::Error` is returned if the connection cannot be established.
-    pub fn connect<T: ToSocketAddrs>(addr: T) -> Result<Self, io::Error> {
-        let stream = TcpStream::connect(addr)?;
+    /// An `Io` error is returned if the connection cannot be established.
+    pub fn connect<T: ToSocketAddrs>(addr: T) -> Result<Self, McError> {
+        MinecraftStream::connect_with(addr, &ConnectionOptions::default())
+    }
+
+    /// Connects to a remote Minecraft server as configured by `options`. Each address
+    /// `addr` resolves to is tried in turn.
+    /// # Errors
+    /// An `Io` error is returned if no address can be connected to, holding the error
+    /// of the last attempt, or if the socket cannot be configured.
+    pub fn connect_with<T: ToSocketAddrs>(addr: T, options: &ConnectionOptions) -> Result<Self, McError> {
+        let mut last_error = None;
+        let mut stream = None;
+        for addr in addr.to_socket_addrs()? {
+            match connect_socket(addr, options) {
+                Ok(connected) => {
+                    stream = Some(connected);
+                    break;
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        let stream = match (stream, last_error) {
+            (Some(stream), _) => stream,
+            (None, Some(e)) => return Err(e.into()),
+            (None, None) => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "Address resolved to no addresses").into())
+            }
+        };
+        stream.set_read_timeout(options.read_timeout())?;
+        stream.set_write_timeout(options.write_timeout())?;
+
+        let writer = BufWriter::new(EncryptingWriter::new(stream.try_clone()?));
+        let reader = BufReader::new(DecryptingReader::new(stream));
+
+        Ok(MinecraftStream {
+            writer,
+            reader,
+            frames: FrameDecoder::new(),
+            scratch: Vec::new(),
+            compressed: Vec::new(),
+            compression_threshold: None,
+        })
+    }
+
+    /// Gets a reference to the underlying TCP stream, e.g., to read its addresses.
+    pub fn get_ref(&self) -> &TcpStream {
+        self.reader.get_ref().get_ref()
+    }
 
-        let writer = BufWriter::new(stream.try_clone().unwrap());
-        let reader = BufReader::new(stream);
+    /// Enables the compressed packet format for both directions, compressing packets
+    /// of at least `threshold` bytes. A negative threshold disables compression, as
+    /// with the Set Compression packet. <https://wiki.vg/Protocol#Set_Compression>
+    pub fn set_compression(&mut self, threshold: i32) {
+        self.compression_threshold = usize::try_from(threshold).ok();
+    }
 
-        Ok(MinecraftStream { writer, reader })
+    /// Gets the size from which packets are compressed, or `None` if compression is off.
+    pub fn compression_threshold(&self) -> Option<usize> {
+        self.compression_threshold
+    }
+
+    /// Encrypts both directions of the stream with `shared_secret`, as sent in
+    /// Encryption Response. Pending outbound bytes are flushed unencrypted first.
+    /// <https://wiki.vg/Protocol_Encryption>
+    /// # Errors
+    /// An `Io` error will be returned if the pending bytes cannot be flushed, or an
+    /// `Encryption` error if inbound bytes were already buffered past the point where
+    /// encryption starts, as they would never be decrypted.
+    pub fn enable_encryption(&mut self, shared_secret: &[u8; SHARED_SECRET_LENGTH]) -> Result<(), McError> {
+        if !self.reader.buffer().is_empty() || self.frames.buffered() > 0 {
+            return Err(McError::Encryption(
+                "Received unencrypted data after the encryption handshake".to_string(),
+            ));
+        }
+
+        self.writer.flush()?;
+        self.writer.get_mut().enable(shared_secret);
+        self.reader.get_mut().enable(shared_secret);
+        Ok(())
+    }
+
+    /// Returns `true` if the stream is encrypted.
+    pub fn is_encrypted(&self) -> bool {
+        self.writer.get_ref().is_enabled()
     }
 
     /// Writes to the TCP outbound buffer. This should be used in tandem with
     /// `flush()` to send the outbound data to the target server. If you want
     /// to abstract this behavior, use `send(&mut self, packet: &dyn OutboundPacket)`.
     /// # Errors
-    /// An `io::Error` of any kind will be returned if the packet cannot be sent.
-    pub fn write(&mut self, packet: &dyn OutboundPacket) -> Result<(), io::Error> {
-        let packet_buffer = OutboundPacketBuffer::from(packet);
+    /// An `Io` error will be returned if the packet cannot be sent, a `Malformed` error
+    /// if it cannot be encoded or its frame is larger than `MAX_FRAME_SIZE`, or a
+    /// `Compression` error if it cannot be compressed. Nothing is written on error.
+    pub fn write(&mut self, packet: &dyn OutboundPacket) -> Result<(), McError> {
+        self.scratch.clear();
+        VarInt::from(packet.packet_id()).write_to(&mut self.scratch);
+        packet.write_to(&mut self.scratch)?;
 
-        Ok(self.writer.write_all(packet_buffer.data())?)
+        let frame = match self.compression_threshold {
+            Some(threshold) => {
+                self.compressed.clear();
+                compression::compress_frame(&self.scratch, threshold, &mut self.compressed)?;
+                &self.compressed
+            }
+            None => &self.scratch,
+        };
+        // The server would refuse the frame and disconnect without saying why.
+        if frame.len() > MAX_FRAME_SIZE {
+            return Err(McError::invalid(format!(
+                "Packet {:#04x} takes a frame of {} bytes, exceeding the maximum of {}",
+                packet.packet_id(),
+                frame.len(),
+                MAX_FRAME_SIZE
+            )));
+        }
+        self.writer.write_all(VarInt::from(frame.len() as i32).bytes())?;
+        self.writer.write_all(frame)?;
+        Ok(())
     }
 
     /// Writes to the TCP outbound buffer, and flushes the buffer.
     /// # Errors
-    /// An `io::Error` of any kind will be returned if the packet cannot be sent or the
-    /// stream cannot be flushed.
-    pub fn send(&mut self, packet: &dyn OutboundPacket) -> Result<(), io::Error> {
+    /// An `Io` error will be returned if the packet cannot be sent or the stream
+    /// cannot be flushed.
+    pub fn send(&mut self, packet: &dyn OutboundPacket) -> Result<(), McError> {
         self.write(packet)?;
         self.flush()?;
         Ok(())
@@ -55,33 +170,67 @@ impl MinecraftStream {
 
     /// Flushes the outbound stream.
     /// # Errors
-    /// An `io::Error` of any kind will be returned if the stream cannot be flushed, i.e.,
-    /// the bytes cannot be sent to the target server.
-    pub fn flush(&mut self) -> Result<(), io::Error> {
-        self.writer.flush()
+    /// An `Io` error will be returned if the stream cannot be flushed, i.e., the bytes
+    /// cannot be sent to the target server.
+    pub fn flush(&mut self) -> Result<(), McError> {
+        self.writer.flush()?;
+        Ok(())
     }
 
-    /// Attempts to consume a packet from the pending inbound byte stream.
+    /// Sends `bytes` as they are, without framing, compression or a packet ID. This
+    /// is only meant for exchanges which predate the packet format, i.e., the legacy
+    /// server list ping.
+    /// # Errors
+    /// An `Io` error will be returned if the bytes cannot be sent.
+    pub fn send_raw(&mut self, bytes: &[u8]) -> Result<(), McError> {
+        self.writer.write_all(bytes)?;
+        self.flush()
+    }
+
+    /// Reads exactly `buf.len()` bytes, without framing. Like `send_raw`, this must
+    /// not be mixed with `read`, which may already have buffered the bytes.
+    /// # Errors
+    /// An `Io` error of kind `UnexpectedEof` will be returned if the stream closes
+    /// before `buf` is filled.
+    pub fn read_raw(&mut self, buf: &mut [u8]) -> Result<(), McError> {
+        self.reader.read_exact(buf)?;
+        Ok(())
+    }
+
+    /// Attempts to consume a packet from the pending inbound byte stream. Exactly one
+    /// packet is returned per call; bytes of any following packet which arrived in the
+    /// same read are kept for the next call.
     /// # Returns
     /// The corresponding packet data upon read success.
     /// # Errors
-    /// This function will return an error if the packet could not be properly consumed.
-    pub fn read(&mut self) -> Result<ClientboundRawPacket, io::Error> {
-        const MAX_HEADER_BYTE_SIZE: usize = 6;
-        let mut header_buf: [u8; MAX_HEADER_BYTE_SIZE] = [0; MAX_HEADER_BYTE_SIZE];
-        let bytes_read = self.reader.read(&mut header_buf)?;
-
-        let mut received: Vec<u8> = header_buf.to_vec();
-
-        let len = TryInto::<i32>::try_into(VarInt::from_bytes(&received)?).unwrap() as usize;
-        let mut total_bytes_read = bytes_read;
-        let mut buf: [u8; 256] = [0; 256];
-        while total_bytes_read < len {
-            let bytes_read = self.reader.read(&mut buf)?;
-            received.extend_from_slice(&buf[..bytes_read]);
-            total_bytes_read += bytes_read;
+    /// This function will return an error if the packet could not be properly consumed,
+    /// i.e., the frame is malformed or oversized, the stream closes mid-packet, or a
+    /// compressed packet cannot be decompressed.
+    pub fn read(&mut self) -> Result<ClientboundRawPacket, McError> {
+        let frame = self.frames.read_frame(&mut self.reader)?;
+
+        match self.compression_threshold {
+            Some(threshold) => ClientboundRawPacket::from_frame(compression::decompress_frame(&frame, threshold)?),
+            None => ClientboundRawPacket::from_frame(frame),
         }
+    }
+}
+
+/// Opens a socket to `addr`, tuned and bound as `options` say.
+fn connect_socket(addr: SocketAddr, options: &ConnectionOptions) -> io::Result<TcpStream> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if let Some(bind_address) = options.bind_address() {
+        socket.bind(&SockAddr::from(bind_address))?;
+    }
+    socket.set_nodelay(options.nodelay())?;
+    if let Some(idle) = options.keepalive() {
+        socket.set_tcp_keepalive(&TcpKeepalive::new().with_time(idle))?;
+    }
 
-        ClientboundRawPacket::from_bytes(&mut received)
+    let addr = SockAddr::from(addr);
+    match options.connect_timeout() {
+        Some(timeout) => socket.connect_timeout(&addr, timeout)?,
+        None => socket.connect(&addr)?,
     }
+    Ok(socket.into())
 }
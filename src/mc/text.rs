@@ -0,0 +1,880 @@
+//! Text components, the rich text format used for chat, disconnect reasons,
+//! MOTDs, item names and more. <https://wiki.vg/Text_formatting>
+//! <br>
+//! A `TextComponent` can be parsed from JSON, from NBT (sent by protocol 1.20.3
+//! and later), and from legacy `§`-formatted strings.
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Map, Value};
+
+use super::{
+    error::McError,
+    mctypes::{self, MCType},
+    nbt::{binary, Compound, NetworkNbt, Tag},
+    packet::{builder::PacketBytesBuilder, reader::BufferedPacketReader},
+};
+
+/// The maximum length of a JSON text component sent as a string, in characters.
+pub const MAX_JSON_TEXT_LENGTH: usize = 262144;
+
+/// The character which introduces a legacy formatting code.
+pub const LEGACY_FORMATTING_CHAR: char = '\u{00A7}';
+
+/// The first protocol version, 1.20.3, which sends text components as NBT rather
+/// than JSON strings.
+pub const NBT_TEXT_PROTOCOL: i32 = 765;
+
+/// One of the 16 named chat colors, in the order of their legacy format codes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NamedColor {
+    Black,
+    DarkBlue,
+    DarkGreen,
+    DarkAqua,
+    DarkRed,
+    DarkPurple,
+    Gold,
+    Gray,
+    DarkGray,
+    Blue,
+    Green,
+    Aqua,
+    Red,
+    LightPurple,
+    Yellow,
+    White,
+}
+
+const NAMED_COLORS: [(NamedColor, &str, char); 16] = [
+    (NamedColor::Black, "black", '0'),
+    (NamedColor::DarkBlue, "dark_blue", '1'),
+    (NamedColor::DarkGreen, "dark_green", '2'),
+    (NamedColor::DarkAqua, "dark_aqua", '3'),
+    (NamedColor::DarkRed, "dark_red", '4'),
+    (NamedColor::DarkPurple, "dark_purple", '5'),
+    (NamedColor::Gold, "gold", '6'),
+    (NamedColor::Gray, "gray", '7'),
+    (NamedColor::DarkGray, "dark_gray", '8'),
+    (NamedColor::Blue, "blue", '9'),
+    (NamedColor::Green, "green", 'a'),
+    (NamedColor::Aqua, "aqua", 'b'),
+    (NamedColor::Red, "red", 'c'),
+    (NamedColor::LightPurple, "light_purple", 'd'),
+    (NamedColor::Yellow, "yellow", 'e'),
+    (NamedColor::White, "white", 'f'),
+];
+
+impl NamedColor {
+    /// Gets the JSON name of the color, e.g. `dark_blue`.
+    pub fn name(&self) -> &'static str {
+        NAMED_COLORS[*self as usize].1
+    }
+
+    /// Gets the legacy format code of the color, e.g. `1` for dark blue.
+    pub fn code(&self) -> char {
+        NAMED_COLORS[*self as usize].2
+    }
+
+    /// Looks up a color by its JSON name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        NAMED_COLORS.iter().find(|(_, n, _)| *n == name).map(|(c, _, _)| *c)
+    }
+
+    /// Looks up a color by its legacy format code. Codes are case-insensitive.
+    pub fn from_code(code: char) -> Option<Self> {
+        let code = code.to_ascii_lowercase();
+        NAMED_COLORS.iter().find(|(_, _, c)| *c == code).map(|(c, _, _)| *c)
+    }
+}
+
+/// The color of a text component: either a named color or a `#RRGGBB` hex color.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TextColor {
+    Named(NamedColor),
+    Rgb(u32),
+}
+
+impl TextColor {
+    /// Parses a color from its JSON representation.
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.strip_prefix('#') {
+            Some(hex) if hex.len() == 6 => u32::from_str_radix(hex, 16).ok().map(TextColor::Rgb),
+            Some(_) => None,
+            None => NamedColor::from_name(s).map(TextColor::Named),
+        }
+    }
+
+    /// Gets the JSON representation of the color.
+    pub fn to_json_string(&self) -> String {
+        match self {
+            TextColor::Named(color) => color.name().to_owned(),
+            TextColor::Rgb(rgb) => format!("#{:06X}", rgb),
+        }
+    }
+}
+
+/// The action performed when a text component is clicked.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ClickEvent {
+    OpenUrl(String),
+    RunCommand(String),
+    SuggestCommand(String),
+    ChangePage(String),
+    CopyToClipboard(String),
+    /// Any action this crate does not model, such as the client-only `open_file`.
+    Other { action: String, value: String },
+}
+
+impl ClickEvent {
+    fn from_json(value: &Value) -> Result<Self, McError> {
+        let action = value
+            .get("action")
+            .and_then(Value::as_str)
+            .ok_or_else(|| McError::invalid("Click event has no action"))?;
+        let value = match value.get("value") {
+            Some(Value::String(s)) => s.clone(),
+            Some(Value::Number(n)) => n.to_string(),
+            _ => return Err(McError::invalid("Click event has no value")),
+        };
+
+        Ok(match action {
+            "open_url" => ClickEvent::OpenUrl(value),
+            "run_command" => ClickEvent::RunCommand(value),
+            "suggest_command" => ClickEvent::SuggestCommand(value),
+            "change_page" => ClickEvent::ChangePage(value),
+            "copy_to_clipboard" => ClickEvent::CopyToClipboard(value),
+            _ => ClickEvent::Other { action: action.to_owned(), value },
+        })
+    }
+
+    fn to_json(&self) -> Value {
+        let (action, value) = match self {
+            ClickEvent::OpenUrl(v) => ("open_url", v),
+            ClickEvent::RunCommand(v) => ("run_command", v),
+            ClickEvent::SuggestCommand(v) => ("suggest_command", v),
+            ClickEvent::ChangePage(v) => ("change_page", v),
+            ClickEvent::CopyToClipboard(v) => ("copy_to_clipboard", v),
+            ClickEvent::Other { action, value } => (action.as_str(), value),
+        };
+        serde_json::json!({ "action": action, "value": value })
+    }
+}
+
+/// The tooltip shown when a text component is hovered.
+#[derive(Clone, Debug, PartialEq)]
+pub enum HoverEvent {
+    ShowText(Box<TextComponent>),
+    ShowItem {
+        id: String,
+        count: Option<i32>,
+        /// The item's NBT as SNBT.
+        tag: Option<String>,
+    },
+    ShowEntity {
+        entity_type: String,
+        /// The entity UUID in its hyphenated string form.
+        id: String,
+        name: Option<Box<TextComponent>>,
+    },
+    /// Any action or legacy `value` layout this crate does not model.
+    Other { action: String, contents: Value },
+}
+
+impl HoverEvent {
+    fn from_json(value: &Value) -> Result<Self, McError> {
+        let action = value
+            .get("action")
+            .and_then(Value::as_str)
+            .ok_or_else(|| McError::invalid("Hover event has no action"))?;
+        let contents = value.get("contents");
+        let legacy_value = value.get("value");
+
+        Ok(match (action, contents, legacy_value) {
+            ("show_text", Some(contents), _) | ("show_text", None, Some(contents)) => {
+                HoverEvent::ShowText(Box::new(TextComponent::from_json(contents)?))
+            }
+            ("show_item", Some(Value::String(id)), _) => HoverEvent::ShowItem {
+                id: id.clone(),
+                count: None,
+                tag: None,
+            },
+            ("show_item", Some(contents @ Value::Object(_)), _) => HoverEvent::ShowItem {
+                id: contents
+                    .get("id")
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| McError::invalid("show_item hover event has no id"))?
+                    .to_owned(),
+                count: contents.get("count").and_then(Value::as_i64).map(|c| c as i32),
+                tag: contents.get("tag").and_then(Value::as_str).map(str::to_owned),
+            },
+            ("show_entity", Some(contents @ Value::Object(_)), _) => HoverEvent::ShowEntity {
+                entity_type: contents
+                    .get("type")
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| McError::invalid("show_entity hover event has no type"))?
+                    .to_owned(),
+                id: match contents.get("id") {
+                    Some(Value::String(id)) => id.clone(),
+                    Some(Value::Array(parts)) if parts.len() == 4 => {
+                        let mut bits = 0_u128;
+                        for part in parts {
+                            let part = part.as_i64().ok_or_else(|| McError::invalid("Invalid entity UUID"))?;
+                            bits = (bits << 32) | (part as u32 as u128);
+                        }
+                        uuid::Uuid::from_u128(bits).hyphenated().to_string()
+                    }
+                    _ => return Err(McError::invalid("show_entity hover event has no id")),
+                },
+                name: match contents.get("name") {
+                    Some(name) => Some(Box::new(TextComponent::from_json(name)?)),
+                    None => None,
+                },
+            },
+            _ => HoverEvent::Other {
+                action: action.to_owned(),
+                contents: contents.or(legacy_value).cloned().unwrap_or(Value::Null),
+            },
+        })
+    }
+
+    fn to_json(&self) -> Value {
+        match self {
+            HoverEvent::ShowText(text) => {
+                serde_json::json!({ "action": "show_text", "contents": text.to_json() })
+            }
+            HoverEvent::ShowItem { id, count, tag } => {
+                let mut contents = Map::new();
+                contents.insert("id".into(), id.clone().into());
+                if let Some(count) = count {
+                    contents.insert("count".into(), (*count).into());
+                }
+                if let Some(tag) = tag {
+                    contents.insert("tag".into(), tag.clone().into());
+                }
+                serde_json::json!({ "action": "show_item", "contents": contents })
+            }
+            HoverEvent::ShowEntity { entity_type, id, name } => {
+                let mut contents = Map::new();
+                contents.insert("type".into(), entity_type.clone().into());
+                contents.insert("id".into(), id.clone().into());
+                if let Some(name) = name {
+                    contents.insert("name".into(), name.to_json());
+                }
+                serde_json::json!({ "action": "show_entity", "contents": contents })
+            }
+            HoverEvent::Other { action, contents } => {
+                serde_json::json!({ "action": action, "contents": contents })
+            }
+        }
+    }
+}
+
+/// The formatting of a text component. Unset fields are inherited from the parent.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Style {
+    pub color: Option<TextColor>,
+    pub bold: Option<bool>,
+    pub italic: Option<bool>,
+    pub underlined: Option<bool>,
+    pub strikethrough: Option<bool>,
+    pub obfuscated: Option<bool>,
+    pub font: Option<String>,
+    pub insertion: Option<String>,
+    pub click_event: Option<ClickEvent>,
+    pub hover_event: Option<HoverEvent>,
+}
+
+impl Style {
+    /// Returns `true` if no formatting is set.
+    pub fn is_empty(&self) -> bool {
+        *self == Style::default()
+    }
+}
+
+/// Where the `nbt` content of a text component is read from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NbtSource {
+    /// Coordinates of a block entity.
+    Block(String),
+    /// An entity selector.
+    Entity(String),
+    /// A command storage identifier.
+    Storage(String),
+}
+
+/// The content of a text component, which decides how it renders.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TextContent {
+    Text(String),
+    Translatable {
+        key: String,
+        fallback: Option<String>,
+        with: Vec<TextComponent>,
+    },
+    Score {
+        name: String,
+        objective: String,
+        /// The resolved score, as sent by the server.
+        value: Option<String>,
+    },
+    Selector {
+        selector: String,
+        separator: Option<Box<TextComponent>>,
+    },
+    Keybind(String),
+    Nbt {
+        path: String,
+        interpret: bool,
+        separator: Option<Box<TextComponent>>,
+        source: NbtSource,
+    },
+}
+
+/// A node of formatted text with content, style and child components.
+/// # Example
+/// ```
+/// use mcclient::mc::text::TextComponent;
+///
+/// let json = r#"{"text":"Hello, ","extra":[{"text":"world","bold":true},"!"]}"#;
+/// let component = TextComponent::from_json_str(json).unwrap();
+/// assert_eq!(component.to_plain_string(), "Hello, world!");
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextComponent {
+    pub content: TextContent,
+    pub style: Style,
+    pub extra: Vec<TextComponent>,
+}
+
+impl From<&str> for TextComponent {
+    fn from(value: &str) -> Self {
+        TextComponent::text(value)
+    }
+}
+
+impl From<String> for TextComponent {
+    fn from(value: String) -> Self {
+        TextComponent::text(value)
+    }
+}
+
+impl TextComponent {
+    /// Creates an unstyled plain text component.
+    pub fn text<S: Into<String>>(text: S) -> Self {
+        TextComponent {
+            content: TextContent::Text(text.into()),
+            style: Style::default(),
+            extra: Vec::new(),
+        }
+    }
+
+    /// Creates an unstyled translatable component.
+    pub fn translatable<S: Into<String>>(key: S, with: Vec<TextComponent>) -> Self {
+        TextComponent {
+            content: TextContent::Translatable {
+                key: key.into(),
+                fallback: None,
+                with,
+            },
+            style: Style::default(),
+            extra: Vec::new(),
+        }
+    }
+
+    /// Parses a text component from a JSON string.
+    /// # Errors
+    /// This function will return a `Malformed` error if `json` is not valid JSON
+    /// or does not describe a text component.
+    pub fn from_json_str(json: &str) -> Result<Self, McError> {
+        let value: Value = serde_json::from_str(json)?;
+        TextComponent::from_json(&value)
+    }
+
+    /// Parses a text component from a JSON value. Strings, numbers and booleans are
+    /// shorthand for plain text, and an array is shorthand for its first element
+    /// with the remaining elements appended as children.
+    /// # Errors
+    /// This function will return a `Malformed` error if `value` does not describe
+    /// a text component.
+    pub fn from_json(value: &Value) -> Result<Self, McError> {
+        match value {
+            Value::String(s) => Ok(TextComponent::text(s.clone())),
+            Value::Number(n) => Ok(TextComponent::text(n.to_string())),
+            Value::Bool(b) => Ok(TextComponent::text(b.to_string())),
+            Value::Array(elements) => {
+                let (first, rest) = elements
+                    .split_first()
+                    .ok_or_else(|| McError::invalid("Empty text component array"))?;
+                let mut component = TextComponent::from_json(first)?;
+                for element in rest {
+                    component.extra.push(TextComponent::from_json(element)?);
+                }
+                Ok(component)
+            }
+            Value::Object(object) => TextComponent::from_json_object(object),
+            Value::Null => Err(McError::invalid("Text component is null")),
+        }
+    }
+
+    fn from_json_object(object: &Map<String, Value>) -> Result<Self, McError> {
+        // NBT lists can only hold one tag type, so heterogeneous lists wrap their
+        // elements in a compound with an empty key.
+        if let (1, Some(inner)) = (object.len(), object.get("")) {
+            return TextComponent::from_json(inner);
+        }
+
+        let get_str = |key: &str| object.get(key).and_then(Value::as_str).map(str::to_owned);
+        let get_component = |key: &str| -> Result<Option<Box<TextComponent>>, McError> {
+            match object.get(key) {
+                Some(v) => Ok(Some(Box::new(TextComponent::from_json(v)?))),
+                None => Ok(None),
+            }
+        };
+
+        let content = if let Some(text) = object.get("text") {
+            TextContent::Text(match text {
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            })
+        } else if let Some(key) = get_str("translate") {
+            let with = match object.get("with") {
+                Some(Value::Array(args)) => args
+                    .iter()
+                    .map(TextComponent::from_json)
+                    .collect::<Result<_, _>>()?,
+                Some(_) => return Err(McError::invalid("Translation arguments are not an array")),
+                None => Vec::new(),
+            };
+            TextContent::Translatable {
+                key,
+                fallback: get_str("fallback"),
+                with,
+            }
+        } else if let Some(score) = object.get("score") {
+            TextContent::Score {
+                name: score.get("name").and_then(Value::as_str).unwrap_or_default().to_owned(),
+                objective: score
+                    .get("objective")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_owned(),
+                value: score.get("value").and_then(Value::as_str).map(str::to_owned),
+            }
+        } else if let Some(selector) = get_str("selector") {
+            TextContent::Selector {
+                selector,
+                separator: get_component("separator")?,
+            }
+        } else if let Some(keybind) = get_str("keybind") {
+            TextContent::Keybind(keybind)
+        } else if let Some(path) = get_str("nbt") {
+            let source = if let Some(block) = get_str("block") {
+                NbtSource::Block(block)
+            } else if let Some(entity) = get_str("entity") {
+                NbtSource::Entity(entity)
+            } else if let Some(storage) = get_str("storage") {
+                NbtSource::Storage(storage)
+            } else {
+                return Err(McError::invalid("NBT text component has no source"));
+            };
+            TextContent::Nbt {
+                path,
+                interpret: object.get("interpret").and_then(json_bool).unwrap_or(false),
+                separator: get_component("separator")?,
+                source,
+            }
+        } else {
+            return Err(McError::invalid("Text component has no recognised content"));
+        };
+
+        let style = Style {
+            color: match get_str("color") {
+                Some(color) => Some(
+                    TextColor::parse(&color)
+                        .ok_or_else(|| McError::invalid(format!("Unknown text color '{}'", color)))?,
+                ),
+                None => None,
+            },
+            bold: object.get("bold").and_then(json_bool),
+            italic: object.get("italic").and_then(json_bool),
+            underlined: object.get("underlined").and_then(json_bool),
+            strikethrough: object.get("strikethrough").and_then(json_bool),
+            obfuscated: object.get("obfuscated").and_then(json_bool),
+            font: get_str("font"),
+            insertion: get_str("insertion"),
+            click_event: match object.get("clickEvent").or_else(|| object.get("click_event")) {
+                Some(v) => Some(ClickEvent::from_json(v)?),
+                None => None,
+            },
+            hover_event: match object.get("hoverEvent").or_else(|| object.get("hover_event")) {
+                Some(v) => Some(HoverEvent::from_json(v)?),
+                None => None,
+            },
+        };
+
+        let extra = match object.get("extra") {
+            Some(Value::Array(children)) => children
+                .iter()
+                .map(TextComponent::from_json)
+                .collect::<Result<_, _>>()?,
+            Some(_) => return Err(McError::invalid("Text component extra is not an array")),
+            None => Vec::new(),
+        };
+
+        Ok(TextComponent { content, style, extra })
+    }
+
+    /// Serializes the component to a JSON value.
+    pub fn to_json(&self) -> Value {
+        let mut object = Map::new();
+
+        match &self.content {
+            TextContent::Text(text) => {
+                object.insert("text".into(), text.clone().into());
+            }
+            TextContent::Translatable { key, fallback, with } => {
+                object.insert("translate".into(), key.clone().into());
+                if let Some(fallback) = fallback {
+                    object.insert("fallback".into(), fallback.clone().into());
+                }
+                if !with.is_empty() {
+                    object.insert("with".into(), with.iter().map(TextComponent::to_json).collect());
+                }
+            }
+            TextContent::Score { name, objective, value } => {
+                let mut score = Map::new();
+                score.insert("name".into(), name.clone().into());
+                score.insert("objective".into(), objective.clone().into());
+                if let Some(value) = value {
+                    score.insert("value".into(), value.clone().into());
+                }
+                object.insert("score".into(), score.into());
+            }
+            TextContent::Selector { selector, separator } => {
+                object.insert("selector".into(), selector.clone().into());
+                if let Some(separator) = separator {
+                    object.insert("separator".into(), separator.to_json());
+                }
+            }
+            TextContent::Keybind(keybind) => {
+                object.insert("keybind".into(), keybind.clone().into());
+            }
+            TextContent::Nbt { path, interpret, separator, source } => {
+                object.insert("nbt".into(), path.clone().into());
+                if *interpret {
+                    object.insert("interpret".into(), true.into());
+                }
+                if let Some(separator) = separator {
+                    object.insert("separator".into(), separator.to_json());
+                }
+                let (key, value) = match source {
+                    NbtSource::Block(v) => ("block", v),
+                    NbtSource::Entity(v) => ("entity", v),
+                    NbtSource::Storage(v) => ("storage", v),
+                };
+                object.insert(key.into(), value.clone().into());
+            }
+        }
+
+        let style = &self.style;
+        if let Some(color) = &style.color {
+            object.insert("color".into(), color.to_json_string().into());
+        }
+        for (key, value) in [
+            ("bold", style.bold),
+            ("italic", style.italic),
+            ("underlined", style.underlined),
+            ("strikethrough", style.strikethrough),
+            ("obfuscated", style.obfuscated),
+        ] {
+            if let Some(value) = value {
+                object.insert(key.into(), value.into());
+            }
+        }
+        if let Some(font) = &style.font {
+            object.insert("font".into(), font.clone().into());
+        }
+        if let Some(insertion) = &style.insertion {
+            object.insert("insertion".into(), insertion.clone().into());
+        }
+        if let Some(click_event) = &style.click_event {
+            object.insert("clickEvent".into(), click_event.to_json());
+        }
+        if let Some(hover_event) = &style.hover_event {
+            object.insert("hoverEvent".into(), hover_event.to_json());
+        }
+
+        if !self.extra.is_empty() {
+            object.insert("extra".into(), self.extra.iter().map(TextComponent::to_json).collect());
+        }
+
+        Value::Object(object)
+    }
+
+    /// Serializes the component to a compact JSON string.
+    pub fn to_json_string(&self) -> String {
+        self.to_json().to_string()
+    }
+
+    /// Parses a text component from NBT, as sent by protocol 1.20.3 and later. A
+    /// string tag is shorthand for plain text, and booleans are bytes.
+    /// # Errors
+    /// This function will return a `Malformed` error if `tag` does not describe a
+    /// text component.
+    pub fn from_nbt(tag: &Tag) -> Result<Self, McError> {
+        TextComponent::from_json(&nbt_to_json(tag))
+    }
+
+    /// Serializes the component to NBT, as sent by protocol 1.20.3 and later.
+    pub fn to_nbt(&self) -> Tag {
+        json_to_nbt(&self.to_json())
+    }
+
+    /// Reads a component as sent by `protocol_version`, i.e., as NBT from 1.20.3 and
+    /// as a JSON string before.
+    /// # Errors
+    /// This function will return a `Malformed` error if the component cannot be read
+    /// or parsed.
+    pub fn read_with(reader: &mut BufferedPacketReader, protocol_version: i32) -> Result<Self, McError> {
+        if protocol_version >= NBT_TEXT_PROTOCOL {
+            let tag = binary::read_nameless(reader)?.ok_or_else(|| McError::invalid("Chat NBT is empty"))?;
+            TextComponent::from_nbt(&tag)
+        } else {
+            TextComponent::from_json_str(&reader.read_string(MAX_JSON_TEXT_LENGTH)?)
+        }
+    }
+
+    /// Appends the component as sent by `protocol_version` to `builder`. See `read_with`.
+    /// # Errors
+    /// This function will return a `Malformed` error if the component cannot be
+    /// encoded as NBT, e.g., a string is longer than 65535 bytes once encoded.
+    pub fn write_with(&self, builder: PacketBytesBuilder, protocol_version: i32) -> Result<PacketBytesBuilder, McError> {
+        if protocol_version >= NBT_TEXT_PROTOCOL {
+            builder.try_append_network_nbt(Some(&NetworkNbt(self.to_nbt())))
+        } else {
+            Ok(builder.append_string(self.to_json_string()))
+        }
+    }
+
+    /// Parses a legacy string using `§` format codes, e.g. `§aGreen §lbold`. A color
+    /// code resets any formatting before it, and `§r` resets everything.
+    pub fn from_legacy(legacy: &str) -> Self {
+        let mut root = TextComponent::text("");
+        let mut style = Style::default();
+        let mut run = String::new();
+        let mut chars = legacy.chars();
+
+        while let Some(c) = chars.next() {
+            if c != LEGACY_FORMATTING_CHAR {
+                run.push(c);
+                continue;
+            }
+            let Some(code) = chars.next() else {
+                break;
+            };
+
+            if !run.is_empty() {
+                root.extra.push(TextComponent {
+                    content: TextContent::Text(std::mem::take(&mut run)),
+                    style: style.clone(),
+                    extra: Vec::new(),
+                });
+            }
+
+            match code.to_ascii_lowercase() {
+                'k' => style.obfuscated = Some(true),
+                'l' => style.bold = Some(true),
+                'm' => style.strikethrough = Some(true),
+                'n' => style.underlined = Some(true),
+                'o' => style.italic = Some(true),
+                'r' => style = Style::default(),
+                other => {
+                    if let Some(color) = NamedColor::from_code(other) {
+                        style = Style {
+                            color: Some(TextColor::Named(color)),
+                            ..Style::default()
+                        };
+                    }
+                }
+            }
+        }
+
+        if !run.is_empty() {
+            root.extra.push(TextComponent {
+                content: TextContent::Text(run),
+                style,
+                extra: Vec::new(),
+            });
+        }
+
+        // Collapse a single unstyled run into plain text.
+        if root.extra.len() == 1 && root.extra[0].style.is_empty() {
+            return root.extra.remove(0);
+        }
+        root
+    }
+
+    /// Renders the component and its children as unformatted text, e.g. for logs.
+    /// Translation keys are not resolved; the fallback or the key itself is used as
+    /// the template for the arguments.
+    pub fn to_plain_string(&self) -> String {
+        let mut out = String::new();
+        self.write_plain(&mut out);
+        out
+    }
+
+    fn write_plain(&self, out: &mut String) {
+        match &self.content {
+            TextContent::Text(text) => out.push_str(text),
+            TextContent::Translatable { key, fallback, with } => {
+                let template = fallback.as_deref().unwrap_or(key);
+                let args: Vec<String> = with.iter().map(TextComponent::to_plain_string).collect();
+                out.push_str(&format_translation(template, &args));
+            }
+            TextContent::Score { value, .. } => out.push_str(value.as_deref().unwrap_or_default()),
+            TextContent::Selector { selector, .. } => out.push_str(selector),
+            TextContent::Keybind(keybind) => out.push_str(keybind),
+            TextContent::Nbt { .. } => {}
+        }
+
+        for child in &self.extra {
+            child.write_plain(out);
+        }
+    }
+}
+
+/// Substitutes `%s` and positional `%1$s` placeholders of a translation template.
+fn format_translation(template: &str, args: &[String]) -> String {
+    let mut out = String::new();
+    let mut next_arg = 0;
+    let mut rest = template;
+
+    while let Some(i) = rest.find('%') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+
+        if let Some(stripped) = rest.strip_prefix('%') {
+            out.push('%');
+            rest = stripped;
+        } else if let Some(stripped) = rest.strip_prefix('s') {
+            out.push_str(args.get(next_arg).map(String::as_str).unwrap_or_default());
+            next_arg += 1;
+            rest = stripped;
+        } else {
+            let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+            match (rest[..digits].parse::<usize>(), rest[digits..].strip_prefix("$s")) {
+                (Ok(n), Some(stripped)) if n > 0 => {
+                    out.push_str(args.get(n - 1).map(String::as_str).unwrap_or_default());
+                    rest = stripped;
+                }
+                _ => out.push('%'),
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+/// Reads a JSON boolean, also accepting the `0`/`1` numbers NBT uses for booleans.
+fn json_bool(value: &Value) -> Option<bool> {
+    match value {
+        Value::Bool(b) => Some(*b),
+        Value::Number(n) => n.as_i64().map(|n| n != 0),
+        _ => None,
+    }
+}
+
+/// Converts an NBT tag to the equivalent JSON value.
+fn nbt_to_json(tag: &Tag) -> Value {
+    match tag {
+        Tag::Byte(v) => (*v).into(),
+        Tag::Short(v) => (*v).into(),
+        Tag::Int(v) => (*v).into(),
+        Tag::Long(v) => (*v).into(),
+        Tag::Float(v) => (*v as f64).into(),
+        Tag::Double(v) => (*v).into(),
+        Tag::ByteArray(array) => array.iter().map(|v| Value::from(*v)).collect(),
+        Tag::String(s) => s.clone().into(),
+        Tag::List(list) => list.iter().map(nbt_to_json).collect(),
+        Tag::Compound(compound) => Value::Object(
+            compound
+                .iter()
+                .map(|(k, v)| (k.to_owned(), nbt_to_json(v)))
+                .collect(),
+        ),
+        Tag::IntArray(array) => array.iter().map(|v| Value::from(*v)).collect(),
+        Tag::LongArray(array) => array.iter().map(|v| Value::from(*v)).collect(),
+    }
+}
+
+/// Converts a JSON value to the equivalent NBT tag. Booleans become bytes, and
+/// lists mixing tag types have their elements wrapped in `{"": value}` compounds.
+fn json_to_nbt(value: &Value) -> Tag {
+    match value {
+        Value::Null => Tag::Compound(Compound::new()),
+        Value::Bool(b) => Tag::Byte(*b as i8),
+        Value::Number(n) => match (n.as_i64(), n.as_f64()) {
+            (Some(i), _) if i32::try_from(i).is_ok() => Tag::Int(i as i32),
+            (Some(i), _) => Tag::Long(i),
+            (None, Some(f)) => Tag::Double(f),
+            (None, None) => Tag::String(n.to_string()),
+        },
+        Value::String(s) => Tag::String(s.clone()),
+        Value::Array(elements) => {
+            let tags: Vec<Tag> = elements.iter().map(json_to_nbt).collect();
+            let homogeneous = tags.windows(2).all(|w| w[0].id() == w[1].id());
+            if homogeneous {
+                Tag::List(tags)
+            } else {
+                Tag::List(
+                    tags.into_iter()
+                        .map(|t| match t {
+                            Tag::Compound(c) => Tag::Compound(c),
+                            other => Tag::Compound([("", other)].into_iter().collect()),
+                        })
+                        .collect(),
+                )
+            }
+        }
+        Value::Object(object) => Tag::Compound(
+            object
+                .iter()
+                .map(|(k, v)| (k.clone(), json_to_nbt(v)))
+                .collect(),
+        ),
+    }
+}
+
+impl MCType for TextComponent {
+    /// Encodes the component as a JSON string, as sent by protocol versions before 1.20.3.
+    fn write_to(&self, buf: &mut Vec<u8>) {
+        mctypes::write_str(buf, &self.to_json_string())
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, McError> {
+        TextComponent::read_from(&mut BufferedPacketReader::new(bytes))
+    }
+
+    fn read_from(reader: &mut BufferedPacketReader) -> Result<Self, McError> {
+        TextComponent::from_json_str(&reader.read_string(MAX_JSON_TEXT_LENGTH)?)
+    }
+
+    fn size(&self) -> i32 {
+        mctypes::str_size(&self.to_json_string())
+    }
+}
+
+impl Serialize for TextComponent {
+    /// Serializes the component as its JSON value, e.g., inside a status response.
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_json().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for TextComponent {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        TextComponent::from_json(&value).map_err(de::Error::custom)
+    }
+}
@@ -1,13 +1,56 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
     use crate::mc::{
-        mctypes::{MCType, VarInt},
+        client::Client,
+        compression,
+        configuration::{
+            self, ClientboundConfigurationType, ConfigurationPacket, ServerboundConfigurationPacket,
+            ServerboundConfigurationType,
+        },
+        connection::{offline_uuid, Connection, OfflineConnection},
+        encryption::{self, DecryptingReader, EncryptingWriter},
+        error::{DataError, McError},
+        frame::{FrameDecoder, MAX_FRAME_SIZE},
+        legacy::{self, LegacyPingFormat},
+        item::{DataComponent, DataComponents, EnchantmentId, ItemData, ItemStack, SlotFormat},
+        metadata::{
+            Direction, EntityMetadata, GlobalPos, MetadataType, MetadataValue, Particle, ParticleData, Pose,
+            Rotations, VibrationSource, VillagerData, METADATA_END,
+        },
+        mctypes::{
+            Angle, BitSet, FixedBitSet, FixedPointByte, FixedPointInt, Identifier, MCString, MCType,
+            Position, PrefixedArray, PrefixedOption, StringError, VarInt, VarLong, MAX_USERNAME_LENGTH,
+        },
+        nbt::{snbt, Compound, NbtCompression, NetworkNbt, Nbt, Tag},
+        options::{ConnectionOptions, ReconnectPolicy},
         packet::{
+            clientbound::{
+                encryption_request::EncryptionRequest,
+                login_disconnect::LoginDisconnect,
+                login_plugin_request::LoginPluginRequest,
+                login_success::{LoginSuccess, Property},
+                ping_response::PingResponse,
+                set_compression::SetCompression,
+                status_response::StatusResponse,
+            },
+            packet_ids,
             serverbound::{
+                encryption_response::EncryptionResponse,
+                login_plugin_response::LoginPluginResponse,
                 handshake::{Handshake, NextState},
+                login_start::LoginStart,
                 status_request::StatusRequest,
-            }, OutboundPacketBuffer, OutboundPacket,
+            },
+            state::{self, ProtocolState}, builder::PacketBytesBuilder, reader::BufferedPacketReader,
+            write_packet, ClientboundPacket, ClientboundRawPacket, ConnectionState, InboundPacket, OutboundPacketBuffer,
+            OutboundPacket, Packet, PacketDirection, ServerboundPacket, ServerboundRawPacket,
         },
+        resolver::{Resolver, ServerAddress, SrvRecord, DEFAULT_PORT},
+        session::{PlayPacketIds, PlaySession, SessionEvent},
+        status::{Favicon, ServerStatus},
+        stream::MinecraftStream,
+        text::{ClickEvent, HoverEvent, NamedColor, TextColor, TextComponent, TextContent},
         PROTOCOL_VERSION,
     };
 
@@ -28,37 +71,39 @@ mod tests {
 
     #[test]
     fn from_varint_to_i32() {
-        assert_eq!(0, TryInto::<i32>::try_into(VarInt::from(&[0][..])).unwrap());
-        assert_eq!(1, TryInto::<i32>::try_into(VarInt::from(&[1][..])).unwrap());
-        assert_eq!(2, TryInto::<i32>::try_into(VarInt::from(&[2][..])).unwrap());
-        assert_eq!(127, TryInto::<i32>::try_into(VarInt::from(&[127][..])).unwrap());
-        assert_eq!(128, TryInto::<i32>::try_into(VarInt::from(&[128, 1][..])).unwrap());
-        assert_eq!(255, TryInto::<i32>::try_into(VarInt::from(&[255, 1][..])).unwrap());
-        assert_eq!(25565, TryInto::<i32>::try_into(VarInt::from(&[221, 199, 1][..])).unwrap());
-        assert_eq!(2097151, TryInto::<i32>::try_into(VarInt::from(&[255, 255, 127][..])).unwrap());
+        assert_eq!(0, i32::from(VarInt::try_from(&[0][..]).unwrap()));
+        assert_eq!(1, i32::from(VarInt::try_from(&[1][..]).unwrap()));
+        assert_eq!(2, i32::from(VarInt::try_from(&[2][..]).unwrap()));
+        assert_eq!(127, i32::from(VarInt::try_from(&[127][..]).unwrap()));
+        assert_eq!(128, i32::from(VarInt::try_from(&[128, 1][..]).unwrap()));
+        assert_eq!(255, i32::from(VarInt::try_from(&[255, 1][..]).unwrap()));
+        assert_eq!(25565, i32::from(VarInt::try_from(&[221, 199, 1][..]).unwrap()));
+        assert_eq!(2097151, i32::from(VarInt::try_from(&[255, 255, 127][..]).unwrap()));
         assert_eq!(
             2147483647,
-            TryInto::<i32>::try_into(VarInt::from(&[255, 255, 255, 255, 7][..])).unwrap()
+            i32::from(VarInt::try_from(&[255, 255, 255, 255, 7][..]).unwrap())
         );
-        assert_eq!(-1, TryInto::<i32>::try_into(VarInt::from(&[255, 255, 255, 255, 15][..])).unwrap());
+        assert_eq!(-1, i32::from(VarInt::try_from(&[255, 255, 255, 255, 15][..]).unwrap()));
         assert_eq!(
             -2147483648,
-            TryInto::<i32>::try_into(VarInt::from(&[128, 128, 128, 128, 8][..])).unwrap()
+            i32::from(VarInt::try_from(&[128, 128, 128, 128, 8][..]).unwrap())
         );
     }
 
     #[test]
     fn from_bytes_to_varint() {
-        assert_eq!(&[0][..], VarInt::from(&[0, 0][..]).bytes());
-        assert_eq!(&[1][..], VarInt::from(&[1, 0][..]).bytes());
-        assert_eq!(&[2][..], VarInt::from(&[2, 0][..]).bytes());
-        assert_eq!(&[127][..], VarInt::from(&[127, 0][..]).bytes());
-        assert_eq!(&[128, 1][..], VarInt::from(&[128, 1, 0][..]).bytes());
-        assert_eq!(&[255, 1][..], VarInt::from(&[255, 1, 0][..]).bytes());
+        assert_eq!(&[0][..], VarInt::try_from(&[0, 0][..]).unwrap().bytes());
+        assert_eq!(&[1][..], VarInt::try_from(&[1, 0][..]).unwrap().bytes());
+        assert_eq!(&[2][..], VarInt::try_from(&[2, 0][..]).unwrap().bytes());
+        assert_eq!(&[127][..], VarInt::try_from(&[127, 0][..]).unwrap().bytes());
+        assert_eq!(&[128, 1][..], VarInt::try_from(&[128, 1, 0][..]).unwrap().bytes());
+        assert_eq!(&[255, 1][..], VarInt::try_from(&[255, 1, 0][..]).unwrap().bytes());
         assert_eq!(
             &[221, 199, 1][..],
-            VarInt::from(&[221, 199, 1, 0][..]).bytes()
+            VarInt::try_from(&[221, 199, 1, 0][..]).unwrap().bytes()
         );
+        assert!(VarInt::try_from(&[255, 255, 255, 255, 255, 1][..]).is_err());
+        assert!(VarInt::try_from(&[255, 255][..]).is_err());
     }
 
     #[test]
@@ -84,7 +129,7 @@ mod tests {
         fake_packet_bytes.append(&mut VarInt::from(NextState::STATUS as i32).to_bytes());
 
         //assert_eq!(serialize_packet(&handshake), fake_packet_bytes);
-        assert_eq!(*OutboundPacketBuffer::from(&handshake as &dyn OutboundPacket).data(), fake_packet_bytes);
+        assert_eq!(*OutboundPacketBuffer::try_from(&handshake as &dyn OutboundPacket).unwrap().data(), fake_packet_bytes);
     }
 
     #[test]
@@ -92,6 +137,2375 @@ mod tests {
         let status_request = StatusRequest;
 
         //assert_eq!(serialize_packet(&status_request), vec![0x01, 0x00]);
-        assert_eq!(*OutboundPacketBuffer::from(&status_request as &dyn OutboundPacket).data(), vec![0x01, 0x00]);
+        assert_eq!(*OutboundPacketBuffer::try_from(&status_request as &dyn OutboundPacket).unwrap().data(), vec![0x01, 0x00]);
+    }
+
+    #[test]
+    fn frame_decoder_splits_back_to_back_packets() {
+        let mut decoder = FrameDecoder::new();
+        decoder.feed(&[0x02, 0x00, 0xAA, 0x03, 0x01, 0xBB, 0xCC, 0x01]);
+
+        assert_eq!(decoder.next_frame().unwrap(), Some(vec![0x00, 0xAA]));
+        assert_eq!(decoder.next_frame().unwrap(), Some(vec![0x01, 0xBB, 0xCC]));
+        assert_eq!(decoder.next_frame().unwrap(), None);
+        assert_eq!(decoder.buffered(), 1);
+
+        decoder.feed(&[0x07]);
+        assert_eq!(decoder.next_frame().unwrap(), Some(vec![0x07]));
+        assert_eq!(decoder.buffered(), 0);
+    }
+
+    #[test]
+    fn frame_decoder_reassembles_partial_reads() {
+        let mut frame = VarInt::from(300).to_bytes();
+        frame.extend([0x42; 300]);
+
+        let mut decoder = FrameDecoder::new();
+        decoder.feed(&frame[..1]);
+        assert_eq!(decoder.next_frame().unwrap(), None);
+        decoder.feed(&frame[1..100]);
+        assert_eq!(decoder.next_frame().unwrap(), None);
+        decoder.feed(&frame[100..]);
+        assert_eq!(decoder.next_frame().unwrap().unwrap().len(), 300);
+
+        // One byte at a time from a reader, as a slow socket would deliver it.
+        struct Trickle<'a>(&'a [u8]);
+        impl std::io::Read for Trickle<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                if self.0.is_empty() || buf.is_empty() {
+                    return Ok(0);
+                }
+                buf[0] = self.0[0];
+                self.0 = &self.0[1..];
+                Ok(1)
+            }
+        }
+        let mut decoder = FrameDecoder::new();
+        let packet = ClientboundRawPacket::from_frame(decoder.read_frame(&mut Trickle(&frame)).unwrap()).unwrap();
+        assert_eq!(packet.header.id, 0x42);
+        assert_eq!(packet.data.len(), 299);
+        assert!(decoder.read_frame(&mut Trickle(&[])).is_err());
+    }
+
+    #[test]
+    fn frame_decoder_rejects_oversized_prefix() {
+        let mut decoder = FrameDecoder::new();
+        decoder.feed(&VarInt::from(MAX_FRAME_SIZE as i32 + 1).to_bytes());
+        assert!(decoder.next_frame().is_err());
+
+        let mut decoder = FrameDecoder::new();
+        decoder.feed(&[0x80, 0x80, 0x80, 0x00]);
+        assert!(decoder.next_frame().is_err());
+
+        let mut decoder = FrameDecoder::new();
+        decoder.feed(&VarInt::from(MAX_FRAME_SIZE as i32).to_bytes());
+        assert_eq!(decoder.next_frame().unwrap(), None);
+    }
+
+    #[test]
+    fn stream_refuses_oversized_frames() {
+        use std::{net::TcpListener, thread};
+
+        use rand::RngCore;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            FrameDecoder::new().read_frame(&mut socket).unwrap()
+        });
+
+        let mut stream = MinecraftStream::connect(("127.0.0.1", port)).unwrap();
+        // The packet ID takes one byte of the frame.
+        let fitting = ServerboundRawPacket { id: 0x01, data: vec![0; MAX_FRAME_SIZE - 1] };
+        let mut oversized = ServerboundRawPacket { id: 0x01, data: vec![0; MAX_FRAME_SIZE] };
+        assert!(matches!(stream.write(&oversized).unwrap_err(), McError::Malformed { .. }));
+
+        // Random data does not compress, so the compressed frame is oversized too.
+        stream.set_compression(256);
+        rand::thread_rng().fill_bytes(&mut oversized.data);
+        assert!(matches!(stream.write(&oversized).unwrap_err(), McError::Malformed { .. }));
+
+        // Nothing was written, so the server reads the next packet first.
+        stream.set_compression(-1);
+        stream.send(&fitting).unwrap();
+        assert_eq!(server.join().unwrap().len(), MAX_FRAME_SIZE);
+    }
+
+    #[test]
+    fn buffered_reader_reads_typed_fields() {
+        let uuid = uuid::Uuid::from_u128(0x0123_4567_89ab_cdef_0011_2233_4455_6677);
+        let mut bytes = PacketBytesBuilder::new()
+            .append_varint(&VarInt::from(25565))
+            .append_string("Makoto")
+            .append_bool(true)
+            .append_u16(0xBEEF)
+            .append_bytes(&(-5_i64).to_be_bytes())
+            .append_bytes(&1.5_f32.to_be_bytes())
+            .append_bytes(uuid.as_bytes())
+            .build();
+        bytes.push(0xFF);
+
+        let mut reader = BufferedPacketReader::new(&bytes);
+        assert_eq!(reader.read_varint().unwrap(), 25565);
+        assert_eq!(reader.read_string(16).unwrap(), "Makoto");
+        assert!(reader.read_bool().unwrap());
+        assert_eq!(reader.read_u16().unwrap(), 0xBEEF);
+        assert_eq!(reader.read_i64().unwrap(), -5);
+        assert_eq!(reader.read_f32().unwrap(), 1.5);
+        assert_eq!(reader.read_uuid().unwrap(), uuid);
+        assert_eq!(reader.remaining(), 1);
+        assert!(reader.finish().is_err());
+    }
+
+    #[test]
+    fn buffered_reader_reports_truncation() {
+        let mut reader = BufferedPacketReader::new(&[0x00, 0x01]);
+        let err = reader.read_i32().unwrap_err();
+        assert!(matches!(
+            err,
+            McError::Malformed { error: DataError::Truncated { needed: 4, remaining: 2, position: 0 }, .. }
+        ));
+        assert_eq!(reader.position(), 0);
+
+        // Declares 10 bytes but only carries 2.
+        let mut reader = BufferedPacketReader::new(&[0x0A, b'h', b'i']);
+        assert!(matches!(
+            reader.read_string(16).unwrap_err(),
+            McError::Malformed { error: DataError::Truncated { .. }, .. }
+        ));
+
+        let mut reader = BufferedPacketReader::new(&[0x80, 0x80]);
+        assert!(matches!(
+            reader.read_varint().unwrap_err(),
+            McError::Malformed { error: DataError::Truncated { .. }, .. }
+        ));
+
+        let mut reader = BufferedPacketReader::new(&[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01]);
+        assert!(matches!(
+            reader.read_varint().unwrap_err(),
+            McError::Malformed { error: DataError::Invalid(_), .. }
+        ));
+    }
+
+    #[test]
+    fn buffered_reader_reads_declared_string_length() {
+        let bytes = PacketBytesBuilder::new()
+            .append_string("abc")
+            .append_string("Makoto")
+            .build();
+
+        let mut reader = BufferedPacketReader::new(&bytes);
+        assert!(reader.read_string(2).is_err());
+
+        let mut reader = BufferedPacketReader::new(&bytes);
+        assert_eq!(String::from(reader.read::<MCString>().unwrap()), "abc");
+        assert_eq!(String::from(reader.read::<MCString>().unwrap()), "Makoto");
+        assert!(reader.finish().is_ok());
+    }
+
+    #[test]
+    fn varlong_round_trip() {
+        assert_eq!(VarLong::from(0).bytes(), [0]);
+        assert_eq!(VarLong::from(127).bytes(), [127]);
+        assert_eq!(VarLong::from(128).bytes(), [128, 1]);
+        assert_eq!(VarLong::from(2147483647).bytes(), [255, 255, 255, 255, 7]);
+        assert_eq!(
+            VarLong::from(i64::MAX).bytes(),
+            [255, 255, 255, 255, 255, 255, 255, 255, 127]
+        );
+        assert_eq!(
+            VarLong::from(-1).bytes(),
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 1]
+        );
+        assert_eq!(
+            VarLong::from(i64::MIN).bytes(),
+            [128, 128, 128, 128, 128, 128, 128, 128, 128, 1]
+        );
+
+        for value in [0, 1, -1, 300, i32::MAX as i64 + 1, i64::MAX, i64::MIN] {
+            let bytes = VarLong::from(value).to_bytes();
+            assert_eq!(i64::from(VarLong::from_bytes(&bytes).unwrap()), value);
+            assert_eq!(BufferedPacketReader::new(&bytes).read_varlong().unwrap(), value);
+        }
+        assert!(VarLong::from_bytes(&[0xFF; 11]).is_err());
+    }
+
+    #[test]
+    fn fixed_width_types_round_trip() {
+        fn round_trip<T: MCType + PartialEq + std::fmt::Debug>(value: T, len: usize) {
+            let bytes = value.to_bytes();
+            assert_eq!(bytes.len(), len);
+            assert_eq!(value.size() as usize, len);
+            assert_eq!(T::from_bytes(&bytes).unwrap(), value);
+        }
+
+        round_trip(-128_i8, 1);
+        round_trip(255_u8, 1);
+        round_trip(-12345_i16, 2);
+        round_trip(65535_u16, 2);
+        round_trip(i32::MIN, 4);
+        round_trip(i64::MAX, 8);
+        round_trip(-0.25_f32, 4);
+        round_trip(std::f64::consts::PI, 8);
+        round_trip(true, 1);
+        round_trip(uuid::Uuid::from_u128(0x0123_4567_89ab_cdef_0011_2233_4455_6677), 16);
+        round_trip(Angle(200), 1);
+        round_trip(FixedPointInt::from_f64(-12.5), 4);
+        round_trip(FixedPointByte::from_f64(3.25), 1);
+
+        assert_eq!(0x1234_i16.to_bytes(), [0x12, 0x34]);
+        assert_eq!(
+            uuid::Uuid::from_u128(0x0123_4567_89ab_cdef_0011_2233_4455_6677).to_bytes()[..4],
+            [0x01, 0x23, 0x45, 0x67]
+        );
+        assert_eq!(FixedPointInt::from_f64(-12.5).to_f64(), -12.5);
+        assert_eq!(FixedPointInt::from_f64(1.0).0, 32);
+        assert!(bool::from_bytes(&[2]).is_err());
+        assert!(i32::from_bytes(&[0, 0]).is_err());
+    }
+
+    #[test]
+    fn fixed_point_rounds_to_nearest() {
+        assert_eq!(FixedPointInt::from_f64(0.99 / 32.0).0, 1);
+        assert_eq!(FixedPointInt::from_f64(-0.99 / 32.0).0, -1);
+        assert_eq!(FixedPointInt::from_f64(0.49 / 32.0).0, 0);
+        assert_eq!(FixedPointByte::from_f64(0.99 / 32.0).0, 1);
+        assert_eq!(FixedPointByte::from_f64(-3.99 / 32.0).0, -4);
+    }
+
+    #[test]
+    fn angle_conversion() {
+        assert_eq!(Angle::from_degrees(0.0), Angle(0));
+        assert_eq!(Angle::from_degrees(90.0), Angle(64));
+        assert_eq!(Angle::from_degrees(-90.0), Angle(192));
+        assert_eq!(Angle::from_degrees(360.0), Angle(0));
+        assert_eq!(Angle(128).degrees(), 180.0);
+    }
+
+    #[test]
+    fn position_packing() {
+        // Example from https://wiki.vg/Protocol#Position
+        const PACKED: i64 = 0x4607_632C_15B4_833F;
+        let position = Position::from_packed(PACKED);
+        assert_eq!(position, Position::new(18357644, 831, -20882616));
+        assert_eq!(position.to_packed(), PACKED);
+
+        for position in [
+            Position::new(0, 0, 0),
+            Position::new(-1, -1, -1),
+            Position::new(-33554432, -2048, 33554431),
+            Position::new(33554431, 2047, -33554432),
+        ] {
+            let bytes = position.to_bytes();
+            assert_eq!(Position::from_bytes(&bytes).unwrap(), position);
+            let built = PacketBytesBuilder::new().append_position(&position).build();
+            assert_eq!(BufferedPacketReader::new(&built).read_position().unwrap(), position);
+        }
+    }
+
+    #[test]
+    fn nbt_binary_round_trip() {
+        // hello_world.nbt from https://wiki.vg/NBT#Examples
+        let mut hello_world = vec![0x0A, 0x00, 0x0B];
+        hello_world.extend(b"hello world");
+        hello_world.extend([0x08, 0x00, 0x04]);
+        hello_world.extend(b"name");
+        hello_world.extend([0x00, 0x09]);
+        hello_world.extend(b"Bananrama");
+        hello_world.push(0x00);
+
+        let nbt = BufferedPacketReader::new(&hello_world).read_nbt().unwrap().unwrap();
+        assert_eq!(nbt.name, "hello world");
+        assert_eq!(nbt.tag.as_compound().unwrap().get_str("name"), Some("Bananrama"));
+        assert_eq!(PacketBytesBuilder::new().try_append_nbt(Some(&nbt)).unwrap().build(), hello_world);
+
+        let compound: Compound = [
+            ("byte", Tag::Byte(-1)),
+            ("long", Tag::Long(i64::MIN)),
+            ("double", Tag::Double(0.5)),
+            ("bytes", Tag::ByteArray(vec![1, -2, 3])),
+            ("ints", Tag::IntArray(vec![i32::MAX, 0])),
+            ("longs", Tag::LongArray(vec![])),
+            ("list", Tag::List(vec![Tag::Short(1), Tag::Short(2)])),
+            ("empty", Tag::List(vec![])),
+            ("text", Tag::String("nul \0 and \u{1F600}".into())),
+        ]
+        .into_iter()
+        .collect();
+        let nbt = NetworkNbt(Tag::Compound(compound));
+        let bytes = network_nbt_bytes(&nbt.0).unwrap();
+        assert_eq!(BufferedPacketReader::new(&bytes).read_network_nbt().unwrap(), Some(nbt));
+
+        assert_eq!(BufferedPacketReader::new(&[0x00]).read_nbt().unwrap(), None);
+        assert_eq!(PacketBytesBuilder::new().try_append_nbt(None).unwrap().build(), [0x00]);
+    }
+
+    #[test]
+    fn nbt_decodes_large_compounds() {
+        // Chunk and registry data carry compounds with many keys; decoding
+        // must not rescan the entries for every key read.
+        const ENTRIES: usize = 100_000;
+        let mut bytes = vec![0x0A];
+        for i in 0..ENTRIES {
+            let key = format!("k{i}");
+            bytes.push(0x01);
+            bytes.extend((key.len() as u16).to_be_bytes());
+            bytes.extend(key.as_bytes());
+            bytes.push(i as u8);
+        }
+        // A repeated key replaces the earlier value in place.
+        bytes.extend([0x01, 0x00, 0x02]);
+        bytes.extend(b"k0");
+        bytes.push(0xFF);
+        bytes.push(0x00);
+
+        let nbt = BufferedPacketReader::new(&bytes).read_network_nbt().unwrap().unwrap();
+        let compound = nbt.0.as_compound().unwrap();
+        assert_eq!(compound.len(), ENTRIES);
+        assert_eq!(compound.iter().next(), Some(("k0", &Tag::Byte(-1))));
+        assert_eq!(compound.get_i64("k99999"), Some(99_999u32 as u8 as i8 as i64));
+        assert_eq!(network_nbt_bytes(&nbt.0).unwrap().len(), bytes.len() - 6);
+    }
+
+    fn network_nbt_bytes(tag: &Tag) -> Result<Vec<u8>, McError> {
+        Ok(PacketBytesBuilder::new()
+            .try_append_network_nbt(Some(&NetworkNbt(tag.clone())))?
+            .build())
+    }
+
+    #[test]
+    fn nbt_encoding_rejects_unrepresentable_tags() {
+        // A list has a single element type, and strings a 16-bit length.
+        let mixed = Tag::List(vec![Tag::Int(1), Tag::String("two".into())]);
+        let long = Tag::String("a".repeat(65536));
+        let nested = Tag::Compound([("ok", Tag::Int(1)), ("mixed", mixed.clone())].into_iter().collect());
+        for tag in [&mixed, &long, &nested] {
+            assert!(matches!(network_nbt_bytes(tag), Err(McError::Malformed { .. })));
+        }
+        assert!(PacketBytesBuilder::new().try_append_nbt(Some(&Nbt::new("", long))).is_err());
+
+        // The error reaches callers which embed NBT, rather than a TAG_End in its place.
+        let item = ItemStack {
+            item_id: 1,
+            count: 1,
+            data: ItemData::Nbt(nested.as_compound().cloned()),
+        };
+        assert!(PacketBytesBuilder::new().try_append_slot(&item, SlotFormat::Legacy).is_err());
+        let mut metadata = EntityMetadata::new();
+        metadata.insert(0, MetadataValue::Nbt(Some(mixed)));
+        assert!(metadata.write_with(PacketBytesBuilder::new(), 764).is_err());
+    }
+
+    #[test]
+    fn nbt_rejects_malformed_input() {
+        // Unknown tag type.
+        let read = |bytes: &[u8]| BufferedPacketReader::new(bytes).read_network_nbt();
+        assert!(read(&[0x0D]).is_err());
+        // Byte array claiming far more bytes than present.
+        assert!(read(&[0x07, 0x7F, 0xFF, 0xFF, 0xFF, 0x00]).is_err());
+        // Compound without its TAG_End.
+        assert!(read(&[0x0A, 0x01, 0x00, 0x01, b'a', 0x05]).is_err());
+        // Lists nested beyond the depth limit.
+        let mut nested = vec![0x09];
+        for _ in 0..600 {
+            nested.extend([0x09, 0x00, 0x00, 0x00, 0x01]);
+        }
+        assert!(read(&nested).is_err());
+    }
+
+    #[test]
+    fn nbt_modified_utf8_strings() {
+        let text = "a\0b\u{E9}\u{1F600}";
+        let bytes = network_nbt_bytes(&Tag::String(text.into())).unwrap();
+        // NUL takes 2 bytes, e-acute 2, and the emoji two 3-byte surrogates.
+        assert_eq!(&bytes[1..3], [0x00, 1 + 2 + 1 + 2 + 6]);
+        assert_eq!(&bytes[4..6], [0xC0, 0x80]);
+        assert_eq!(
+            BufferedPacketReader::new(&bytes).read_network_nbt().unwrap(),
+            Some(NetworkNbt(Tag::String(text.into())))
+        );
+    }
+
+    #[test]
+    fn snbt_parse_and_print() {
+        let tag = snbt::parse(
+            r#"{ name: "Makoto", 'quoted key': 'it"s', level: 3b, hp: 20.5f, xp: 1.5, big: 9L,
+                flag: true, pos: [I; 1, 64, -2], raw: [B;], items: [{id: "minecraft:stone"}], word: hello,
+                sci: 1.0e3d }"#,
+        )
+        .unwrap();
+        let compound = tag.as_compound().unwrap();
+        assert_eq!(compound.get_str("name"), Some("Makoto"));
+        assert_eq!(compound.get_str("quoted key"), Some("it\"s"));
+        assert_eq!(compound.get("level"), Some(&Tag::Byte(3)));
+        assert_eq!(compound.get("hp"), Some(&Tag::Float(20.5)));
+        assert_eq!(compound.get("xp"), Some(&Tag::Double(1.5)));
+        assert_eq!(compound.get("big"), Some(&Tag::Long(9)));
+        assert_eq!(compound.get("flag"), Some(&Tag::Byte(1)));
+        assert_eq!(compound.get("pos"), Some(&Tag::IntArray(vec![1, 64, -2])));
+        assert_eq!(compound.get("raw"), Some(&Tag::ByteArray(vec![])));
+        assert_eq!(compound.get_list("items").unwrap().len(), 1);
+        assert_eq!(compound.get_str("word"), Some("hello"));
+        assert_eq!(compound.get("sci"), Some(&Tag::Double(1000.0)));
+
+        let printed = tag.to_string();
+        assert_eq!(printed.parse::<Tag>().unwrap(), tag);
+        assert_eq!(Tag::Compound(compound.clone()).to_string(), compound.to_string());
+        assert_eq!(snbt::parse("[L;1L,-2L]").unwrap().to_string(), "[L;1L,-2L]");
+
+        assert!(snbt::parse("{a:1,}").is_err());
+        assert!(snbt::parse("[1, 2b]").is_err());
+        assert!(snbt::parse("[I; 1b]").is_err());
+        assert!(snbt::parse("\"unterminated").is_err());
+        assert!(snbt::parse("{a:1} trailing").is_err());
+    }
+
+    #[test]
+    fn nbt_compressed_files() {
+        let nbt = Nbt::new(
+            "root",
+            Tag::Compound([("answer", Tag::Int(42))].into_iter().collect()),
+        );
+
+        for compression in [NbtCompression::None, NbtCompression::Gzip, NbtCompression::Zlib] {
+            let mut bytes = Vec::new();
+            nbt.to_writer(&mut bytes, compression).unwrap();
+            assert_eq!(NbtCompression::detect(&bytes), compression);
+            assert_eq!(Nbt::from_reader(bytes.as_slice()).unwrap(), nbt);
+        }
+    }
+
+    #[test]
+    fn nbt_through_packet_builder_and_reader() {
+        let nbt = Nbt::new("", Tag::Compound([("id", Tag::Short(1))].into_iter().collect()));
+        let bytes = PacketBytesBuilder::new()
+            .try_append_nbt(Some(&nbt))
+            .and_then(|builder| builder.try_append_nbt(None))
+            .and_then(|builder| builder.try_append_network_nbt(Some(&NetworkNbt(Tag::String("hi".into())))))
+            .unwrap()
+            .append_bool(true)
+            .build();
+
+        let mut reader = BufferedPacketReader::new(&bytes);
+        assert_eq!(reader.read_nbt().unwrap(), Some(nbt));
+        assert_eq!(reader.read_nbt().unwrap(), None);
+        assert_eq!(reader.read_network_nbt().unwrap(), Some(NetworkNbt(Tag::String("hi".into()))));
+        assert!(reader.read_bool().unwrap());
+        assert!(reader.finish().is_ok());
+    }
+
+    #[test]
+    fn text_component_from_json() {
+        let component = TextComponent::from_json_str(
+            r##"{"text":"","extra":[
+                {"text":"Hello ","color":"gold","bold":true},
+                {"translate":"chat.type.text","fallback":"<%s> %s","with":[{"text":"Makoto","color":"#00FF7F",
+                    "clickEvent":{"action":"suggest_command","value":"/msg Makoto "},
+                    "hoverEvent":{"action":"show_entity","contents":{"type":"minecraft:player",
+                        "id":[1,2,3,4],"name":"Makoto"}}},"hi"]},
+                {"keybind":"key.jump"},
+                {"score":{"name":"@p","objective":"kills","value":"7"}},
+                {"selector":"@a"},
+                {"nbt":"Items","block":"0 64 0"},
+                "!"]}"##,
+        )
+        .unwrap();
+
+        assert_eq!(component.extra[0].style.color, Some(TextColor::Named(NamedColor::Gold)));
+        assert_eq!(component.extra[0].style.bold, Some(true));
+        let TextContent::Translatable { with, .. } = &component.extra[1].content else {
+            panic!("expected a translatable component");
+        };
+        assert_eq!(with[0].style.color, Some(TextColor::Rgb(0x00FF7F)));
+        assert_eq!(
+            with[0].style.click_event,
+            Some(ClickEvent::SuggestCommand("/msg Makoto ".into()))
+        );
+        match &with[0].style.hover_event {
+            Some(HoverEvent::ShowEntity { id, .. }) => {
+                assert_eq!(id, "00000001-0000-0002-0000-000300000004")
+            }
+            other => panic!("unexpected hover event {:?}", other),
+        }
+        assert_eq!(component.to_plain_string(), "Hello <Makoto> hikey.jump7@a!");
+
+        let reparsed = TextComponent::from_json(&component.to_json()).unwrap();
+        assert_eq!(reparsed, component);
+
+        assert!(TextComponent::from_json_str(r#"{"color":"red"}"#).is_err());
+        assert!(TextComponent::from_json_str(r#"{"text":"x","color":"pink"}"#).is_err());
+    }
+
+    #[test]
+    fn text_component_from_nbt() {
+        let tag = snbt::parse(
+            r#"{text:"Disconnected: ",color:"red",extra:[{"":"plain"},{text:"!",bold:1b}]}"#,
+        )
+        .unwrap();
+        let component = TextComponent::from_nbt(&tag).unwrap();
+        assert_eq!(component.style.color, Some(TextColor::Named(NamedColor::Red)));
+        assert_eq!(component.extra[1].style.bold, Some(true));
+        assert_eq!(component.to_plain_string(), "Disconnected: plain!");
+
+        assert_eq!(TextComponent::from_nbt(&component.to_nbt()).unwrap(), component);
+        assert_eq!(
+            TextComponent::from_nbt(&Tag::String("shorthand".into())).unwrap(),
+            TextComponent::text("shorthand")
+        );
+    }
+
+    #[test]
+    fn text_component_from_legacy() {
+        let component = TextComponent::from_legacy("\u{A7}6A \u{A7}lMinecraft\u{A7}r Server");
+        assert_eq!(component.to_plain_string(), "A Minecraft Server");
+        assert_eq!(component.extra.len(), 3);
+        assert_eq!(component.extra[0].style.color, Some(TextColor::Named(NamedColor::Gold)));
+        assert_eq!(component.extra[1].style.color, Some(TextColor::Named(NamedColor::Gold)));
+        assert_eq!(component.extra[1].style.bold, Some(true));
+        assert!(component.extra[2].style.is_empty());
+
+        assert_eq!(TextComponent::from_legacy("plain"), TextComponent::text("plain"));
+    }
+
+    #[test]
+    fn text_component_translation_arguments() {
+        let component = TextComponent::translatable(
+            "%2$s gave %1$s 100%% of %s",
+            vec!["Alice".into(), "Bob".into()],
+        );
+        assert_eq!(component.to_plain_string(), "Bob gave Alice 100% of Alice");
+    }
+
+    #[test]
+    fn text_component_as_mctype() {
+        let component = TextComponent::from_json_str(r#"{"text":"a","italic":false}"#).unwrap();
+        // Whitespace in the received JSON must not desynchronize the reader.
+        let bytes = PacketBytesBuilder::new()
+            .append_string(r#"{ "text" : "a", "italic" : false }"#)
+            .append_bool(true)
+            .build();
+
+        let mut reader = BufferedPacketReader::new(&bytes);
+        assert_eq!(reader.read::<TextComponent>().unwrap(), component);
+        assert!(reader.read_bool().unwrap());
+        assert_eq!(TextComponent::from_bytes(&component.to_bytes()).unwrap(), component);
+    }
+
+    #[test]
+    fn item_stack_legacy_slot() {
+        let nbt: Tag = r#"{Damage:12,display:{Name:'{"text":"Blade"}',Lore:['"First"','{"text":"Second"}']},Enchantments:[{id:"minecraft:sharpness",lvl:5s}]}"#
+            .parse()
+            .unwrap();
+        let item = ItemStack {
+            item_id: 802,
+            count: 1,
+            data: ItemData::Nbt(nbt.as_compound().cloned()),
+        };
+
+        let bytes = PacketBytesBuilder::new()
+            .try_append_slot(&item, SlotFormat::Legacy)
+            .unwrap()
+            .try_append_slot(&ItemStack::empty(), SlotFormat::Legacy)
+            .unwrap()
+            .build();
+        let mut reader = BufferedPacketReader::new(&bytes);
+        let read = reader.read_slot(SlotFormat::Legacy).unwrap();
+        assert!(reader.read_slot(SlotFormat::Legacy).unwrap().is_empty());
+        reader.finish().unwrap();
+
+        assert_eq!(read, item);
+        assert_eq!(read.custom_name().unwrap().to_plain_string(), "Blade");
+        let lore: Vec<_> = read.lore().iter().map(TextComponent::to_plain_string).collect();
+        assert_eq!(lore, ["First", "Second"]);
+        assert_eq!(read.damage(), Some(12));
+        let enchantments = read.enchantments();
+        assert_eq!(enchantments[0].id, EnchantmentId::Named("minecraft:sharpness".to_owned()));
+        assert_eq!(enchantments[0].level, 5);
+
+        // From 1.20.2, the NBT root has no name.
+        assert_eq!(SlotFormat::for_protocol(764), Some(SlotFormat::NamelessNbt));
+        let bytes = PacketBytesBuilder::new()
+            .try_append_slot(&item, SlotFormat::NamelessNbt)
+            .unwrap()
+            .build();
+        assert_eq!(bytes[..4], [0x01, 0xA2, 0x06, 0x01]);
+        assert_eq!(bytes[4..6], [0x0A, 0x03]);
+        let mut reader = BufferedPacketReader::new(&bytes);
+        assert_eq!(reader.read_slot(SlotFormat::NamelessNbt).unwrap(), item);
+        reader.finish().unwrap();
+    }
+
+    #[test]
+    fn item_stack_structured_slot() {
+        let item = ItemStack {
+            item_id: 1,
+            count: 3,
+            data: ItemData::Components(DataComponents {
+                added: vec![
+                    DataComponent::CustomName(TextComponent::text("Named")),
+                    DataComponent::Lore(vec![TextComponent::text("Line")]),
+                    DataComponent::Damage(4),
+                    DataComponent::Enchantments {
+                        enchantments: vec![(9, 2)],
+                        show_in_tooltip: true,
+                    },
+                    DataComponent::FireResistant,
+                ],
+                removed: vec![8],
+            }),
+        };
+
+        let bytes = PacketBytesBuilder::new()
+            .try_append_slot(&item, SlotFormat::Structured)
+            .unwrap()
+            .build();
+        assert_eq!(bytes[..5], [0x03, 0x01, 0x05, 0x01, 0x05]);
+        let mut reader = BufferedPacketReader::new(&bytes);
+        let read = reader.read_slot(SlotFormat::Structured).unwrap();
+        reader.finish().unwrap();
+
+        assert_eq!(read, item);
+        assert_eq!(read.custom_name().unwrap().to_plain_string(), "Named");
+        assert_eq!(read.lore().len(), 1);
+        assert_eq!(read.damage(), Some(4));
+        assert_eq!(read.enchantments()[0].id, EnchantmentId::Registry(9));
+
+        // Empty slots are a lone zero count.
+        let empty = PacketBytesBuilder::new()
+            .try_append_slot(&ItemStack::empty(), SlotFormat::Structured)
+            .unwrap()
+            .build();
+        assert_eq!(empty, [0x00]);
+
+        // Components are not length-prefixed, so unknown types cannot be skipped and
+        // fail the whole slot, even after a known component.
+        assert_eq!(SlotFormat::for_protocol(767), Some(SlotFormat::Structured));
+        assert_eq!(SlotFormat::for_protocol(768), None);
+        assert_eq!(SlotFormat::for_protocol(403), None);
+        for unknown in [&[0x01, 0x01, 0x01, 0x00, 0x7F][..], &[0x01, 0x01, 0x02, 0x00, 0x03, 0x04, 0x7F]] {
+            assert!(matches!(
+                BufferedPacketReader::new(unknown).read_slot(SlotFormat::Structured),
+                Err(McError::Malformed { error: DataError::UnsupportedComponent { id: 127 }, .. })
+            ));
+        }
+
+        // Exactly these types of the 1.20.5 registry are modelled; the rest, e.g. 20
+        // (`food`), 12 (`attribute_modifiers`) and 24 (`dyed_color`), are unsupported.
+        let modelled = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 13, 14, 15, 16, 17, 18, 19, 21, 23];
+        for id in 0..=55 {
+            // Enough zeroes to complete any modelled component with empty data.
+            let mut reader = BufferedPacketReader::new(&[0x00; 8]);
+            match DataComponent::read_with_id(&mut reader, id) {
+                Ok(component) => {
+                    assert!(modelled.contains(&id), "{} is modelled", id);
+                    assert_eq!(component.id(), id);
+                }
+                Err(McError::Malformed { error: DataError::UnsupportedComponent { id: unsupported }, .. }) => {
+                    assert!(!modelled.contains(&id), "{} is unsupported", id);
+                    assert_eq!(unsupported, id);
+                }
+                Err(e) => assert!(modelled.contains(&id), "{} failed with {:?}", id, e),
+            }
+        }
+    }
+
+    #[test]
+    fn prefixed_array_and_option() {
+        let array = PrefixedArray(vec![MCString::try_from("a").unwrap(), MCString::try_from("bc").unwrap()]);
+        let bytes = array.to_bytes();
+        assert_eq!(bytes, [0x02, 0x01, b'a', 0x02, b'b', b'c']);
+        assert_eq!(array.size(), bytes.len() as i32);
+
+        let mut reader = BufferedPacketReader::new(&bytes);
+        let read: PrefixedArray<MCString> = reader.read().unwrap();
+        reader.finish().unwrap();
+        let strings: Vec<String> = read.0.into_iter().map(String::from).collect();
+        assert_eq!(strings, ["a", "bc"]);
+
+        // A forged count larger than the data fails instead of allocating.
+        assert!(PrefixedArray::<i64>::from_bytes(&[0xFF, 0xFF, 0xFF, 0xFF, 0x07]).is_err());
+
+        let some = PrefixedOption(Some(0x1234_i16));
+        assert_eq!(some.to_bytes(), [0x01, 0x12, 0x34]);
+        assert_eq!(PrefixedOption::<i16>::from_bytes(&[0x01, 0x12, 0x34]).unwrap(), some);
+        assert_eq!(PrefixedOption::<i16>(None).to_bytes(), [0x00]);
+        assert_eq!(PrefixedOption::<i16>::from_bytes(&[0x00]).unwrap().0, None);
+        assert!(PrefixedOption::<i16>::from_bytes(&[0x02]).is_err());
+    }
+
+    #[test]
+    fn login_start_optional_uuid() {
+        let uuid = uuid::Uuid::from_u128(0x0102_0304_0506_0708_090A_0B0C_0D0E_0F10);
+        let with_uuid = LoginStart {
+            username: "Makoto".to_owned(),
+            uuid: Some(uuid),
+        }
+        .to_bytes()
+        .unwrap();
+        assert_eq!(with_uuid[..8], [0x06, b'M', b'a', b'k', b'o', b't', b'o', 0x01]);
+        assert_eq!(with_uuid[8..], *uuid.as_bytes());
+
+        let without_uuid = LoginStart {
+            username: "Makoto".to_owned(),
+            uuid: None,
+        }
+        .to_bytes()
+        .unwrap();
+        assert_eq!(without_uuid.len(), 8);
+        assert_eq!(without_uuid[7], 0x00);
+    }
+
+    #[test]
+    fn bit_sets() {
+        let mut bits = BitSet::new();
+        bits.set(0, true);
+        bits.set(65, true);
+        bits.set(200, false);
+        assert!(bits.get(0) && bits.get(65));
+        assert!(!bits.get(1) && !bits.get(200));
+        assert_eq!(bits.words(), [1, 2]);
+
+        let bytes = bits.to_bytes();
+        assert_eq!(bytes.len(), 1 + 16);
+        assert_eq!(bytes[..9], [0x02, 0, 0, 0, 0, 0, 0, 0, 0x01]);
+        assert_eq!(BitSet::from_bytes(&bytes).unwrap(), bits);
+        bits.set(65, false);
+        assert!(!bits.get(65));
+
+        let mut fixed = FixedBitSet::<10>::new();
+        fixed.set(1, true);
+        fixed.set(9, true);
+        assert_eq!(fixed.to_bytes(), [0b0000_0010, 0b0000_0010]);
+        assert_eq!(fixed.size(), 2);
+
+        let mut reader = BufferedPacketReader::new(&[0x81, 0x00, 0xFF]);
+        let read: FixedBitSet<10> = reader.read().unwrap();
+        assert!(read.get(0) && read.get(7) && !read.get(8));
+        assert_eq!(reader.remaining(), 1);
+    }
+
+    #[test]
+    fn identifier_parsing_and_encoding() {
+        let stone: Identifier = "stone".parse().unwrap();
+        assert_eq!(stone.namespace(), "minecraft");
+        assert_eq!(stone.path(), "stone");
+        assert_eq!(stone, Identifier::parse(":stone").unwrap());
+        assert_eq!(stone, Identifier::minecraft("stone").unwrap());
+
+        let channel = Identifier::parse("my_mod:net/sync.v2").unwrap();
+        assert_eq!(channel.to_string(), "my_mod:net/sync.v2");
+        assert!(Identifier::parse("Minecraft:stone").is_err());
+        assert!(Identifier::parse("my/mod:stone").is_err());
+        assert!(Identifier::parse("minecraft:a:b").is_err());
+        assert!(Identifier::new("minecraft", "x".repeat(32767)).is_err());
+
+        let bytes = stone.to_bytes();
+        assert_eq!(bytes[0], 15);
+        assert_eq!(&bytes[1..], b"minecraft:stone");
+        assert_eq!(stone.size(), bytes.len() as i32);
+        let mut reader = BufferedPacketReader::new(&bytes);
+        assert_eq!(reader.read::<Identifier>().unwrap(), stone);
+        assert!(reader.is_empty());
+
+        // Identifiers order by namespace first, so they can key sorted registries.
+        let mut ids: Vec<Identifier> = ["b:a", "a:z", "a:b"].iter().map(|s| s.parse().unwrap()).collect();
+        ids.sort();
+        let sorted: Vec<String> = ids.iter().map(Identifier::to_string).collect();
+        assert_eq!(sorted, ["a:b", "a:z", "b:a"]);
+    }
+
+    #[derive(Packet, Debug, PartialEq)]
+    #[packet(id = 0x2A, state = Play, direction = Clientbound)]
+    struct DerivedPacket {
+        #[packet(varint)]
+        entity_id: i32,
+        flags: u8,
+        #[packet(max_len = 16)]
+        name: String,
+        #[packet(length_prefixed, varint)]
+        passengers: Vec<i32>,
+        #[packet(bool_prefixed)]
+        target: Option<Position>,
+        #[packet(condition = flags & 0x01 != 0)]
+        velocity: Option<i16>,
+        #[packet(remaining)]
+        extra: Vec<u8>,
+    }
+
+    #[test]
+    fn derived_packet_round_trip() {
+        assert_eq!(DerivedPacket::ID, 0x2A);
+        assert_eq!(DerivedPacket::STATE, ConnectionState::Play);
+        assert_eq!(DerivedPacket::DIRECTION, PacketDirection::Clientbound);
+
+        let packet = DerivedPacket {
+            entity_id: 300,
+            flags: 0x01,
+            name: "Makoto".to_owned(),
+            passengers: vec![1, 128],
+            target: None,
+            velocity: Some(-2),
+            extra: vec![0xCA, 0xFE],
+        };
+        let bytes = OutboundPacket::to_bytes(&packet).unwrap();
+        assert_eq!(
+            bytes,
+            [
+                0xAC, 0x02, 0x01, 0x06, b'M', b'a', b'k', b'o', b't', b'o', 0x02, 0x01, 0x80, 0x01,
+                0x00, 0xFF, 0xFE, 0xCA, 0xFE,
+            ]
+        );
+
+        let mut frame = vec![0x2A];
+        frame.extend(&bytes);
+        let raw = ClientboundRawPacket::from_frame(frame).unwrap();
+        assert_eq!(DerivedPacket::from_data(&raw).unwrap(), packet);
+
+        // The condition is evaluated against the fields decoded before it.
+        let mut frame = vec![0x2A, 0x00, 0x00, 0x00, 0x00, 0x00];
+        let raw = ClientboundRawPacket::from_frame(frame.clone()).unwrap();
+        assert_eq!(DerivedPacket::from_data(&raw).unwrap().velocity, None);
+
+        // Names longer than the declared maximum are rejected.
+        frame.splice(3..4, [17].into_iter().chain(std::iter::repeat_n(b'a', 17)));
+        let raw = ClientboundRawPacket::from_frame(frame).unwrap();
+        assert!(DerivedPacket::from_data(&raw).is_err());
+    }
+
+    #[derive(Packet, Debug, PartialEq)]
+    #[packet(id = 0x2B, state = Play, direction = Clientbound)]
+    struct DerivedNbtPacket {
+        #[packet(nbt)]
+        data: Option<Nbt>,
+        #[packet(network_nbt)]
+        network: Option<NetworkNbt>,
+        #[packet(length_prefixed, slot = SlotFormat::Structured)]
+        items: Vec<ItemStack>,
+    }
+
+    #[test]
+    fn derived_packet_nbt_and_slot_fields() {
+        let compound: Compound = [("id", Tag::Int(7))].into_iter().collect();
+        let packet = DerivedNbtPacket {
+            data: Some(Nbt::new("root", Tag::Compound(compound))),
+            network: None,
+            items: vec![
+                ItemStack { item_id: 1, count: 2, data: ItemData::Components(DataComponents::default()) },
+                ItemStack::empty(),
+            ],
+        };
+        let bytes = OutboundPacket::to_bytes(&packet).unwrap();
+        assert_eq!(bytes[..3], [0x0A, 0x00, 0x04]);
+        assert_eq!(bytes[bytes.len() - 8..], [0x00, 0x00, 0x02, 0x02, 0x01, 0x00, 0x00, 0x00]);
+
+        let mut frame = vec![0x2B];
+        frame.extend(&bytes);
+        let raw = ClientboundRawPacket::from_frame(frame).unwrap();
+        assert_eq!(DerivedNbtPacket::from_data(&raw).unwrap(), packet);
+
+        // Tags which NBT cannot express fail the packet, naming the field.
+        let mixed = Tag::List(vec![Tag::Int(1), Tag::String("two".into())]);
+        let packet = DerivedNbtPacket { network: Some(NetworkNbt(mixed)), ..packet };
+        match OutboundPacket::to_bytes(&packet).unwrap_err() {
+            McError::Malformed { field, id, .. } => {
+                assert_eq!(field, Some("network"));
+                assert_eq!(id, Some(0x2B));
+            }
+            e => panic!("unexpected error {:?}", e),
+        }
+
+        // So does a truncated compound when decoding.
+        let raw = ClientboundRawPacket::from_frame(vec![0x2B, 0x0A, 0x00, 0x00, 0x03]).unwrap();
+        match DerivedNbtPacket::from_data(&raw).unwrap_err() {
+            McError::Malformed { field, .. } => assert_eq!(field, Some("data")),
+            e => panic!("unexpected error {:?}", e),
+        }
+    }
+
+    #[test]
+    fn encoding_into_caller_buffer() {
+        let mut buf = vec![0xAA];
+        VarInt::from(300).write_to(&mut buf);
+        MCString::try_from("hi").unwrap().write_to(&mut buf);
+        true.write_to(&mut buf);
+        assert_eq!(buf, [0xAA, 0xAC, 0x02, 0x02, b'h', b'i', 0x01]);
+
+        // Sizes are known without encoding, or without an instance for fixed-width types.
+        for value in [0, 127, 128, 16383, 16384, 2097151, 2097152, i32::MAX, -1, i32::MIN] {
+            assert_eq!(VarInt::encoded_len(value), VarInt::from(value).len());
+        }
+        for value in [0, 127, 128, i64::MAX, -1, i64::MIN] {
+            assert_eq!(VarLong::encoded_len(value), VarLong::from(value).len());
+        }
+        assert_eq!(<i64 as MCType>::FIXED_SIZE, Some(8));
+        assert_eq!(<Position as MCType>::FIXED_SIZE, Some(8));
+        assert_eq!(<FixedBitSet<20> as MCType>::FIXED_SIZE, Some(3));
+        assert_eq!(<MCString as MCType>::FIXED_SIZE, None);
+        assert_eq!(PrefixedArray(vec![1_i32; 200]).size(), 2 + 800);
+
+        // Packets are appended after any bytes already in the buffer.
+        let mut buf = vec![0xFF];
+        write_packet(&StatusRequest, &mut buf).unwrap();
+        write_packet(&StatusRequest, &mut buf).unwrap();
+        assert_eq!(buf, [0xFF, 0x01, 0x00, 0x01, 0x00]);
+
+        let handshake = Handshake {
+            protocol_version: PROTOCOL_VERSION,
+            server_addr: "localhost".to_owned(),
+            port: 25565,
+            next_state: NextState::STATUS,
+        };
+        let mut buf = Vec::new();
+        write_packet(&handshake, &mut buf).unwrap();
+        assert_eq!(buf, *OutboundPacketBuffer::try_from(&handshake as &dyn OutboundPacket).unwrap().data());
+
+        let builder = PacketBytesBuilder::with_buffer(vec![0x01]).append_string(String::from("a"));
+        assert_eq!(builder.append_string("b").build(), [0x01, 0x01, b'a', 0x01, b'b']);
+    }
+
+    #[test]
+    fn borrowed_string_decoding() {
+        let bytes = [0x05, b'h', b'e', b'l', b'l', b'o', 0x02, b'h', b'i'];
+        let mut reader = BufferedPacketReader::new(&bytes);
+        let hello: &str = reader.read_str(16).unwrap();
+        let hi = MCString::read(&mut reader, 16).unwrap();
+        reader.finish().unwrap();
+
+        // Both point into `bytes` rather than into a copy.
+        assert_eq!(hello, "hello");
+        assert_eq!(hello.as_ptr(), bytes[1..].as_ptr());
+        assert_eq!(hi.as_str(), "hi");
+        assert_eq!(hi.bytes().as_ptr(), bytes[7..].as_ptr());
+
+        assert!(BufferedPacketReader::new(&bytes).read_str(4).is_err());
+        assert!(BufferedPacketReader::new(&[0x02, 0xC3, 0x28]).read_str(16).is_err());
+    }
+
+    #[test]
+    fn string_length_limits() {
+        // Limits count UTF-16 code units: 'é' is one unit, '😀' is two.
+        assert!(MCString::new("é".repeat(16), MAX_USERNAME_LENGTH).is_ok());
+        assert_eq!(
+            MCString::new("😀".repeat(9), MAX_USERNAME_LENGTH),
+            Err(StringError::TooLong { len: 18, max_len: 16 })
+        );
+        assert!(PacketBytesBuilder::new().try_append_string("Makoto", 16).is_ok());
+        assert!(PacketBytesBuilder::new().try_append_string("a".repeat(17), 16).is_err());
+
+        // Decoding reads exactly the declared bytes and leaves the rest.
+        let mut bytes = MCString::new("é".repeat(16), 16).unwrap().to_bytes();
+        assert_eq!(bytes[0], 32);
+        bytes.push(0x01);
+        let mut reader = BufferedPacketReader::new(&bytes);
+        assert_eq!(reader.read_str(16).unwrap(), "é".repeat(16));
+        assert!(reader.read_bool().unwrap());
+
+        // Over-long strings, invalid UTF-8 and impossible byte lengths are errors
+        // carrying a `StringError`, not panics.
+        let mut too_long = vec![17];
+        too_long.extend([b'a'; 17]);
+        let error = BufferedPacketReader::new(&too_long).read_string(16).unwrap_err();
+        assert!(matches!(
+            error,
+            McError::Malformed { error: DataError::String(StringError::TooLong { len: 17, max_len: 16 }), .. }
+        ));
+        let error = BufferedPacketReader::new(&[0x02, 0xC3, 0x28]).read_string(16).unwrap_err();
+        assert!(matches!(
+            error,
+            McError::Malformed { error: DataError::String(StringError::InvalidUtf8(_)), .. }
+        ));
+        let error = BufferedPacketReader::new(&[0x31]).read_string(16).unwrap_err();
+        assert!(matches!(
+            error,
+            McError::Malformed { error: DataError::String(StringError::TooManyBytes { bytes: 49, max_len: 16 }), .. }
+        ));
+
+        // Login Start enforces the username limit when decoded.
+        let mut frame = vec![0x00, 17];
+        frame.extend([b'a'; 17]);
+        frame.push(0x00);
+        let raw = ClientboundRawPacket::from_frame(frame).unwrap();
+        assert!(LoginStart::from_data(&raw).is_err());
+
+        // ...and when encoded, in every protocol version.
+        let login_start = LoginStart { username: "a".repeat(17), uuid: None };
+        for error in [login_start.to_bytes().unwrap_err(), login_start.to_bytes_with(764).unwrap_err()] {
+            assert!(matches!(
+                error,
+                McError::Malformed {
+                    error: DataError::String(StringError::TooLong { len: 17, max_len: 16 }),
+                    field: Some("username"),
+                    ..
+                }
+            ));
+        }
+        let mut buf = vec![0xFF];
+        assert!(write_packet(&login_start, &mut buf).is_err());
+        assert_eq!(buf, [0xFF]);
+    }
+
+    #[test]
+    fn decode_errors_carry_field_and_packet() {
+        let raw = ClientboundRawPacket::from_frame(vec![0x00, 0x06, b'M', b'a']).unwrap();
+        match LoginStart::from_data(&raw).unwrap_err() {
+            McError::Malformed { error: DataError::Truncated { .. }, field, state, id } => {
+                assert_eq!(field, Some("username"));
+                assert_eq!(state, Some(ConnectionState::Login));
+                assert_eq!(id, Some(0x00));
+            }
+            e => panic!("unexpected error {:?}", e),
+        }
+
+        // Trailing bytes belong to the packet, not to a field.
+        let raw = ClientboundRawPacket::from_frame(vec![0x00, 0x01, b'M', 0x00, 0xFF]).unwrap();
+        let err = LoginStart::from_data(&raw).unwrap_err();
+        assert!(matches!(
+            err,
+            McError::Malformed { error: DataError::TrailingBytes { count: 1, .. }, field: None, .. }
+        ));
+        assert_eq!(
+            err.to_string(),
+            "malformed data of packet 0x00 (Login): 1 trailing bytes left unread at position 3"
+        );
+    }
+
+    #[test]
+    fn login_reports_server_disconnect() {
+        use std::{io::Write, net::TcpListener, thread};
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let disconnect = LoginDisconnect { reason: TextComponent::text("Server is full") };
+            let mut bytes = Vec::new();
+            write_packet(&disconnect, &mut bytes).unwrap();
+            socket.write_all(&bytes).unwrap();
+        });
+
+        let mut connection = OfflineConnection::connect("127.0.0.1", port).unwrap();
+        match connection.login("Makoto").unwrap_err() {
+            McError::Disconnected(reason) => assert_eq!(reason.to_plain_string(), "Server is full"),
+            e => panic!("unexpected error {:?}", e),
+        }
+        server.join().unwrap();
+
+        // The stream is closed now, which is an I/O error rather than a panic.
+        assert!(matches!(connection.login("Makoto"), Err(McError::Io(_))));
+        assert!(matches!(
+            connection.login("a".repeat(17)),
+            Err(McError::Malformed { error: DataError::String(_), .. })
+        ));
+    }
+
+    #[test]
+    fn entity_metadata_round_trip() {
+        let mut metadata = EntityMetadata::new();
+        metadata.insert(0, MetadataValue::Byte(0x02));
+        metadata.insert(1, MetadataValue::VarInt(300));
+        metadata.insert(2, MetadataValue::OptChat(Some(TextComponent::text("Makoto"))));
+        metadata.insert(3, MetadataValue::Boolean(true));
+        metadata.insert(6, MetadataValue::Pose(Pose::Sneaking));
+        metadata.insert(8, MetadataValue::Slot(ItemStack::new(1, 3)));
+        metadata.insert(9, MetadataValue::OptUuid(None));
+        metadata.insert(10, MetadataValue::OptBlockState(Some(7)));
+        metadata.insert(11, MetadataValue::OptVarInt(Some(0)));
+        metadata.insert(12, MetadataValue::Rotations(Rotations { x: 1.0, y: 2.0, z: 3.0 }));
+        metadata.insert(13, MetadataValue::Direction(Direction::West));
+        metadata.insert(
+            14,
+            MetadataValue::Particle(Particle {
+                id: 14,
+                data: ParticleData::Dust { color: [1.0, 0.5, 0.0], scale: 2.0 },
+            }),
+        );
+        metadata.insert(
+            15,
+            MetadataValue::OptGlobalPos(Some(GlobalPos {
+                dimension: Identifier::minecraft("overworld").unwrap(),
+                position: Position::new(1, 64, -2),
+            })),
+        );
+        metadata.insert(16, MetadataValue::VillagerData(VillagerData { villager_type: 2, profession: 5, level: 1 }));
+
+        let bytes = metadata.write_with(PacketBytesBuilder::new(), 761).unwrap().build();
+        assert_eq!(&bytes[..3], [0x00, 0x00, 0x02]);
+        // OptVarInt sends absent as 0, so Some(0) is sent as 1; its serializer is 18 in 1.19.3.
+        let entry = bytes.windows(3).position(|w| w == [11, 18, 1]);
+        assert!(entry.is_some());
+        assert_eq!(bytes.last(), Some(&METADATA_END));
+        let mut reader = BufferedPacketReader::new(&bytes);
+        assert_eq!(EntityMetadata::read_with(&mut reader, 761).unwrap(), metadata);
+        assert_eq!(reader.remaining(), 0);
+
+        // The same values are renumbered for 1.20.4, where `BlockState` shifted every
+        // later serializer, and the pose table gained `Sitting`.
+        let mut later = EntityMetadata::new();
+        later.insert(0, MetadataValue::Pose(Pose::Digging));
+        later.insert(1, MetadataValue::SnifferState(3));
+        let bytes = later.write_with(PacketBytesBuilder::new(), 765).unwrap().build();
+        assert_eq!(bytes, [0, 20, 14, 1, 25, 3, METADATA_END]);
+        let mut reader = BufferedPacketReader::new(&bytes);
+        assert_eq!(reader.read_entity_metadata(765).unwrap(), later);
+
+        // 1.19.3 cannot express a sniffer, so encoding for it fails rather than drop the entry.
+        assert!(later.write_with(PacketBytesBuilder::new(), 761).is_err());
+    }
+
+    #[test]
+    fn entity_metadata_rejects_malformed_input() {
+        // Unknown serializer, unknown pose, missing terminator.
+        let read = |bytes: &[u8]| BufferedPacketReader::new(bytes).read_entity_metadata(761);
+        assert!(read(&[0x00, 24, 0x00, METADATA_END]).is_err());
+        assert!(read(&[0x00, 19, 14, METADATA_END]).is_err());
+        assert!(read(&[0x00, 0, 0x01]).is_err());
+        // No serializer table for the protocol.
+        let mut reader = BufferedPacketReader::new(&[METADATA_END]);
+        assert!(reader.read_entity_metadata(758).is_err());
+        assert_eq!(MetadataType::from_id(767, 30), Some(MetadataType::Quaternion));
+        assert_eq!(MetadataType::Particles.id(761), None);
+        // VarLong was only added in 1.19.3, shifting every later serializer.
+        assert_eq!(MetadataType::from_id(760, 2), Some(MetadataType::Float));
+        assert_eq!(MetadataType::from_id(761, 2), Some(MetadataType::VarLong));
+        assert_eq!(MetadataType::VarLong.id(760), None);
+        assert_eq!(MetadataType::PaintingVariant.id(760), Some(22));
+    }
+
+    #[test]
+    fn particles_follow_each_registry() {
+        let round_trip = |particle: &Particle, protocol_version: i32| {
+            let bytes = particle.write_with(PacketBytesBuilder::new(), protocol_version).unwrap().build();
+            let mut reader = BufferedPacketReader::new(&bytes);
+            assert_eq!(&Particle::read_with(&mut reader, protocol_version).unwrap(), particle);
+            assert_eq!(reader.remaining(), 0);
+            bytes
+        };
+
+        // `shriek` moved from 92 to 95 in 1.19.4, then to 93 in 1.20 and 96 in 1.20.3.
+        for (protocol_version, id) in [(759, 92), (761, 92), (762, 95), (764, 93), (765, 96), (767, 99)] {
+            let shriek = Particle { id, data: ParticleData::Shriek { delay: 5 } };
+            assert_eq!(round_trip(&shriek, protocol_version).len(), 2);
+            let wrong = Particle { id: id + 1, data: ParticleData::Shriek { delay: 5 } };
+            assert!(wrong.write_with(PacketBytesBuilder::new(), protocol_version).is_err());
+        }
+        let mut reader = BufferedPacketReader::new(&[0x00]);
+        assert!(Particle::read_with(&mut reader, 758).is_err());
+        let none = Particle { id: 0, data: ParticleData::None };
+        assert!(none.write_with(PacketBytesBuilder::new(), 768).is_err());
+
+        // 1.20.5 sends the scale of a color transition last, and the color of `entity_effect`.
+        let transition = ParticleData::DustColorTransition { from: [1.0; 3], scale: 0.5, to: [0.0; 3] };
+        let bytes = round_trip(&Particle { id: 15, data: transition.clone() }, 765);
+        assert_eq!(bytes[13..17], 0.5f32.to_be_bytes());
+        let bytes = round_trip(&Particle { id: 14, data: transition }, 766);
+        assert_eq!(bytes[25..29], 0.5f32.to_be_bytes());
+        let effect = Particle { id: 20, data: ParticleData::EntityEffect { color: -1 } };
+        assert_eq!(round_trip(&effect, 766), [20, 0xFF, 0xFF, 0xFF, 0xFF]);
+
+        // 1.20.2 sends the type of a vibration source as a registry ID.
+        let vibration = ParticleData::Vibration {
+            source: VibrationSource::Entity { entity_id: 7, eye_height: 1.5 },
+            ticks: 20,
+        };
+        let bytes = round_trip(&Particle { id: 41, data: vibration.clone() }, 763);
+        assert_eq!(&bytes[1..18], b"\x10minecraft:entity");
+        let bytes = round_trip(&Particle { id: 41, data: vibration }, 764);
+        assert_eq!(bytes[..3], [41, 1, 7]);
+        let block = ParticleData::Vibration { source: VibrationSource::Block(Position::new(1, 2, 3)), ticks: 1 };
+        assert_eq!(round_trip(&Particle { id: 45, data: block }, 767)[..2], [45, 0]);
+    }
+
+    #[test]
+    fn compressed_frames() {
+        // Below the threshold, the body is sent as is after a data length of 0.
+        let mut frame = Vec::new();
+        compression::compress_frame(&[0x00, 0x01, 0x02], 256, &mut frame).unwrap();
+        assert_eq!(frame, [0x00, 0x00, 0x01, 0x02]);
+        assert_eq!(compression::decompress_frame(&frame, 256).unwrap(), [0x00, 0x01, 0x02]);
+
+        let body = vec![0x2A; 1000];
+        let mut frame = Vec::new();
+        compression::compress_frame(&body, 256, &mut frame).unwrap();
+        assert_eq!(&frame[..2], VarInt::from(1000).bytes());
+        assert!(frame.len() < 100);
+        assert_eq!(compression::decompress_frame(&frame, 256).unwrap(), body);
+
+        // A compressed body below the threshold, a wrong declared length, an oversized
+        // declared length and a corrupt body are all rejected.
+        assert!(matches!(compression::decompress_frame(&frame, 2000), Err(McError::Compression(_))));
+        let mut wrong_len = VarInt::from(999).to_bytes();
+        wrong_len.extend_from_slice(&frame[2..]);
+        assert!(matches!(compression::decompress_frame(&wrong_len, 256), Err(McError::Compression(_))));
+        let mut oversized = VarInt::from(compression::MAX_DECOMPRESSED_SIZE as i32 + 1).to_bytes();
+        oversized.extend_from_slice(&frame[2..]);
+        assert!(matches!(compression::decompress_frame(&oversized, 256), Err(McError::Compression(_))));
+        let mut corrupt = frame.clone();
+        corrupt[4] ^= 0xFF;
+        assert!(compression::decompress_frame(&corrupt, 256).is_err());
+    }
+
+    #[test]
+    fn login_enables_compression() {
+        use std::{io::{Read, Write}, net::TcpListener, thread};
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let mut frames = FrameDecoder::new();
+            // Handshake and Login Start are sent before compression is enabled.
+            frames.read_frame(&mut socket).unwrap();
+            let login_start = frames.read_frame(&mut socket).unwrap();
+            assert_eq!(login_start[0], 0x00);
+
+            let mut bytes = Vec::new();
+            write_packet(&SetCompression { threshold: 4 }, &mut bytes).unwrap();
+            let login_success = LoginSuccess {
+                uuid: offline_uuid("Makoto"),
+                username: "Makoto".to_string(),
+                properties: Vec::new(),
+            };
+            let mut body = vec![0x02];
+            OutboundPacket::write_to(&login_success, &mut body).unwrap();
+            let mut frame = Vec::new();
+            compression::compress_frame(&body, 4, &mut frame).unwrap();
+            bytes.extend(VarInt::from(frame.len() as i32).bytes());
+            bytes.extend(frame);
+            socket.write_all(&bytes).unwrap();
+
+            // Anything the client sends from now on is in the compressed format.
+            let frame = frames.read_frame(&mut socket).unwrap();
+            assert_eq!(compression::decompress_frame(&frame, 4).unwrap(), [0x00]);
+            assert_eq!(frame, [0x00, 0x00]);
+            let mut rest = Vec::new();
+            socket.read_to_end(&mut rest).unwrap();
+        });
+
+        let mut connection = OfflineConnection::connect("127.0.0.1", port).unwrap();
+        connection.login("Makoto").unwrap();
+        assert_eq!(connection.sock().compression_threshold(), Some(4));
+        connection.sock().send(&StatusRequest).unwrap();
+        drop(connection);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn server_hash_matches_known_vectors() {
+        // The vectors hash the name alone, as if it were the server ID.
+        assert_eq!(encryption::server_hash("Notch", b"", b""), "4ed1f46bbe04bc756bcb17c0c7ce3e4632f06a48");
+        assert_eq!(encryption::server_hash("jeb_", b"", b""), "-7c9d5b0044c130109a5d7b5fb5c317c02b4e28c1");
+        assert_eq!(encryption::server_hash("simon", b"", b""), "88e16a1019277b15d58faf0541e11910eb756f6");
+        // Splitting the input between the parts does not change the digest.
+        assert_eq!(encryption::server_hash("No", b"tc", b"h"), encryption::server_hash("Notch", b"", b""));
+    }
+
+    #[test]
+    fn cfb8_stream_round_trip() {
+        use std::io::{Read, Write};
+
+        let secret = encryption::generate_shared_secret();
+        let plain: Vec<u8> = (0..=255).collect();
+
+        let mut writer = EncryptingWriter::new(Vec::new());
+        writer.write_all(&plain[..100]).unwrap();
+        writer.enable(&secret);
+        writer.write_all(&plain[100..150]).unwrap();
+        writer.write_all(&plain[150..]).unwrap();
+        let sent = writer.get_ref().clone();
+        assert_eq!(sent[..100], plain[..100]);
+        assert_ne!(sent[100..], plain[100..]);
+
+        // Matches `openssl enc -aes-128-cfb8` with the same key and IV.
+        let key: [u8; 16] = std::array::from_fn(|i| i as u8);
+        let mut known = EncryptingWriter::new(Vec::new());
+        known.enable(&key);
+        known.write_all(b"Minecraft").unwrap();
+        assert_eq!(known.get_ref(), &[0x47, 0xa8, 0x0c, 0x00, 0xb2, 0x5e, 0xa2, 0xe7, 0x3e]);
+
+        // Reads in odd-sized chunks decrypt the same way as a single read.
+        let mut reader = DecryptingReader::new(&sent[..]);
+        let mut received = vec![0; 100];
+        reader.read_exact(&mut received).unwrap();
+        reader.enable(&secret);
+        let mut chunk = [0_u8; 7];
+        loop {
+            let n = reader.read(&mut chunk).unwrap();
+            if n == 0 {
+                break;
+            }
+            received.extend_from_slice(&chunk[..n]);
+        }
+        assert_eq!(received, plain);
+    }
+
+    #[test]
+    fn encryption_response_decrypts_with_private_key() {
+        use rsa::{pkcs8::EncodePublicKey, Pkcs1v15Encrypt, RsaPrivateKey};
+
+        let private_key = RsaPrivateKey::new(&mut rand::thread_rng(), 1024).unwrap();
+        let public_key = private_key.to_public_key().to_public_key_der().unwrap();
+        let secret = encryption::generate_shared_secret();
+
+        let request = EncryptionRequest {
+            server_id: String::new(),
+            public_key: public_key.as_bytes().to_vec(),
+            verify_token: vec![1, 2, 3, 4],
+            should_authenticate: None,
+        };
+        let response = EncryptionResponse::encrypt(&request.public_key, &secret, &request.verify_token).unwrap();
+        assert_eq!(private_key.decrypt(Pkcs1v15Encrypt, &response.shared_secret).unwrap(), secret);
+        assert_eq!(private_key.decrypt(Pkcs1v15Encrypt, &response.verify_token).unwrap(), [1, 2, 3, 4]);
+
+        let err = EncryptionResponse::encrypt(b"not a key", &secret, &[]).unwrap_err();
+        assert!(matches!(err, McError::Encryption(_)));
+    }
+
+    #[test]
+    fn stream_encrypts_after_enable() {
+        use std::{io::{Read, Write}, net::TcpListener, thread};
+
+        let secret = encryption::generate_shared_secret();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (socket, _) = listener.accept().unwrap();
+            let mut reader = DecryptingReader::new(socket.try_clone().unwrap());
+            let mut writer = EncryptingWriter::new(socket);
+            let mut frames = FrameDecoder::new();
+
+            // The Encryption Response itself is sent in the clear. It is read exactly,
+            // so that no encrypted bytes are buffered before decryption is enabled.
+            let mut frame = [0_u8; 12];
+            reader.read_exact(&mut frame).unwrap();
+            assert_eq!(frame[..2], [11, packet_ids::serverbound::ENCRYPTION_RESPONSE as u8]);
+            reader.enable(&secret);
+            writer.enable(&secret);
+
+            assert_eq!(frames.read_frame(&mut reader).unwrap(), [0x00]);
+            let mut bytes = Vec::new();
+            write_packet(&SetCompression { threshold: 64 }, &mut bytes).unwrap();
+            writer.write_all(&bytes).unwrap();
+
+            let mut rest = Vec::new();
+            reader.read_to_end(&mut rest).unwrap();
+        });
+
+        let mut stream = MinecraftStream::connect(("127.0.0.1", port)).unwrap();
+        let response = EncryptionResponse {
+            shared_secret: vec![0; 4],
+            verify_token: vec![0; 4],
+        };
+        stream.send(&response).unwrap();
+        assert!(!stream.is_encrypted());
+        stream.enable_encryption(&secret).unwrap();
+        assert!(stream.is_encrypted());
+        stream.send(&StatusRequest).unwrap();
+
+        let packet = stream.read().unwrap();
+        assert_eq!(SetCompression::from_data(&packet).unwrap().threshold, 64);
+        drop(stream);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn login_success_properties_round_trip() {
+        let login_success = LoginSuccess {
+            uuid: uuid::Uuid::from_u128(0x069a_79f4_44e9_4726_a5be_fca9_0e38_aaf5),
+            username: "Notch".to_string(),
+            properties: vec![
+                Property {
+                    name: "textures".to_string(),
+                    value: "e30=".to_string(),
+                    signature: Some("c2lnbmF0dXJl".to_string()),
+                },
+                Property {
+                    name: "unsigned".to_string(),
+                    value: String::new(),
+                    signature: None,
+                },
+            ],
+        };
+        let bytes = login_success.to_bytes().unwrap();
+        assert_eq!(login_success.properties[0].size() + login_success.properties[1].size(), bytes.len() as i32 - 23);
+
+        let decoded = LoginSuccess::from_data(&ClientboundRawPacket::from_frame([&[0x02][..], &bytes].concat()).unwrap())
+            .unwrap();
+        assert_eq!(decoded.uuid, login_success.uuid);
+        assert_eq!(decoded.username, "Notch");
+        assert_eq!(decoded.properties, login_success.properties);
+    }
+
+    #[test]
+    fn login_plugin_response_omits_data_when_not_understood() {
+        assert_eq!(LoginPluginResponse::not_understood(300).to_bytes().unwrap(), [0xAC, 0x02, 0x00]);
+        assert_eq!(LoginPluginResponse::understood(1, vec![7, 8]).to_bytes().unwrap(), [0x01, 0x01, 7, 8]);
+    }
+
+    /// Accepts one login and answers it with `packets`, returning every frame the client
+    /// sent after Login Start.
+    fn fake_login_server(packets: Vec<Box<dyn OutboundPacket + Send>>) -> (u16, std::thread::JoinHandle<Vec<Vec<u8>>>) {
+        use std::{io::Write, net::TcpListener, thread};
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let mut frames = FrameDecoder::new();
+            frames.read_frame(&mut socket).unwrap();
+            frames.read_frame(&mut socket).unwrap();
+
+            let mut bytes = Vec::new();
+            for packet in &packets {
+                write_packet(packet.as_ref(), &mut bytes).unwrap();
+            }
+            socket.write_all(&bytes).unwrap();
+
+            let mut received = Vec::new();
+            while let Ok(frame) = frames.read_frame(&mut socket) {
+                received.push(frame);
+            }
+            received
+        });
+        (port, server)
+    }
+
+    #[test]
+    fn login_answers_plugin_requests() {
+        let request = |message_id, channel| LoginPluginRequest {
+            message_id,
+            channel: Identifier::parse(channel).unwrap(),
+            data: vec![1, 2, 3],
+        };
+        let (port, server) = fake_login_server(vec![
+            Box::new(request(5, "velocity:player_info")),
+            Box::new(request(6, "example:echo")),
+            Box::new(LoginSuccess {
+                uuid: offline_uuid("Makoto"),
+                username: "makoto".to_string(),
+                properties: Vec::new(),
+            }),
+        ]);
+
+        let mut connection = OfflineConnection::connect("127.0.0.1", port).unwrap();
+        connection.set_login_plugin_handler(|request: &LoginPluginRequest| {
+            (request.channel.namespace() == "example").then(|| request.data.clone())
+        });
+        let login_success = connection.login("Makoto").unwrap();
+        assert_eq!(login_success.username, "makoto");
+        assert_eq!(connection.username().as_deref(), Some("makoto"));
+        drop(connection);
+
+        // Protocol 761 has no Login Acknowledged, so only the two responses are sent.
+        assert_eq!(server.join().unwrap(), [vec![0x02, 0x05, 0x00], vec![0x02, 0x06, 0x01, 1, 2, 3]]);
+    }
+
+    #[test]
+    fn login_rejects_encryption_request_offline() {
+        let request = || EncryptionRequest {
+            server_id: String::new(),
+            public_key: vec![0x30],
+            verify_token: vec![1, 2, 3, 4],
+            should_authenticate: None,
+        };
+        let (port, server) = fake_login_server(vec![Box::new(request())]);
+
+        let mut connection = OfflineConnection::connect("127.0.0.1", port).unwrap();
+        assert!(matches!(connection.login("Makoto").unwrap_err(), McError::Auth(_)));
+        drop(connection);
+        assert!(server.join().unwrap().is_empty());
+
+        // An authenticator refusing the join fails the login before anything is sent.
+        let (port, server) = fake_login_server(vec![Box::new(request())]);
+        let mut connection = OfflineConnection::connect("127.0.0.1", port).unwrap();
+        connection.set_session_authenticator(|_: &str| Err(McError::Auth("Refused".to_owned())));
+        assert!(matches!(connection.login("Makoto").unwrap_err(), McError::Auth(message) if message == "Refused"));
+        drop(connection);
+        assert!(server.join().unwrap().is_empty());
+    }
+
+    /// The shared secret the client picked, and the frames it sent after encryption started.
+    type EncryptedLogin = ([u8; 16], Vec<Vec<u8>>);
+
+    /// Accepts a login and answers it with `request`. Once the Encryption Response is
+    /// checked against `private_key`, the encrypted `packets` are sent.
+    fn fake_encrypted_login_server(
+        private_key: rsa::RsaPrivateKey,
+        request: EncryptionRequest,
+        packets: Vec<Box<dyn OutboundPacket + Send>>,
+    ) -> (u16, std::thread::JoinHandle<EncryptedLogin>) {
+        use std::{io::Write, net::TcpListener, thread};
+
+        use rsa::Pkcs1v15Encrypt;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (socket, _) = listener.accept().unwrap();
+            let mut reader = socket.try_clone().unwrap();
+            let mut writer = EncryptingWriter::new(socket);
+            let mut frames = FrameDecoder::new();
+            frames.read_frame(&mut reader).unwrap();
+            frames.read_frame(&mut reader).unwrap();
+            let mut bytes = Vec::new();
+            write_packet(&request, &mut bytes).unwrap();
+            writer.write_all(&bytes).unwrap();
+
+            // The Encryption Response is the last packet the client sends in the clear.
+            let raw = ClientboundRawPacket::from_frame(frames.read_frame(&mut reader).unwrap()).unwrap();
+            assert_eq!(raw.header.id, packet_ids::serverbound::ENCRYPTION_RESPONSE);
+            let response = EncryptionResponse::from_data(&raw).unwrap();
+            assert_eq!(private_key.decrypt(Pkcs1v15Encrypt, &response.verify_token).unwrap(), request.verify_token);
+            let secret: [u8; 16] = private_key
+                .decrypt(Pkcs1v15Encrypt, &response.shared_secret)
+                .unwrap()
+                .try_into()
+                .unwrap();
+
+            writer.enable(&secret);
+            let mut bytes = Vec::new();
+            for packet in &packets {
+                write_packet(packet.as_ref(), &mut bytes).unwrap();
+            }
+            writer.write_all(&bytes).unwrap();
+
+            let mut reader = DecryptingReader::new(reader);
+            reader.enable(&secret);
+            let mut received = Vec::new();
+            while let Ok(frame) = frames.read_frame(&mut reader) {
+                received.push(frame);
+            }
+            (secret, received)
+        });
+        (port, server)
+    }
+
+    #[test]
+    fn login_encrypts_after_joining_session() {
+        use std::sync::{Arc, Mutex};
+
+        use rsa::{pkcs8::EncodePublicKey, RsaPrivateKey};
+
+        let private_key = RsaPrivateKey::new(&mut rand::thread_rng(), 1024).unwrap();
+        let public_key = private_key.to_public_key().to_public_key_der().unwrap().as_bytes().to_vec();
+        let request = EncryptionRequest {
+            server_id: String::new(),
+            public_key: public_key.clone(),
+            verify_token: vec![1, 2, 3, 4],
+            should_authenticate: None,
+        };
+        let (port, server) = fake_encrypted_login_server(
+            private_key,
+            request,
+            vec![Box::new(LoginSuccess {
+                uuid: offline_uuid("Makoto"),
+                username: "Makoto".to_string(),
+                properties: Vec::new(),
+            })],
+        );
+
+        let joins = Arc::new(Mutex::new(Vec::new()));
+        let mut connection = OfflineConnection::connect("127.0.0.1", port).unwrap();
+        let hashes = Arc::clone(&joins);
+        connection.set_session_authenticator(move |server_hash: &str| -> Result<(), McError> {
+            hashes.lock().unwrap().push(server_hash.to_owned());
+            Ok(())
+        });
+        assert_eq!(connection.login("Makoto").unwrap().username, "Makoto");
+        assert!(connection.sock().is_encrypted());
+        drop(connection);
+
+        let (secret, _) = server.join().unwrap();
+        assert_eq!(*joins.lock().unwrap(), [encryption::server_hash("", &secret, &public_key)]);
+    }
+
+    #[test]
+    fn login_encrypts_without_session_when_not_required() {
+        use rsa::{pkcs8::EncodePublicKey, RsaPrivateKey};
+
+        let private_key = RsaPrivateKey::new(&mut rand::thread_rng(), 1024).unwrap();
+        let request = EncryptionRequest {
+            server_id: String::new(),
+            public_key: private_key.to_public_key().to_public_key_der().unwrap().as_bytes().to_vec(),
+            verify_token: vec![1, 2, 3, 4],
+            should_authenticate: Some(false),
+        };
+
+        // The flag is only part of the packet from 1.20.5.
+        let bytes = request.to_bytes().unwrap();
+        assert_eq!(bytes.last(), Some(&0x00));
+        let raw = ClientboundRawPacket::from_frame([&[0x01][..], &bytes].concat()).unwrap();
+        assert!(!EncryptionRequest::from_data_with(&raw, 766).unwrap().should_authenticate());
+        assert!(EncryptionRequest::from_data_with(&raw, 765).is_err());
+        let raw = ClientboundRawPacket::from_frame([&[0x01][..], &bytes[..bytes.len() - 1]].concat()).unwrap();
+        assert!(EncryptionRequest::from_data_with(&raw, 765).unwrap().should_authenticate());
+
+        let login_success = LoginSuccess {
+            uuid: offline_uuid("Makoto"),
+            username: "Makoto".to_string(),
+            properties: Vec::new(),
+        };
+        let (port, server) = fake_encrypted_login_server(
+            private_key,
+            request,
+            vec![raw_packet(packet_ids::clientbound::LOGIN_SUCCESS, [login_success.to_bytes().unwrap(), vec![1]].concat())],
+        );
+
+        // No authenticator is set, and none is needed.
+        let login = Client::connect("127.0.0.1", port).unwrap().with_protocol_version(766).unwrap().into_login().unwrap();
+        let (configuration, login_success) = login.login_into_configuration("Makoto").unwrap();
+        assert_eq!(login_success.username, "Makoto");
+        drop(configuration);
+
+        // Login Acknowledged arrives encrypted.
+        let (_, received) = server.join().unwrap();
+        assert_eq!(received, [vec![packet_ids::serverbound::LOGIN_ACKNOWLEDGED as u8]]);
+    }
+
+    /// Accepts one status handshake and answers each request, echoing pings with the
+    /// payload changed by `tamper`.
+    fn fake_status_server(tamper: i64) -> (u16, std::thread::JoinHandle<()>) {
+        use std::{io::Write, net::TcpListener, thread};
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let mut frames = FrameDecoder::new();
+            let handshake = frames.read_frame(&mut socket).unwrap();
+            assert_eq!(*handshake.last().unwrap(), NextState::STATUS as u8);
+
+            while let Ok(frame) = frames.read_frame(&mut socket) {
+                let mut bytes = Vec::new();
+                match frame[0] {
+                    0x00 => {
+                        let mut json = Vec::new();
+                        r#"{"version":{"name":"1.19.3","protocol":761}}"#.to_string().write_to(&mut json);
+                        bytes.extend(VarInt::from(json.len() as i32 + 1).bytes());
+                        bytes.push(0x00);
+                        bytes.extend(json);
+                    }
+                    0x01 => {
+                        let payload = i64::from_bytes(&frame[1..]).unwrap();
+                        write_packet(&PingResponse { payload: payload.wrapping_add(tamper) }, &mut bytes).unwrap();
+                    }
+                    id => panic!("unexpected packet {}", id),
+                }
+                socket.write_all(&bytes).unwrap();
+            }
+        });
+        (port, server)
+    }
+
+    #[test]
+    fn ping_measures_round_trip() {
+        let (port, server) = fake_status_server(0);
+        let mut connection = OfflineConnection::connect("127.0.0.1", port).unwrap();
+        let latency = connection.ping().unwrap();
+        assert!(latency < std::time::Duration::from_secs(5));
+        drop(connection);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn ping_rejects_wrong_payload() {
+        let (port, server) = fake_status_server(1);
+        let mut connection = OfflineConnection::connect("127.0.0.1", port).unwrap();
+        let err = connection.ping().unwrap_err();
+        assert!(matches!(
+            err,
+            McError::Malformed {
+                error: DataError::Invalid(_),
+                field: Some("payload"),
+                ..
+            }
+        ));
+        drop(connection);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn server_list_ping_uses_one_handshake() {
+        let (port, server) = fake_status_server(0);
+        let mut connection = OfflineConnection::connect("127.0.0.1", port).unwrap();
+        let server_list_ping = connection.server_list_ping().unwrap();
+        assert_eq!(server_list_ping.status.version.protocol, 761);
+        assert!(server_list_ping.latency < std::time::Duration::from_secs(5));
+        drop(connection);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn server_status_parses_vanilla_and_forge_fields() {
+        let json = r#"{
+            "version": { "name": "1.19.3", "protocol": 761 },
+            "players": {
+                "max": 100,
+                "online": 2,
+                "sample": [{ "name": "thinkofdeath", "id": "4566e69f-c907-48ee-8d71-d7ba5aa00d20" }]
+            },
+            "description": { "text": "Hello, ", "extra": [{ "text": "world", "bold": true }] },
+            "favicon": "data:image/png;base64,iVBO\nRw==",
+            "enforcesSecureChat": true,
+            "previewsChat": false,
+            "forgeData": {
+                "channels": [{ "res": "forge:tier_sorting", "version": "1.0", "required": false }],
+                "mods": [{ "modId": "forge", "modmarker": "45.1.0" }],
+                "fmlNetworkVersion": 3,
+                "d": "packed"
+            },
+            "preventsChatReports": true
+        }"#;
+
+        let status = ServerStatus::from_json(json).unwrap();
+        assert_eq!(status.version.name, "1.19.3");
+        let players = status.players.as_ref().unwrap();
+        assert_eq!((players.max, players.online), (100, 2));
+        assert_eq!(players.sample[0].name, "thinkofdeath");
+        assert_eq!(status.description.as_ref().unwrap().to_plain_string(), "Hello, world");
+        assert_eq!(status.favicon.as_ref().unwrap().png, [0x89, b'P', b'N', b'G']);
+        assert_eq!(status.enforces_secure_chat, Some(true));
+        assert_eq!(status.previews_chat, Some(false));
+        assert!(status.modinfo.is_none());
+        let forge_data = status.forge_data.as_ref().unwrap();
+        assert_eq!(forge_data.mods[0].mod_id, "forge");
+        assert_eq!(forge_data.fml_network_version, Some(3));
+        assert_eq!(forge_data.extra["d"], "packed");
+        assert_eq!(status.extra["preventsChatReports"], true);
+
+        // Serializing keeps every field, including the unknown ones.
+        let reparsed = ServerStatus::from_json(&status.to_json_string().unwrap()).unwrap();
+        assert_eq!(reparsed, status);
+        assert!(status.to_json_string().unwrap().contains(r#""favicon":"data:image/png;base64,iVBORw==""#));
+    }
+
+    #[test]
+    fn server_status_parses_legacy_modinfo_and_skips_bad_favicon() {
+        let status = ServerStatus::from_json(
+            r#"{"version":{"name":"1.12.2","protocol":340},"description":"A server",
+                "modinfo":{"type":"FML","modList":[{"modid":"minecraft","version":"1.12.2"}]}}"#,
+        )
+        .unwrap();
+        assert!(status.players.is_none());
+        let modinfo = status.modinfo.unwrap();
+        assert_eq!(modinfo.kind, "FML");
+        assert_eq!(modinfo.mod_list[0].modid, "minecraft");
+
+        assert!(Favicon::from_data_uri("data:image/jpeg;base64,AAAA").is_err());
+        assert!(Favicon::from_data_uri("data:image/png;base64,!!!").is_err());
+        // The rest of a status with a bad favicon survives.
+        for favicon in [r#""nope""#, r#""data:image/png;base64,!!!""#, "42"] {
+            let status = ServerStatus::from_json(&format!(
+                r#"{{"version":{{"name":"x","protocol":1}},"players":{{"max":20,"online":3}},
+                    "description":"A server","favicon":{}}}"#,
+                favicon
+            ))
+            .unwrap();
+            assert_eq!(status.favicon, None);
+            assert_eq!(status.players.unwrap().online, 3);
+            assert_eq!(status.description.unwrap().to_plain_string(), "A server");
+        }
+    }
+
+    #[test]
+    fn legacy_ping_requests() {
+        assert_eq!(legacy::encode_request(LegacyPingFormat::Beta18To13, "localhost", 25565).unwrap(), [0xFE]);
+        assert_eq!(legacy::encode_request(LegacyPingFormat::V14To15, "localhost", 25565).unwrap(), [0xFE, 0x01]);
+
+        let utf16 = |s: &str| s.encode_utf16().flat_map(u16::to_be_bytes).collect::<Vec<u8>>();
+        let mut expected = vec![0xFE, 0x01, 0xFA, 0x00, 0x0B];
+        expected.extend(utf16("MC|PingHost"));
+        expected.extend([0x00, 0x19, 78, 0x00, 0x09]);
+        expected.extend(utf16("localhost"));
+        expected.extend([0x00, 0x00, 0x63, 0xDD]);
+        assert_eq!(legacy::encode_request(LegacyPingFormat::V16, "localhost", 25565).unwrap(), expected);
+
+        // A host too long for the length fields is an error rather than a panic.
+        let error = legacy::encode_request(LegacyPingFormat::V16, &"a".repeat(40_000), 25565).unwrap_err();
+        assert!(matches!(error, McError::Malformed { error: DataError::String(StringError::TooLong { .. }), .. }));
+        assert!(legacy::encode_request(LegacyPingFormat::V16, &"a".repeat(255), 25565).is_ok());
+    }
+
+    #[test]
+    fn legacy_ping_responses() {
+        let status = legacy::parse_response("\u{a7}1\u{0}127\u{0}1.6.4\u{0}\u{a7}aA Minecraft Server\u{0}3\u{0}20").unwrap();
+        assert_eq!((status.version.protocol, status.version.name.as_str()), (127, "1.6.4"));
+        assert_eq!(status.description.as_ref().unwrap().to_plain_string(), "A Minecraft Server");
+        let players = status.players.unwrap();
+        assert_eq!((players.online, players.max), (3, 20));
+
+        // The MOTD of the oldest format may itself hold `§`.
+        let status = legacy::parse_response("A \u{a7}lbold\u{a7}r server\u{a7}0\u{a7}10").unwrap();
+        assert_eq!(status.version.protocol, legacy::UNKNOWN_LEGACY_PROTOCOL);
+        assert_eq!(status.description.unwrap().to_plain_string(), "A bold server");
+        assert_eq!(status.players.unwrap().max, 10);
+
+        assert!(legacy::parse_response("\u{a7}1\u{0}127\u{0}1.6.4").is_err());
+        assert!(legacy::parse_response("No counts").is_err());
+        assert!(legacy::parse_response("MOTD\u{a7}some\u{a7}20").is_err());
+        assert!(legacy::decode_utf16(&[0xD8, 0x00]).is_err());
+        assert!(legacy::decode_utf16(&[0x00]).is_err());
+    }
+
+    #[test]
+    fn legacy_status_over_tcp() {
+        use std::{io::{Read, Write}, net::TcpListener, thread};
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let mut request = [0_u8; 3];
+            socket.read_exact(&mut request).unwrap();
+            assert_eq!(request, [0xFE, 0x01, 0xFA]);
+
+            let response: Vec<u16> = "\u{a7}1\u{0}47\u{0}1.4.2\u{0}Old server\u{0}0\u{0}8".encode_utf16().collect();
+            let mut bytes = vec![0xFF];
+            bytes.extend((response.len() as u16).to_be_bytes());
+            bytes.extend(response.iter().flat_map(|unit| unit.to_be_bytes()));
+            socket.write_all(&bytes).unwrap();
+        });
+
+        let mut connection = OfflineConnection::connect("127.0.0.1", port).unwrap();
+        let status = connection.legacy_status(LegacyPingFormat::V16).unwrap();
+        assert_eq!(status.version.name, "1.4.2");
+        assert_eq!(status.players.unwrap().max, 8);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn derived_packets_are_marked_with_their_state() {
+        fn serverbound<S: ProtocolState, P: ServerboundPacket<S>>() {}
+        fn clientbound<S: ProtocolState, P: ClientboundPacket<S>>() {}
+
+        serverbound::<state::Handshaking, Handshake>();
+        serverbound::<state::Status, StatusRequest>();
+        serverbound::<state::Login, LoginStart>();
+        clientbound::<state::Status, StatusResponse>();
+        clientbound::<state::Login, LoginSuccess>();
+        assert_eq!(<state::Configuration as ProtocolState>::STATE, ConnectionState::Configuration);
+    }
+
+    #[test]
+    fn client_status_then_ping() {
+        let (port, server) = fake_status_server(0);
+        let mut status = Client::connect("127.0.0.1", port).unwrap().into_status().unwrap();
+        assert_eq!(status.status().unwrap().version.protocol, 761);
+        assert!(status.ping().unwrap() < std::time::Duration::from_secs(5));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn client_login_moves_to_play() {
+        let (port, server) = fake_login_server(vec![
+            Box::new(LoginSuccess {
+                uuid: offline_uuid("Makoto"),
+                username: "Makoto".to_string(),
+                properties: Vec::new(),
+            }),
+            Box::new(LoginDisconnect {
+                reason: TextComponent::text("Not a Play packet"),
+            }),
+        ]);
+
+        let login = Client::connect("127.0.0.1", port).unwrap().into_login().unwrap();
+        assert_eq!((login.domain(), login.port()), ("127.0.0.1", port));
+        let (mut play, login_success) = login.login("Makoto").unwrap();
+        assert_eq!(login_success.username, "Makoto");
+
+        // The packet after Login Success is read in the Play state.
+        let err = play.read_packet::<PlayTestPacket>().unwrap_err();
+        assert!(matches!(err, McError::UnexpectedPacket { state: ConnectionState::Play, id: 0x00 }));
+        drop(play);
+        server.join().unwrap();
+    }
+
+    #[derive(Debug, Packet)]
+    #[packet(id = 0x7F, state = Play, direction = Clientbound)]
+    struct PlayTestPacket;
+
+    fn raw_packet(id: i32, data: Vec<u8>) -> Box<dyn OutboundPacket + Send> {
+        Box::new(ServerboundRawPacket { id, data })
+    }
+
+    #[test]
+    fn configuration_ids_follow_protocol_tables() {
+        assert_eq!(ClientboundConfigurationType::from_id(764, 0x05), Some(ClientboundConfigurationType::RegistryData));
+        assert_eq!(ClientboundConfigurationType::from_id(765, 0x05), Some(ClientboundConfigurationType::RegistryData));
+        assert_eq!(ClientboundConfigurationType::from_id(767, 0x07), Some(ClientboundConfigurationType::RegistryData));
+        assert_eq!(ClientboundConfigurationType::from_id(767, 0x10), Some(ClientboundConfigurationType::ServerLinks));
+        assert_eq!(ClientboundConfigurationType::from_id(766, 0x10), None);
+        assert_eq!(ClientboundConfigurationType::from_id(761, 0x00), None);
+        assert_eq!(ClientboundConfigurationType::KnownPacks.id(765), None);
+        assert_eq!(ServerboundConfigurationType::AcknowledgeFinishConfiguration.id(764), Some(0x02));
+        assert_eq!(ServerboundConfigurationType::KnownPacks.id(767), Some(0x07));
+        assert_eq!(configuration::start_configuration_id(765), Some(0x67));
+        assert_eq!(configuration::acknowledge_configuration_id(766), Some(0x0C));
+
+        let known_packs = ServerboundConfigurationPacket::KnownPacks(Vec::new());
+        assert!(matches!(known_packs.write_with(764), Err(McError::Malformed { .. })));
+        assert_eq!(known_packs.write_with(766).unwrap(), ServerboundRawPacket { id: 0x07, data: vec![0] });
+    }
+
+    #[test]
+    fn configuration_decodes_registry_codec() {
+        let entry = |name: &str, id: i32| {
+            let mut entry = Compound::new();
+            entry.insert("name", name);
+            entry.insert("id", id);
+            entry.insert("element", Compound::new());
+            Tag::Compound(entry)
+        };
+        let mut registry = Compound::new();
+        registry.insert("type", "minecraft:dimension_type");
+        registry.insert("value", Tag::List(vec![entry("the_end", 1), entry("minecraft:overworld", 0)]));
+        let mut codec = Compound::new();
+        codec.insert("minecraft:dimension_type", registry);
+        let data = PacketBytesBuilder::new()
+            .try_append_network_nbt(Some(&NetworkNbt(Tag::Compound(codec))))
+            .unwrap()
+            .build();
+
+        let packet = ClientboundRawPacket::from_frame([vec![0x05], data].concat()).unwrap();
+        let ConfigurationPacket::RegistryData(registries) = ConfigurationPacket::read_with(&packet, 764).unwrap() else {
+            panic!("expected registry data");
+        };
+        let [dimension_types] = &registries[..] else {
+            panic!("expected a single registry");
+        };
+        assert_eq!(dimension_types.id, Identifier::minecraft("dimension_type").unwrap());
+        assert_eq!(dimension_types.id_of(&Identifier::minecraft("overworld").unwrap()), Some(0));
+        assert_eq!(dimension_types.get(1).unwrap().id, Identifier::minecraft("the_end").unwrap());
+        assert_eq!(dimension_types.get(1).unwrap().data, Some(Tag::Compound(Compound::new())));
+
+        // Unknown packets are reported in the Configuration state.
+        let packet = ClientboundRawPacket::from_frame(vec![0x09]).unwrap();
+        let err = ConfigurationPacket::read_with(&packet, 764).unwrap_err();
+        assert!(matches!(err, McError::UnexpectedPacket { state: ConnectionState::Configuration, id: 0x09 }));
+    }
+
+    #[test]
+    fn client_configuration_answers_server_and_collects_registries() {
+        let login_success = LoginSuccess {
+            uuid: offline_uuid("Makoto"),
+            username: "Makoto".to_string(),
+            properties: Vec::new(),
+        };
+        // 1.21 appends the strict error handling flag to Login Success.
+        let registry = PacketBytesBuilder::new()
+            .append(&Identifier::minecraft("damage_type").unwrap())
+            .append_varint(&2.into())
+            .append(&Identifier::minecraft("arrow").unwrap())
+            .append_bool(false)
+            .append(&Identifier::minecraft("cactus").unwrap())
+            .append_bool(true)
+            .try_append_network_nbt(Some(&NetworkNbt(Tag::Compound(Compound::new()))))
+            .unwrap()
+            .build();
+        let reason = TextComponent::text("Reconfigured").write_with(PacketBytesBuilder::new(), 767).unwrap().build();
+        let (port, server) = fake_login_server(vec![
+            raw_packet(packet_ids::clientbound::LOGIN_SUCCESS, [login_success.to_bytes().unwrap(), vec![1]].concat()),
+            raw_packet(0x0E, vec![1, 9, b'm', b'i', b'n', b'e', b'c', b'r', b'a', b'f', b't', 4, b'c', b'o', b'r', b'e', 4, b'1', b'.', b'2', b'1']),
+            raw_packet(0x04, 42_i64.to_be_bytes().to_vec()),
+            raw_packet(0x05, 7_i32.to_be_bytes().to_vec()),
+            raw_packet(0x07, registry),
+            raw_packet(0x03, Vec::new()),
+            raw_packet(0x69, Vec::new()),
+            raw_packet(0x02, reason),
+        ]);
+
+        let login = Client::connect("127.0.0.1", port).unwrap().with_protocol_version(767).unwrap();
+        let (mut play, login_success) = login.into_login().unwrap().login("Makoto").unwrap();
+        assert_eq!(login_success.username, "Makoto");
+        let damage_types = play.registries().get(&Identifier::minecraft("damage_type").unwrap()).unwrap();
+        assert_eq!(damage_types.get(0).unwrap().data, None);
+        assert_eq!(damage_types.id_of(&Identifier::minecraft("cactus").unwrap()), Some(1));
+
+        let start_configuration = play.read().unwrap();
+        assert!(play.is_start_configuration(&start_configuration));
+        let mut configuration = play.reconfigure().unwrap();
+        let err = configuration.next_packet().unwrap_err();
+        assert!(matches!(&err, McError::Disconnected(reason) if reason.to_plain_string() == "Reconfigured"));
+        drop(configuration);
+
+        let received = server.join().unwrap();
+        let expected: Vec<Vec<u8>> = vec![
+            vec![packet_ids::serverbound::LOGIN_ACKNOWLEDGED as u8],
+            vec![0x07, 0],
+            [vec![0x04], 42_i64.to_be_bytes().to_vec()].concat(),
+            [vec![0x05], 7_i32.to_be_bytes().to_vec()].concat(),
+            vec![0x03],
+            vec![0x0C],
+        ];
+        assert_eq!(received, expected);
+    }
+
+    #[test]
+    fn client_checks_protocol_versions() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let client = Client::connect("127.0.0.1", port).unwrap();
+        assert_eq!(client.protocol_version(), PROTOCOL_VERSION);
+        let client = client.with_protocol_version(760).map(|_| ()).unwrap_err();
+        assert!(matches!(client, McError::Malformed { .. }));
+
+        let login = Client::connect("127.0.0.1", port).unwrap().into_login().unwrap();
+        let err = login.login_into_configuration("Makoto").map(|_| ()).unwrap_err();
+        assert!(matches!(err, McError::Malformed { .. }));
+
+        // From 1.20.2, Login Start always carries a UUID.
+        let login_start = LoginStart {
+            username: "Makoto".to_string(),
+            uuid: None,
+        };
+        assert_eq!(login_start.to_bytes_with(761).unwrap(), [&[6][..], b"Makoto", &[0]].concat());
+        assert_eq!(login_start.to_bytes_with(764).unwrap(), [&[6][..], b"Makoto", &[0; 16]].concat());
+    }
+
+    #[test]
+    fn play_session_answers_keep_alive_and_reports_disconnect() {
+        let (port, server) = fake_login_server(vec![
+            Box::new(LoginSuccess {
+                uuid: offline_uuid("Makoto"),
+                username: "Makoto".to_string(),
+                properties: Vec::new(),
+            }),
+            raw_packet(0x1F, 42_i64.to_be_bytes().to_vec()),
+            raw_packet(0x2E, 7_i32.to_be_bytes().to_vec()),
+            raw_packet(0x50, vec![1, 2, 3]),
+            raw_packet(0x17, PacketBytesBuilder::new().append_string(r#"{"text":"Server closed"}"#).build()),
+        ]);
+
+        let (play, _) = Client::connect("127.0.0.1", port).unwrap().into_login().unwrap().login("Makoto").unwrap();
+        let mut session = PlaySession::new(play).unwrap();
+        let SessionEvent::Packet(packet) = session.next_event().unwrap() else {
+            panic!("expected a packet");
+        };
+        assert_eq!((packet.header.id, packet.data), (0x50, vec![1, 2, 3]));
+        let err = session.next_event().unwrap_err();
+        assert!(matches!(&err, McError::Disconnected(reason) if reason.to_plain_string() == "Server closed"));
+        drop(session);
+
+        let received = server.join().unwrap();
+        let expected: Vec<Vec<u8>> = vec![
+            [vec![0x11], 42_i64.to_be_bytes().to_vec()].concat(),
+            [vec![0x1F], 7_i32.to_be_bytes().to_vec()].concat(),
+        ];
+        assert_eq!(received, expected);
+    }
+
+    #[test]
+    fn play_session_groups_bundles() {
+        use std::ops::ControlFlow;
+
+        assert_eq!(PlayPacketIds::for_protocol(761).unwrap().bundle_delimiter, None);
+        assert_eq!(PlayPacketIds::for_protocol(760), None);
+
+        let login_success = LoginSuccess {
+            uuid: offline_uuid("Makoto"),
+            username: "Makoto".to_string(),
+            properties: Vec::new(),
+        };
+        let (port, server) = fake_login_server(vec![
+            raw_packet(packet_ids::clientbound::LOGIN_SUCCESS, [login_success.to_bytes().unwrap(), vec![0]].concat()),
+            raw_packet(0x03, Vec::new()),
+            raw_packet(0x00, Vec::new()),
+            raw_packet(0x01, vec![1]),
+            raw_packet(0x26, 9_i64.to_be_bytes().to_vec()),
+            raw_packet(0x02, vec![2]),
+            raw_packet(0x00, Vec::new()),
+            raw_packet(0x40, Vec::new()),
+            raw_packet(0x69, Vec::new()),
+        ]);
+
+        let login = Client::connect("127.0.0.1", port).unwrap().with_protocol_version(767).unwrap();
+        let (play, _) = login.into_login().unwrap().login("Makoto").unwrap();
+        let mut session = PlaySession::new(play).unwrap();
+        let mut events = Vec::new();
+        session
+            .run(|_, event| {
+                events.push(match event {
+                    SessionEvent::Packet(packet) => vec![packet.header.id],
+                    SessionEvent::Bundle(bundle) => bundle.iter().map(|packet| packet.header.id).collect(),
+                    SessionEvent::StartConfiguration => Vec::new(),
+                });
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+        assert_eq!(events, vec![vec![0x01, 0x02], vec![0x40], vec![]]);
+        drop(session.into_client().reconfigure().unwrap());
+
+        let received = server.join().unwrap();
+        let expected: Vec<Vec<u8>> = vec![
+            vec![packet_ids::serverbound::LOGIN_ACKNOWLEDGED as u8],
+            vec![0x03],
+            [vec![0x18], 9_i64.to_be_bytes().to_vec()].concat(),
+            vec![0x0C],
+        ];
+        assert_eq!(received, expected);
+    }
+
+    #[test]
+    fn stream_applies_connection_options() {
+        use std::{io::ErrorKind, net::TcpListener, time::Duration};
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let options = ConnectionOptions::new()
+            .with_connect_timeout(Duration::from_secs(5))
+            .with_read_timeout(Duration::from_millis(50))
+            .with_write_timeout(Duration::from_secs(5))
+            .with_nodelay(true)
+            .with_keepalive(Duration::from_secs(60))
+            .with_bind_address("127.0.0.1:0".parse().unwrap());
+        let mut stream = MinecraftStream::connect_with(("127.0.0.1", port), &options).unwrap();
+        let (_server, _) = listener.accept().unwrap();
+
+        let socket = stream.get_ref();
+        assert!(socket.nodelay().unwrap());
+        // The system rounds timeouts to its clock resolution.
+        assert!(socket.read_timeout().unwrap().is_some());
+        assert!(socket.write_timeout().unwrap().is_some());
+        assert!(socket.local_addr().unwrap().ip().is_loopback());
+
+        // The server never answers, so the read times out instead of hanging.
+        match stream.read() {
+            Err(McError::Io(e)) => assert!(matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)),
+            other => panic!("expected a timeout, got {:?}", other.map(|packet| packet.header.id)),
+        }
+    }
+
+    #[test]
+    fn reconnect_policy_backs_off_exponentially() {
+        use std::{io, time::Duration};
+
+        let policy = ReconnectPolicy::exponential(4, Duration::from_millis(1), Duration::from_millis(3));
+        assert_eq!(
+            (1..=4).map(|retry| policy.backoff(retry)).collect::<Vec<_>>(),
+            [1, 2, 3, 3].map(Duration::from_millis)
+        );
+
+        let mut attempts = 0;
+        let result: Result<(), McError> = policy.retry(|| {
+            attempts += 1;
+            Err(io::Error::from(io::ErrorKind::ConnectionRefused).into())
+        });
+        assert!(matches!(result, Err(McError::Io(_))));
+        assert_eq!(attempts, 4);
+
+        // Errors other than I/O errors are not retried.
+        attempts = 0;
+        let result: Result<(), McError> = policy.retry(|| {
+            attempts += 1;
+            Err(McError::invalid("not a connection error"))
+        });
+        assert!(matches!(result, Err(McError::Malformed { .. })));
+        assert_eq!(attempts, 1);
+
+        let mut attempts = 0;
+        let result = policy.retry(|| {
+            attempts += 1;
+            if attempts < 3 {
+                return Err(io::Error::from(io::ErrorKind::ConnectionRefused).into());
+            }
+            Ok(attempts)
+        });
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(ReconnectPolicy::default(), ReconnectPolicy::none());
+    }
+
+    #[test]
+    fn reset_reports_unreachable_server() {
+        use std::{net::TcpListener, time::Duration};
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let options = ConnectionOptions::new().with_reconnect_policy(ReconnectPolicy::exponential(
+            3,
+            Duration::from_millis(1),
+            Duration::from_millis(5),
+        ));
+        let mut connection = OfflineConnection::connect_with("127.0.0.1", port, options).unwrap();
+        connection.reset().unwrap();
+
+        drop(listener);
+        assert!(matches!(connection.reset(), Err(McError::Io(_))));
+    }
+
+    /// A resolver answering from fixed records, which also logs the SRV lookups.
+    #[derive(Default)]
+    struct StubResolver {
+        srv: std::collections::HashMap<String, Vec<SrvRecord>>,
+        ips: std::collections::HashMap<String, Vec<std::net::IpAddr>>,
+        srv_lookups: std::cell::RefCell<Vec<String>>,
+    }
+
+    impl Resolver for StubResolver {
+        fn lookup_srv(&self, name: &str) -> Result<Vec<SrvRecord>, McError> {
+            self.srv_lookups.borrow_mut().push(name.to_string());
+            self.srv.get(name).cloned().ok_or_else(|| McError::invalid("NXDOMAIN"))
+        }
+
+        fn lookup_ip(&self, host: &str) -> Result<Vec<std::net::IpAddr>, McError> {
+            self.ips.get(host).cloned().ok_or_else(|| McError::invalid("NXDOMAIN"))
+        }
+    }
+
+    fn srv_record(priority: u16, weight: u16, port: u16, target: &str) -> SrvRecord {
+        SrvRecord {
+            priority,
+            weight,
+            port,
+            target: target.to_string(),
+        }
+    }
+
+    #[test]
+    fn server_address_parses_host_and_port() {
+        let parse = |address: &str| ServerAddress::parse(address).map(|address| (address.host, address.port));
+        assert_eq!(parse("play.example.net").unwrap(), ("play.example.net".to_string(), None));
+        assert_eq!(parse("play.example.net:25570").unwrap(), ("play.example.net".to_string(), Some(25570)));
+        assert_eq!(parse("::1").unwrap(), ("::1".to_string(), None));
+        assert_eq!(parse("[::1]").unwrap(), ("::1".to_string(), None));
+        assert_eq!(parse("[::1]:25570").unwrap(), ("::1".to_string(), Some(25570)));
+        assert!(parse("play.example.net:65536").is_err());
+        assert!(parse(":25565").is_err());
+        assert!(parse("[::1").is_err());
+
+        assert_eq!(ServerAddress::new("::1", Some(25570)).to_string(), "[::1]:25570");
+        assert_eq!(ServerAddress::new("play.example.net", None).to_string(), "play.example.net");
+    }
+
+    #[test]
+    fn server_address_resolves_srv_records() {
+        let ip = |ip: &str| vec![ip.parse().unwrap()];
+        let mut resolver = StubResolver::default();
+        resolver.srv.insert(
+            "_minecraft._tcp.play.example.net".to_string(),
+            vec![
+                srv_record(20, 0, 25580, "backup.example.net."),
+                srv_record(10, 1, 25571, "light.example.net."),
+                srv_record(10, 5, 25570, "heavy.example.net."),
+                srv_record(10, 9, 25590, "gone.example.net."),
+            ],
+        );
+        resolver.ips.insert("backup.example.net".to_string(), ip("10.0.0.3"));
+        resolver.ips.insert("light.example.net".to_string(), ip("10.0.0.2"));
+        resolver.ips.insert("heavy.example.net".to_string(), ip("10.0.0.1"));
+        resolver.ips.insert("play.example.net".to_string(), ip("10.0.0.9"));
+        resolver.ips.insert("lone.example.net".to_string(), ip("10.0.0.8"));
+
+        let resolve = |address: &str| {
+            let addrs = ServerAddress::parse(address).unwrap().resolve(&resolver).unwrap();
+            addrs.iter().map(|addr| addr.to_string()).collect::<Vec<_>>()
+        };
+        assert_eq!(resolve("play.example.net"), ["10.0.0.1:25570", "10.0.0.2:25571", "10.0.0.3:25580"]);
+        // Without SRV records, the host itself is used on the default port.
+        assert_eq!(resolve("lone.example.net"), ["10.0.0.8:25565"]);
+        // An explicit port or an IP address skips the SRV lookup.
+        resolver.srv_lookups.borrow_mut().clear();
+        assert_eq!(resolve("play.example.net:25566"), ["10.0.0.9:25566"]);
+        assert_eq!(resolve("127.0.0.1"), ["127.0.0.1:25565"]);
+        assert!(resolver.srv_lookups.borrow().is_empty());
+
+        let err = ServerAddress::parse("missing.example.net").unwrap().resolve(&resolver).unwrap_err();
+        assert!(matches!(err, McError::Malformed { .. }));
+    }
+
+    #[test]
+    fn connection_through_srv_keeps_original_host() {
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut resolver = StubResolver::default();
+        resolver.srv.insert(
+            "_minecraft._tcp.play.example.net".to_string(),
+            vec![srv_record(0, 0, port, "node.example.net.")],
+        );
+        resolver.ips.insert("node.example.net".to_string(), vec!["127.0.0.1".parse().unwrap()]);
+        let server = std::thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let frame = FrameDecoder::new().read_frame(&mut socket).unwrap();
+            Handshake::from_data(&ClientboundRawPacket::from_frame(frame).unwrap()).unwrap()
+        });
+
+        let address = ServerAddress::parse("play.example.net").unwrap();
+        let client = Client::connect_to(&address, &ConnectionOptions::new(), &resolver).unwrap();
+        assert_eq!((client.domain(), client.port()), ("play.example.net", DEFAULT_PORT));
+        let status = client.into_status().unwrap();
+        let handshake = server.join().unwrap();
+        // The SRV target's port is only used for the socket.
+        assert_ne!(port, DEFAULT_PORT);
+        assert_eq!((handshake.server_addr.as_str(), handshake.port), ("play.example.net", DEFAULT_PORT));
+        drop(status);
+
+        // Connections resolve the same way and reconnect to the resolved addresses.
+        let listener = TcpListener::bind(("127.0.0.1", port)).unwrap();
+        let mut connection = OfflineConnection::connect_to(&address, ConnectionOptions::new(), &resolver).unwrap();
+        assert_eq!((connection.domain(), connection.port()), ("play.example.net", DEFAULT_PORT));
+        connection.reset().unwrap();
+        assert_eq!(connection.port(), DEFAULT_PORT);
+        drop(listener);
+    }
+
+    #[test]
+    fn offline_uuid_is_name_based() {
+        assert_eq!(offline_uuid("Notch").to_string(), "b50ad385-829d-3141-a216-7e7d7539ba7f");
+        assert_eq!(offline_uuid("notch").to_string(), "42653081-a90e-3475-b3d6-3550cdb43f8e");
+        assert_eq!(offline_uuid("Makoto").get_version(), Some(uuid::Version::Md5));
+        assert_eq!(offline_uuid("Makoto").get_variant(), uuid::Variant::RFC4122);
+    }
+
+    #[test]
+    fn login_sends_offline_uuid() {
+        use std::{io::Write, net::TcpListener, thread};
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let mut frames = FrameDecoder::new();
+            frames.read_frame(&mut socket).unwrap();
+            let login_start = frames.read_frame(&mut socket).unwrap();
+            let mut bytes = Vec::new();
+            write_packet(
+                &LoginSuccess {
+                    uuid: offline_uuid("Makoto"),
+                    username: "Makoto".to_string(),
+                    properties: Vec::new(),
+                },
+                &mut bytes,
+            ).unwrap();
+            socket.write_all(&bytes).unwrap();
+            LoginStart::from_data(&ClientboundRawPacket::from_frame(login_start).unwrap()).unwrap()
+        });
+
+        let mut connection = OfflineConnection::connect("127.0.0.1", port).unwrap();
+        let login_success = connection.login("Makoto").unwrap();
+        assert_eq!(login_success.uuid, offline_uuid("Makoto"));
+        assert_eq!(server.join().unwrap().uuid, Some(offline_uuid("Makoto")));
+
+        // A server may assign another UUID, e.g., an online-mode one, which is only
+        // refused if the offline-mode UUID is required.
+        let assigning_nil = || {
+            fake_login_server(vec![Box::new(LoginSuccess {
+                uuid: uuid::Uuid::nil(),
+                username: "Makoto".to_string(),
+                properties: Vec::new(),
+            })])
+        };
+        let (port, server) = assigning_nil();
+        let mut connection = OfflineConnection::connect("127.0.0.1", port).unwrap();
+        assert_eq!(connection.login("Makoto").unwrap().uuid, uuid::Uuid::nil());
+        drop(connection);
+        server.join().unwrap();
+
+        let (port, server) = assigning_nil();
+        let mut connection = OfflineConnection::connect("127.0.0.1", port).unwrap();
+        connection.set_require_offline_uuid(true);
+        assert!(matches!(connection.login("Makoto").unwrap_err(), McError::Auth(_)));
+        drop(connection);
+        server.join().unwrap();
     }
 }